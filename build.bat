@@ -8,10 +8,9 @@ copy ".\app\dist\my_electron_app.exe" ".\dist\"
 robocopy ".\app\build\static" ".\dist\static" /E
 
 
-@REM Build and copy service
-cd ./service
+@REM Build and copy service and command-line client
 cargo build --release
-cd ..
-copy ".\service\target\release\service.exe" ".\dist\service\"
+copy ".\target\release\service.exe" ".\dist\service\"
+copy ".\target\release\productivity.exe" ".\dist\"
 
 copy "start.bat" ".\dist\"
\ No newline at end of file
@@ -0,0 +1,125 @@
+use chrono::prelude::*;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::model::{Current, Group, TimelineEntry};
+use crate::By;
+
+#[derive(Deserialize)]
+struct StatisticsResponse {
+    groups: Vec<Group>,
+}
+
+#[derive(Deserialize)]
+struct SessionStatisticsResponse {
+    app_visited_entries: Vec<TimelineEntry>,
+}
+
+#[derive(Deserialize)]
+struct Event {
+    app_title: String,
+    title: String,
+    timestamp: DateTime<Utc>,
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct TrackingStatus {
+    paused: bool,
+}
+
+/// Client of running service api
+pub struct ApiClient {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl ApiClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn is_reachable(&self) -> bool {
+        self.client
+            .get(format!("{}/tracking/status", self.url))
+            .send()
+            .await
+            .is_ok()
+    }
+
+    pub async fn groups(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        by: By,
+    ) -> Result<Vec<Group>, String> {
+        let response: StatisticsResponse = self
+            .client
+            .get(format!("{}/statistics", self.url))
+            .query(&[
+                ("from", from.to_rfc3339()),
+                ("to", to.to_rfc3339()),
+                ("group_by", by.as_str().to_owned()),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("Could not get statistics: {}", err))?
+            .json()
+            .await
+            .map_err(|err| format!("Unexpected statistics response: {}", err))?;
+
+        Ok(response.groups)
+    }
+
+    pub async fn timeline(&self) -> Result<Vec<TimelineEntry>, String> {
+        let response = self
+            .client
+            .get(format!("{}/session/current/statistics", self.url))
+            .send()
+            .await
+            .map_err(|err| format!("Could not get current session: {}", err))?;
+
+        // session without events yet
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(vec![]);
+        }
+
+        let response: SessionStatisticsResponse = response
+            .error_for_status()
+            .map_err(|err| format!("Could not get current session: {}", err))?
+            .json()
+            .await
+            .map_err(|err| format!("Unexpected session response: {}", err))?;
+
+        Ok(response.app_visited_entries)
+    }
+
+    pub async fn current(&self) -> Result<Option<Current>, String> {
+        let events: Vec<Event> = self.get("/session/current/events").await?;
+
+        let status: TrackingStatus = self.get("/tracking/status").await?;
+
+        Ok(events.into_iter().last().map(|event| Current {
+            tracking: event.kind == "focus" && !status.paused,
+            app_title: event.app_title,
+            title: event.title,
+            since: event.timestamp,
+        }))
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<T, String> {
+        self.client
+            .get(format!("{}{}", self.url, path))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("Could not get {}: {}", path, err))?
+            .json()
+            .await
+            .map_err(|err| format!("Unexpected response of {}: {}", path, err))
+    }
+}
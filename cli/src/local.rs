@@ -0,0 +1,255 @@
+use chrono::prelude::*;
+use productivity_service::entity::*;
+use productivity_service::server::{self, statistics::GroupBy};
+use sea_orm::{Database, DatabaseConnection, EntityTrait, QueryOrder};
+
+use crate::model::{Current, Group, TimelineEntry};
+use crate::By;
+
+/// Database of stopped service, opened read-only. Time is computed by the same functions as
+/// statistics of the service
+pub struct LocalDb {
+    db: DatabaseConnection,
+}
+
+impl LocalDb {
+    pub async fn open(path: &str) -> Result<Self, String> {
+        let db = Database::connect(format!("sqlite://{}?mode=ro", path))
+            .await
+            .map_err(|err| format!("Could not open {}: {}", path, err))?;
+
+        Ok(Self { db })
+    }
+
+    /// Milliseconds per group within range, sorted by duration
+    pub async fn groups(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        by: By,
+    ) -> Result<Vec<Group>, String> {
+        let group_by = match by {
+            By::App => GroupBy::App,
+            By::Category => GroupBy::Category,
+            By::Project => GroupBy::Project,
+        };
+
+        let durations = server::statistics::range_durations(&self.db, from, to, group_by, None)
+            .await
+            .map_err(|err| format!("Could not compute statistics: {}", err))?;
+
+        let mut groups: Vec<Group> = durations
+            .into_iter()
+            .filter(|(_, duration)| *duration > 0)
+            .map(|(key, duration)| Group { key, duration })
+            .collect();
+
+        groups.sort_by(|a, b| b.duration.cmp(&a.duration).then(a.key.cmp(&b.key)));
+
+        Ok(groups)
+    }
+
+    /// Focused apps and manual entries of the latest session
+    pub async fn timeline(&self) -> Result<Vec<TimelineEntry>, String> {
+        let statistics = server::current_session_statistics(&self.db, None, Utc::now())
+            .await
+            .map_err(|err| format!("Could not read current session: {}", err))?;
+
+        let Some(statistics) = statistics else {
+            return Ok(vec![]);
+        };
+
+        Ok(statistics
+            .app_visited_entries
+            .into_iter()
+            .map(|entry| TimelineEntry {
+                start: entry.start,
+                finish: entry.finish,
+                duration: entry.duration,
+                app_title: entry.app_title,
+                manual: entry.manual,
+            })
+            .collect())
+    }
+
+    /// The last recorded app, service is not running so nothing is tracked
+    pub async fn current(&self) -> Result<Option<Current>, String> {
+        let event = event::Entity::find()
+            .order_by_desc(event::Column::Timestamp)
+            .order_by_desc(event::Column::Id)
+            .one(&self.db)
+            .await
+            .map_err(|err| format!("Could not read events: {}", err))?;
+
+        Ok(event.map(|event| Current {
+            app_title: event.app_title,
+            title: event.title,
+            since: event.timestamp,
+            tracking: false,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use productivity_service::database;
+    use sea_orm::{ActiveModelTrait, Set};
+
+    use super::*;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap() + chrono::Duration::minutes(minutes)
+    }
+
+    async fn local_db() -> (LocalDb, i32) {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let session = session::ActiveModel {
+            datetime: Set(at(0)),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        (LocalDb { db }, session.id)
+    }
+
+    async fn insert_event(
+        local: &LocalDb,
+        session_id: i32,
+        minutes: i64,
+        app_title: &str,
+        kind: event::EventKind,
+        project: Option<&str>,
+    ) {
+        event::ActiveModel {
+            path: Set(format!("C:\\{}.exe", app_title)),
+            title: Set(String::new()),
+            timestamp: Set(at(minutes)),
+            app_title: Set(app_title.to_owned()),
+            offset: Set(0),
+            session_id: Set(session_id),
+            kind: Set(kind),
+            in_schedule: Set(true),
+            project: Set(project.map(str::to_owned)),
+            ..Default::default()
+        }
+        .insert(&local.db)
+        .await
+        .unwrap();
+    }
+
+    async fn insert_manual(local: &LocalDb, start: i64, end: i64, description: &str) {
+        manual_entry::ActiveModel {
+            start: Set(at(start)),
+            end: Set(Some(at(end))),
+            description: Set(description.to_owned()),
+            ..Default::default()
+        }
+        .insert(&local.db)
+        .await
+        .unwrap();
+    }
+
+    async fn insert_activity(local: &LocalDb, start: i64, end: i64, app: &str, project: &str) {
+        activity::ActiveModel {
+            client_id: Set(format!("{}-{}", app, start)),
+            source: Set("editor".to_owned()),
+            timestamp: Set(at(start)),
+            end: Set(at(end)),
+            app: Set(Some(app.to_owned())),
+            project: Set(Some(project.to_owned())),
+            ..Default::default()
+        }
+        .insert(&local.db)
+        .await
+        .unwrap();
+    }
+
+    fn durations(groups: Vec<Group>) -> Vec<(String, i64)> {
+        groups
+            .into_iter()
+            .map(|group| (group.key, group.duration as i64 / 60_000))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn groups_are_computed_like_service_statistics() {
+        let (local, session_id) = local_db().await;
+
+        insert_event(&local, session_id, 0, "code", event::EventKind::Focus, None).await;
+        insert_event(
+            &local,
+            session_id,
+            30,
+            "browser",
+            event::EventKind::Deleted,
+            None,
+        )
+        .await;
+        insert_event(
+            &local,
+            session_id,
+            40,
+            "browser",
+            event::EventKind::Focus,
+            None,
+        )
+        .await;
+        insert_event(&local, session_id, 60, "", event::EventKind::Shutdown, None).await;
+        insert_manual(&local, 20, 25, "meeting").await;
+        insert_activity(&local, 0, 10, "code", "editor").await;
+
+        let apps = local.groups(at(0), at(60), By::App).await.unwrap();
+        let projects = local.groups(at(0), at(60), By::Project).await.unwrap();
+
+        // deleted time is not counted, manual entry overrides tracked time
+        assert_eq!(
+            durations(apps),
+            vec![
+                ("code".to_owned(), 25),
+                ("browser".to_owned(), 20),
+                ("meeting".to_owned(), 5),
+            ]
+        );
+        assert_eq!(
+            durations(projects),
+            vec![("".to_owned(), 40), ("editor".to_owned(), 10)]
+        );
+    }
+
+    #[tokio::test]
+    async fn timeline_of_latest_session_includes_manual_entries() {
+        let (local, session_id) = local_db().await;
+
+        insert_event(&local, session_id, 0, "code", event::EventKind::Focus, None).await;
+        insert_event(&local, session_id, 30, "", event::EventKind::Shutdown, None).await;
+        insert_manual(&local, 10, 20, "meeting").await;
+
+        let timeline: Vec<(String, i64, bool)> = local
+            .timeline()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| {
+                (
+                    entry.app_title,
+                    (entry.start - at(0)).num_minutes(),
+                    entry.manual,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            timeline,
+            vec![
+                ("code".to_owned(), 0, false),
+                ("meeting".to_owned(), 10, true),
+                ("code".to_owned(), 20, false),
+            ]
+        );
+        assert_eq!(local.current().await.unwrap().unwrap().since, at(30));
+    }
+}
@@ -0,0 +1,170 @@
+use chrono::prelude::*;
+use clap::{Parser, Subcommand, ValueEnum};
+
+mod api;
+mod local;
+mod model;
+mod output;
+
+use api::ApiClient;
+use local::LocalDb;
+use model::{Current, Group, TimelineEntry};
+use productivity_service::statistics::{day_start, parse_datetime, week_start};
+
+#[derive(Parser)]
+#[command(name = "productivity", about = "Tracked time from terminal")]
+struct Cli {
+    /// Api of running service
+    #[arg(long, global = true, default_value = "http://localhost:8000/api")]
+    url: String,
+    /// Database opened read-only when service is not running
+    #[arg(long, global = true, default_value = "data.db")]
+    db: String,
+    /// Print JSON instead of table
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Time per app today
+    Today,
+    /// Time per app since Monday
+    Week,
+    /// Groups with the most time within range, today by default
+    Top {
+        #[arg(long, value_enum, default_value_t = By::App)]
+        by: By,
+        /// RFC 3339 datetime or date
+        #[arg(long)]
+        from: Option<String>,
+        /// RFC 3339 datetime or date, now by default
+        #[arg(long)]
+        to: Option<String>,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Apps visited within current session
+    Timeline,
+    /// Currently focused app
+    Current,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum By {
+    App,
+    Category,
+    Project,
+}
+
+impl By {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            By::App => "app",
+            By::Category => "category",
+            By::Project => "project",
+        }
+    }
+}
+
+/// Where data is read from, database is used only when api is not reachable
+enum Source {
+    Api(ApiClient),
+    Local(LocalDb),
+}
+
+impl Source {
+    async fn connect(cli: &Cli) -> Result<Self, String> {
+        let api = ApiClient::new(&cli.url);
+
+        if api.is_reachable().await {
+            return Ok(Source::Api(api));
+        }
+
+        eprintln!("Service is not running, reading {}", cli.db);
+
+        Ok(Source::Local(LocalDb::open(&cli.db).await?))
+    }
+
+    async fn groups(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        by: By,
+    ) -> Result<Vec<Group>, String> {
+        match self {
+            Source::Api(api) => api.groups(from, to, by).await,
+            Source::Local(db) => db.groups(from, to, by).await,
+        }
+    }
+
+    async fn timeline(&self) -> Result<Vec<TimelineEntry>, String> {
+        match self {
+            Source::Api(api) => api.timeline().await,
+            Source::Local(db) => db.timeline().await,
+        }
+    }
+
+    async fn current(&self) -> Result<Option<Current>, String> {
+        match self {
+            Source::Api(api) => api.current().await,
+            Source::Local(db) => db.current().await,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    if let Err(err) = run(cli).await {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let source = Source::connect(&cli).await?;
+    let now = Utc::now();
+
+    match cli.command {
+        Command::Today => {
+            let groups = source.groups(day_start(now), now, By::App).await?;
+            output::print_groups(&groups, None, cli.json);
+        }
+        Command::Week => {
+            let groups = source.groups(week_start(now), now, By::App).await?;
+            output::print_groups(&groups, None, cli.json);
+        }
+        Command::Top {
+            by,
+            from,
+            to,
+            limit,
+        } => {
+            let to = match to {
+                Some(to) => parse_datetime(&to)?,
+                None => now,
+            };
+            let from = match from {
+                Some(from) => parse_datetime(&from)?,
+                None => day_start(to),
+            };
+
+            let groups = source.groups(from, to, by).await?;
+            output::print_groups(&groups, Some(limit), cli.json);
+        }
+        Command::Timeline => {
+            let entries = source.timeline().await?;
+            output::print_timeline(&entries, cli.json);
+        }
+        Command::Current => {
+            let current = source.current().await?;
+            output::print_current(current.as_ref(), cli.json);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Group {
+    pub key: String,
+    /// Milliseconds
+    pub duration: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub start: DateTime<Utc>,
+    /// Missing for app focused now
+    pub finish: Option<DateTime<Utc>>,
+    /// Milliseconds
+    pub duration: u32,
+    pub app_title: String,
+    #[serde(default)]
+    pub manual: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Current {
+    pub app_title: String,
+    pub title: String,
+    pub since: DateTime<Utc>,
+    /// Whether service is tracking focus changes now
+    pub tracking: bool,
+}
@@ -0,0 +1,160 @@
+use chrono::prelude::*;
+use serde::Serialize;
+
+use crate::model::{Current, Group, TimelineEntry};
+
+pub fn print_groups(groups: &[Group], limit: Option<usize>, json: bool) {
+    let total: u64 = groups.iter().map(|group| group.duration).sum();
+    let groups = &groups[..limit.unwrap_or(groups.len()).min(groups.len())];
+
+    if json {
+        return print_json(&groups);
+    }
+
+    if groups.is_empty() {
+        println!("Nothing tracked");
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = groups
+        .iter()
+        .map(|group| {
+            vec![
+                if group.key.is_empty() {
+                    "(none)".to_owned()
+                } else {
+                    group.key.clone()
+                },
+                format_duration(group.duration),
+                format!(
+                    "{:.1}%",
+                    group.duration as f64 * 100.0 / total.max(1) as f64
+                ),
+            ]
+        })
+        .collect();
+
+    print_table(&["NAME", "TIME", "SHARE"], &rows);
+    println!("\nTotal {}", format_duration(total));
+}
+
+pub fn print_timeline(entries: &[TimelineEntry], json: bool) {
+    if json {
+        return print_json(&entries);
+    }
+
+    if entries.is_empty() {
+        println!("Nothing tracked in current session");
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|entry| {
+            let app = if entry.manual {
+                format!("{} (manual)", entry.app_title)
+            } else {
+                entry.app_title.clone()
+            };
+
+            vec![
+                format_time(entry.start),
+                entry.finish.map_or("now".to_owned(), format_time),
+                entry
+                    .finish
+                    .map_or("".to_owned(), |_| format_duration(entry.duration as u64)),
+                app,
+            ]
+        })
+        .collect();
+
+    print_table(&["START", "END", "TIME", "APP"], &rows);
+}
+
+pub fn print_current(current: Option<&Current>, json: bool) {
+    if json {
+        return print_json(&current);
+    }
+
+    let Some(current) = current else {
+        println!("Nothing tracked yet");
+        return;
+    };
+
+    let elapsed = (Utc::now() - current.since).num_milliseconds().max(0) as u64;
+
+    if current.tracking {
+        println!(
+            "{} - {} for {}",
+            current.app_title,
+            current.title,
+            format_duration(elapsed)
+        );
+    } else {
+        println!(
+            "Not tracking, last app was {} - {} at {}",
+            current.app_title,
+            current.title,
+            format_time(current.since)
+        );
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+/// Columns are padded to the widest cell, the last one is not padded
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.chars().count()).collect();
+
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        let last = cells.len() - 1;
+
+        cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                if index == last {
+                    cell.to_string()
+                } else {
+                    format!("{:width$}", cell, width = widths[index])
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("  ")
+    };
+
+    println!("{}", format_row(header.to_vec()));
+
+    for row in rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
+
+/// Milliseconds as "1h 05m", "12m 30s" or "45s"
+fn format_duration(milliseconds: u64) -> String {
+    let seconds = milliseconds / 1000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+fn format_time(datetime: DateTime<Utc>) -> String {
+    datetime
+        .with_timezone(&Local)
+        .format("%H:%M:%S")
+        .to_string()
+}
@@ -0,0 +1,507 @@
+use std::path::Path;
+
+use chrono::prelude::*;
+use rocket::serde::{json, Deserialize, Serialize};
+use sea_orm::{
+    prelude::DateTimeUtc, ActiveModelTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr,
+    EntityTrait, IntoActiveModel, PaginatorTrait, Statement, TransactionTrait,
+};
+
+use crate::database;
+use crate::entity::*;
+use crate::usage;
+
+/// Rows inserted by single statement on import, sqlite limits amount of bound values
+static IMPORT_CHUNK: usize = 500;
+
+/// Recorded data of all tables, including cached metadata of applications, except derived daily
+/// usage and webhook deliveries log
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Export {
+    pub schema_version: usize,
+    pub exported_at: DateTimeUtc,
+    pub sessions: Vec<session::Model>,
+    pub events: Vec<event::Model>,
+    pub violations: Vec<violation::Model>,
+    pub goals: Vec<goal::Model>,
+    pub activities: Vec<activity::Model>,
+    pub manual_entries: Vec<manual_entry::Model>,
+    pub event_edits: Vec<event_edit::Model>,
+    pub usage_aggregates: Vec<usage_aggregate::Model>,
+    /// Missing in exports made before metadata of applications was stored
+    #[serde(default)]
+    pub applications: Vec<application::Model>,
+}
+
+/// Consistency rules as (description, query counting rows breaking the rule)
+static CHECKS: &[(&str, &str)] = &[
+    (
+        "events of missing sessions",
+        "SELECT COUNT(*) AS count FROM event WHERE session_id NOT IN (SELECT id FROM session)",
+    ),
+    (
+        "violations of missing sessions",
+        "SELECT COUNT(*) AS count FROM violation WHERE session_id NOT IN (SELECT id FROM session)",
+    ),
+    (
+        "events recorded before start of their session",
+        "SELECT COUNT(*) AS count FROM event JOIN session ON session.id = event.session_id \
+         WHERE event.timestamp < session.datetime",
+    ),
+    (
+        "sessions with events after start of the next session",
+        "SELECT COUNT(*) AS count FROM session AS current \
+         JOIN session AS next ON next.id = (SELECT MIN(id) FROM session WHERE id > current.id) \
+         WHERE (SELECT MAX(timestamp) FROM event WHERE session_id = current.id) > next.datetime",
+    ),
+    (
+        "manual entries ending before their start",
+        "SELECT COUNT(*) AS count FROM manual_entry WHERE \"end\" IS NOT NULL AND \"end\" <= start",
+    ),
+    (
+        "running timers besides one",
+        "SELECT MAX(COUNT(*) - 1, 0) AS count FROM manual_entry WHERE \"end\" IS NULL",
+    ),
+    (
+        "days with more than 25 hours of daily usage",
+        "SELECT COUNT(*) AS count FROM (SELECT day FROM daily_app_usage GROUP BY day \
+         HAVING SUM(duration) > 90000000)",
+    ),
+];
+
+pub async fn export(db: &DatabaseConnection, path: &Path) -> Result<Export, String> {
+    let export = Export {
+        schema_version: database::schema_version(db).await,
+        exported_at: Utc::now(),
+        sessions: session::Entity::find().all(db).await.map_err(db_error)?,
+        events: event::Entity::find().all(db).await.map_err(db_error)?,
+        violations: violation::Entity::find().all(db).await.map_err(db_error)?,
+        goals: goal::Entity::find().all(db).await.map_err(db_error)?,
+        activities: activity::Entity::find().all(db).await.map_err(db_error)?,
+        manual_entries: manual_entry::Entity::find()
+            .all(db)
+            .await
+            .map_err(db_error)?,
+        event_edits: event_edit::Entity::find().all(db).await.map_err(db_error)?,
+        usage_aggregates: usage_aggregate::Entity::find()
+            .all(db)
+            .await
+            .map_err(db_error)?,
+        applications: application::Entity::find()
+            .all(db)
+            .await
+            .map_err(db_error)?,
+    };
+
+    let content = json::to_pretty_string(&export)
+        .map_err(|err| format!("Could not serialize export: {}", err))?;
+
+    std::fs::write(path, content)
+        .map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+
+    Ok(export)
+}
+
+/// Loads export into database without recorded data, keeping ids so references stay valid.
+/// Daily usage is computed from imported data afterwards
+pub async fn import(db: &DatabaseConnection, path: &Path) -> Result<Export, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+    let export: Export = json::from_str(&content)
+        .map_err(|err| format!("Could not parse {}: {}", path.display(), err))?;
+
+    let version = database::schema_version(db).await;
+
+    if export.schema_version > version {
+        return Err(format!(
+            "Export has schema version {}, newer than {} of database",
+            export.schema_version, version
+        ));
+    }
+
+    let counts = [
+        ("session", session::Entity::find().count(db).await),
+        ("event", event::Entity::find().count(db).await),
+        ("violation", violation::Entity::find().count(db).await),
+        ("goal", goal::Entity::find().count(db).await),
+        ("activity", activity::Entity::find().count(db).await),
+        ("manual_entry", manual_entry::Entity::find().count(db).await),
+        ("event_edit", event_edit::Entity::find().count(db).await),
+        (
+            "usage_aggregate",
+            usage_aggregate::Entity::find().count(db).await,
+        ),
+        ("application", application::Entity::find().count(db).await),
+    ];
+
+    for (table, count) in counts {
+        if count.map_err(db_error)? > 0 {
+            return Err(format!(
+                "Table {} is not empty, import needs new database",
+                table
+            ));
+        }
+    }
+
+    let txn = db.begin().await.map_err(db_error)?;
+
+    insert_all::<application::ActiveModel>(&txn, &export.applications).await?;
+    insert_all::<session::ActiveModel>(&txn, &export.sessions).await?;
+    insert_all::<event::ActiveModel>(&txn, &export.events).await?;
+    insert_all::<violation::ActiveModel>(&txn, &export.violations).await?;
+    insert_all::<goal::ActiveModel>(&txn, &export.goals).await?;
+    insert_all::<activity::ActiveModel>(&txn, &export.activities).await?;
+    insert_all::<manual_entry::ActiveModel>(&txn, &export.manual_entries).await?;
+    insert_all::<event_edit::ActiveModel>(&txn, &export.event_edits).await?;
+    insert_all::<usage_aggregate::ActiveModel>(&txn, &export.usage_aggregates).await?;
+
+    txn.commit().await.map_err(db_error)?;
+
+    usage::rebuild(db).await.map_err(db_error)?;
+
+    Ok(export)
+}
+
+/// Problems of database file and recorded data, empty when everything is consistent
+pub async fn check(db: &DatabaseConnection) -> Result<Vec<String>, String> {
+    let mut problems = vec![];
+
+    let integrity = db
+        .query_all(Statement::from_string(
+            DbBackend::Sqlite,
+            "PRAGMA integrity_check".to_owned(),
+        ))
+        .await
+        .map_err(db_error)?;
+
+    for row in integrity {
+        let message: String = row.try_get("", "integrity_check").map_err(db_error)?;
+
+        if message != "ok" {
+            problems.push(format!("Integrity: {}", message));
+        }
+    }
+
+    let version = database::schema_version(db).await;
+
+    if version != database::latest_schema_version() {
+        problems.push(format!(
+            "Schema version is {}, expected {}, run migrate",
+            version,
+            database::latest_schema_version()
+        ));
+    }
+
+    for (description, query) in CHECKS {
+        let row = db
+            .query_one(Statement::from_string(DbBackend::Sqlite, query.to_string()))
+            .await
+            .map_err(db_error)?
+            .unwrap();
+
+        let count: i64 = row.try_get("", "count").map_err(db_error)?;
+
+        if count > 0 {
+            problems.push(format!("{} {}", count, description));
+        }
+    }
+
+    Ok(problems)
+}
+
+async fn insert_all<A>(
+    conn: &impl ConnectionTrait,
+    models: &[<A::Entity as EntityTrait>::Model],
+) -> Result<(), String>
+where
+    A: ActiveModelTrait,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+{
+    for chunk in models.chunks(IMPORT_CHUNK) {
+        A::Entity::insert_many(
+            chunk
+                .iter()
+                .cloned()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .exec_without_returning(conn)
+        .await
+        .map_err(db_error)?;
+    }
+
+    Ok(())
+}
+
+fn db_error(err: DbErr) -> String {
+    format!("Database error: {}", err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+    use sea_orm::{Database, Set};
+
+    async fn setup() -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+        database::migrate(&db).await;
+
+        db
+    }
+
+    async fn insert_session(db: &DatabaseConnection, id: i32, datetime: DateTimeUtc) {
+        session::ActiveModel {
+            id: Set(id),
+            datetime: Set(datetime),
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    async fn insert_event(db: &DatabaseConnection, session_id: i32, timestamp: DateTimeUtc) {
+        event::ActiveModel {
+            path: Set("code.exe".to_owned()),
+            title: Set("main.rs".to_owned()),
+            timestamp: Set(timestamp),
+            app_title: Set("Code".to_owned()),
+            offset: Set(0),
+            session_id: Set(session_id),
+            kind: Set(event::EventKind::Focus),
+            in_schedule: Set(true),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    async fn insert_manual_entry(
+        db: &DatabaseConnection,
+        start: DateTimeUtc,
+        end: Option<DateTimeUtc>,
+    ) {
+        manual_entry::ActiveModel {
+            start: Set(start),
+            end: Set(end),
+            description: Set("Meeting".to_owned()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    /// References to missing sessions can only be made with foreign keys disabled
+    async fn disable_foreign_keys(db: &DatabaseConnection) {
+        db.execute(Statement::from_string(
+            DbBackend::Sqlite,
+            "PRAGMA foreign_keys = OFF".to_owned(),
+        ))
+        .await
+        .unwrap();
+    }
+
+    async fn assert_single_problem(db: &DatabaseConnection, expected: &str) {
+        assert_eq!(check(db).await.unwrap(), vec![expected.to_owned()]);
+    }
+
+    fn start() -> DateTimeUtc {
+        Utc::now() - Duration::days(1)
+    }
+
+    #[tokio::test]
+    async fn export_import_round_trip() {
+        let db = setup().await;
+        let start = start();
+
+        insert_session(&db, 1, start).await;
+        insert_event(&db, 1, start).await;
+        insert_event(&db, 1, start + Duration::minutes(30)).await;
+        insert_manual_entry(
+            &db,
+            start + Duration::hours(1),
+            Some(start + Duration::hours(2)),
+        )
+        .await;
+
+        goal::ActiveModel {
+            name: Set("Less chat".to_owned()),
+            target: Set(goal::GoalTarget::App),
+            target_name: Set("slack".to_owned()),
+            comparison: Set(goal::GoalComparison::AtMost),
+            minutes: Set(30),
+            period: Set(goal::GoalPeriod::Day),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        application::ActiveModel {
+            path: Set("c:\\program files\\slack\\slack.exe".to_owned()),
+            app_title: Set("Slack".to_owned()),
+            publisher: Set(Some("Slack Technologies".to_owned())),
+            icon_key: Set("0123456789abcdef".to_owned()),
+            file_size: Set(Some(1024)),
+            updated_at: Set(start),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("productivity-export-{}.json", std::process::id()));
+
+        let exported = export(&db, &path).await.unwrap();
+
+        let imported_db = setup().await;
+        let imported = import(&imported_db, &path).await.unwrap();
+        let exported_again = export(&imported_db, &path).await.unwrap();
+
+        // import needs empty database
+        let imported_again = import(&imported_db, &path).await;
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(exported.events.len(), 2);
+        assert_eq!(exported.applications.len(), 1);
+        assert_eq!(
+            json::to_value(&imported).unwrap(),
+            json::to_value(&exported).unwrap()
+        );
+        assert_eq!(
+            json::to_value(Export {
+                exported_at: exported.exported_at,
+                ..exported_again
+            })
+            .unwrap(),
+            json::to_value(&exported).unwrap()
+        );
+
+        assert!(imported_again.unwrap_err().contains("is not empty"));
+        assert!(check(&imported_db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn consistent_database_has_no_problems() {
+        let db = setup().await;
+        let start = start();
+
+        insert_session(&db, 1, start).await;
+        insert_event(&db, 1, start).await;
+        insert_session(&db, 2, start + Duration::hours(1)).await;
+        insert_event(&db, 2, start + Duration::hours(2)).await;
+        insert_manual_entry(&db, start, Some(start + Duration::hours(1))).await;
+        insert_manual_entry(&db, start + Duration::hours(3), None).await;
+
+        assert!(check(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn events_of_missing_sessions() {
+        let db = setup().await;
+
+        disable_foreign_keys(&db).await;
+        insert_event(&db, 7, start()).await;
+
+        assert_single_problem(&db, "1 events of missing sessions").await;
+    }
+
+    #[tokio::test]
+    async fn violations_of_missing_sessions() {
+        let db = setup().await;
+
+        disable_foreign_keys(&db).await;
+
+        violation::ActiveModel {
+            rule: Set("games".to_owned()),
+            path: Set("game.exe".to_owned()),
+            title: Set("Game".to_owned()),
+            app_title: Set("Game".to_owned()),
+            timestamp: Set(start()),
+            enforced: Set(false),
+            session_id: Set(7),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        assert_single_problem(&db, "1 violations of missing sessions").await;
+    }
+
+    #[tokio::test]
+    async fn events_before_session_start() {
+        let db = setup().await;
+        let start = start();
+
+        insert_session(&db, 1, start).await;
+        insert_event(&db, 1, start - Duration::minutes(1)).await;
+
+        assert_single_problem(&db, "1 events recorded before start of their session").await;
+    }
+
+    #[tokio::test]
+    async fn events_after_next_session_start() {
+        let db = setup().await;
+        let start = start();
+
+        insert_session(&db, 1, start).await;
+        insert_session(&db, 2, start + Duration::hours(1)).await;
+        insert_event(&db, 1, start + Duration::hours(2)).await;
+
+        assert_single_problem(
+            &db,
+            "1 sessions with events after start of the next session",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn manual_entries_ending_before_start() {
+        let db = setup().await;
+        let start = start();
+
+        insert_manual_entry(&db, start, Some(start)).await;
+
+        assert_single_problem(&db, "1 manual entries ending before their start").await;
+    }
+
+    #[tokio::test]
+    async fn several_running_timers() {
+        let db = setup().await;
+        let start = start();
+
+        insert_manual_entry(&db, start, None).await;
+        insert_manual_entry(&db, start + Duration::hours(1), None).await;
+
+        assert_single_problem(&db, "1 running timers besides one").await;
+    }
+
+    #[tokio::test]
+    async fn days_with_too_much_usage() {
+        let db = setup().await;
+        let day = start();
+
+        for app_title in ["Code", "Firefox"] {
+            daily_app_usage::ActiveModel {
+                day: Set(day),
+                app_title: Set(app_title.to_owned()),
+                category: Set(None),
+                duration: Set(13 * 60 * 60 * 1000),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+
+        assert_single_problem(&db, "1 days with more than 25 hours of daily usage").await;
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use rocket::figment::{
+    providers::{Format, Toml},
+    Figment,
+};
+use rocket::serde::{Deserialize, Serialize};
+
+static DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct Config {
+    pub filter: FilterConfig,
+    pub privacy: PrivacyConfig,
+    pub schedule: ScheduleConfig,
+    pub rules: RulesConfig,
+    pub webhooks: Vec<WebhookConfig>,
+    pub categories: Vec<Category>,
+    pub browser: BrowserConfig,
+    pub idle: IdleConfig,
+    pub retention: RetentionConfig,
+    pub logging: LoggingConfig,
+    pub storage: StorageConfig,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct FilterConfig {
+    /// Hide already recorded events matching the rules from statistics on startup
+    pub hide_history: bool,
+    pub exclusions: Vec<WindowMatch>,
+}
+
+/// Every specified field must match for a window to match
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct WindowMatch {
+    /// Application title or executable file name, case insensitive
+    pub app: Option<String>,
+    /// Glob pattern for executable path, case insensitive
+    pub path: Option<String>,
+    /// Regular expression for window title
+    pub title: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct PrivacyConfig {
+    /// Salt for `hash` mode, required when any rule uses it
+    pub salt: Option<String>,
+    pub rules: Vec<PrivacyRule>,
+}
+
+/// How window title is stored for matching app, first matching rule wins
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PrivacyRule {
+    /// Application title or executable file name, case insensitive
+    pub app: String,
+    pub mode: PrivacyMode,
+    /// Regular expression for `redact` mode, whole title is replaced when missing
+    pub pattern: Option<String>,
+    pub replacement: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum PrivacyMode {
+    Full,
+    Redact,
+    Hash,
+    Drop,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct ScheduleConfig {
+    /// Keep recording outside of schedule, marking events as out of schedule instead of dropping them
+    pub record_outside: bool,
+    pub windows: Vec<ScheduleWindow>,
+}
+
+/// Tracked window of local time, `end` before `start` means window finishes on the next day
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ScheduleWindow {
+    /// Week days, like "mon" or "Monday"
+    pub days: Vec<String>,
+    /// Time in "HH:MM" format
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct RulesConfig {
+    pub forbidden: Vec<ForbiddenRule>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ForbiddenRule {
+    pub name: String,
+    #[serde(flatten)]
+    pub window: WindowMatch,
+    /// Local time windows when rule is active, always active when empty
+    #[serde(default)]
+    pub windows: Vec<ScheduleWindow>,
+    /// Enforce rule by closing forbidden application, only notify otherwise
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// Subscription of url to notifications, sent with POST request
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Key for HMAC-SHA256 signature of request body, sent in `X-Signature` header
+    pub secret: Option<String>,
+    /// Notification types, like "focus" or "goal_threshold", every notification is sent when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+/// Category is assigned to window matching any of `matches`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Category {
+    pub name: String,
+    pub matches: Vec<WindowMatch>,
+}
+
+/// What part of tab url reported by browser extension is stored, domain is always stored
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct BrowserConfig {
+    pub keep_path: bool,
+    pub keep_query: bool,
+    /// Application titles or executable file names of browsers, tab reported without browser is
+    /// attributed to focused application only when it is one of them
+    pub apps: Vec<String>,
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        Self {
+            keep_path: true,
+            keep_query: false,
+            apps: [
+                "chrome.exe",
+                "msedge.exe",
+                "firefox.exe",
+                "brave.exe",
+                "opera.exe",
+                "vivaldi.exe",
+            ]
+            .map(str::to_owned)
+            .to_vec(),
+        }
+    }
+}
+
+/// User without keyboard and mouse input is idle, transitions are sent as notifications
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct IdleConfig {
+    /// Seconds without input after which user is idle, idle time is not detected when 0
+    pub threshold_seconds: u32,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            threshold_seconds: 300,
+        }
+    }
+}
+
+/// Old events are compacted into aggregates, data is kept as is when days are not set
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct RetentionConfig {
+    /// Events older than this amount of days are rolled up into hourly aggregates
+    pub raw_days: Option<u32>,
+    /// Hourly aggregates older than this amount of days are coarsened into daily ones
+    pub hourly_days: Option<u32>,
+}
+
+/// Writing of recorded events into database
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct StorageConfig {
+    /// Events waiting to be written, tracker waits for free space once it is full
+    pub buffer_size: usize,
+    /// Events are appended to this file while database is unavailable and written on recovery
+    pub journal: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: 256,
+            journal: "pending-events.jsonl".to_owned(),
+        }
+    }
+}
+
+/// Log of `run` command, one-off commands always write text into stderr
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct LoggingConfig {
+    /// One of "off", "error", "warn", "info", "debug", "trace"
+    pub level: String,
+    /// Levels of modules (log targets) and their submodules, like "service::webhooks" or "sqlx"
+    pub modules: BTreeMap<String, String>,
+    pub format: LogFormat,
+    /// Log is written into this file instead of stdout
+    pub file: Option<String>,
+    /// Log file is rotated once it grows over this size
+    pub max_size_mb: u64,
+    /// Amount of rotated files kept next to log file
+    pub max_files: u32,
+    /// Panics are appended into this file, `crashes.log` next to log file or executable by default
+    pub crash_file: Option<String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_owned(),
+            modules: BTreeMap::new(),
+            format: LogFormat::Text,
+            file: None,
+            max_size_mb: 10,
+            max_files: 5,
+            crash_file: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+pub fn config_path() -> String {
+    std::env::var("CONFIG_FILE").unwrap_or(DEFAULT_CONFIG_FILE.to_owned())
+}
+
+// Config file is optional, missing file means default config. It is loaded before logger is set up
+pub fn load_config() -> Config {
+    let path = config_path();
+
+    Figment::new()
+        .merge(Toml::file(&path))
+        .extract()
+        .unwrap_or_else(|err| panic!("Could not load config from {}: {}", path, err))
+}
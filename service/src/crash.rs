@@ -0,0 +1,171 @@
+use std::{
+    backtrace::Backtrace,
+    fs::OpenOptions,
+    future::Future,
+    io::Write,
+    panic,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::prelude::*;
+use once_cell::sync::Lazy;
+use rocket::serde::{json, Serialize};
+use sea_orm::prelude::DateTimeUtc;
+
+use crate::config::LoggingConfig;
+
+static CRASH_LOG: &str = "crashes.log";
+
+/// The latest panic of supervised task, panics of server handlers and other tasks are only logged
+static LAST_PANIC: Lazy<Mutex<Option<CrashReport>>> = Lazy::new(|| Mutex::new(None));
+
+tokio::task_local! {
+    /// Set while supervised task is polled, so panic hook knows who panicked
+    static SUPERVISED: ();
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CrashReport {
+    pub timestamp: DateTimeUtc,
+    pub thread: String,
+    pub message: String,
+    /// File, line and column of panic
+    pub location: Option<String>,
+    pub backtrace: String,
+}
+
+/// Crash log set in config, otherwise next to log file, or next to executable when log is written
+/// into stdout. Working directory of Windows service is System32
+pub fn crash_log_path(config: &LoggingConfig) -> PathBuf {
+    if let Some(file) = &config.crash_file {
+        return PathBuf::from(file);
+    }
+
+    let dir = match &config.file {
+        Some(file) => Path::new(file).parent().map(Path::to_path_buf),
+        None => std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf)),
+    };
+
+    dir.unwrap_or_default().join(CRASH_LOG)
+}
+
+/// Panics are logged and appended to crash log as JSON lines instead of aborting the process, so
+/// supervised tasks can be restarted
+pub fn install_panic_hook(crash_log: PathBuf) {
+    panic::set_hook(Box::new(move |info| {
+        let message = match info.payload().downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match info.payload().downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => "Box<dyn Any>".to_owned(),
+            },
+        };
+
+        let report = CrashReport {
+            timestamp: Utc::now(),
+            thread: std::thread::current()
+                .name()
+                .unwrap_or("<unnamed>")
+                .to_owned(),
+            message,
+            location: info.location().map(|location| location.to_string()),
+            backtrace: Backtrace::force_capture().to_string(),
+        };
+
+        error!(
+            "Got panic in thread {} at {}: {}\n@stackTrace:{}",
+            report.thread,
+            report.location.as_deref().unwrap_or("unknown location"),
+            report.message,
+            report.backtrace
+        );
+
+        if let Err(err) = append_report(&crash_log, &report) {
+            error!(
+                "Could not write crash report into {}: {}",
+                crash_log.display(),
+                err
+            );
+        }
+
+        if SUPERVISED.try_with(|_| ()).is_ok() {
+            *LAST_PANIC.lock().unwrap_or_else(|err| err.into_inner()) = Some(report);
+        }
+    }));
+}
+
+/// Runs task whose panics are kept for its supervisor
+pub async fn supervised<F: Future>(task: F) -> F::Output {
+    SUPERVISED.scope((), task).await
+}
+
+/// Report of the latest panic, taken by supervisor of crashed task
+pub fn take_last_panic() -> Option<CrashReport> {
+    LAST_PANIC
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .take()
+}
+
+fn append_report(path: &Path, report: &CrashReport) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    writeln!(file, "{}", json::to_string(report).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crash_log_is_next_to_log_file_unless_set() {
+        let mut config = LoggingConfig {
+            file: Some("C:/ProgramData/productivity/service.log".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            crash_log_path(&config),
+            Path::new("C:/ProgramData/productivity").join(CRASH_LOG)
+        );
+
+        config.crash_file = Some("D:/crashes.jsonl".to_owned());
+        assert_eq!(crash_log_path(&config), PathBuf::from("D:/crashes.jsonl"));
+
+        config.file = None;
+        config.crash_file = None;
+        assert!(crash_log_path(&config).is_absolute());
+    }
+
+    #[tokio::test]
+    async fn only_panics_of_supervised_tasks_are_kept() {
+        let crash_log =
+            std::env::temp_dir().join(format!("productivity-crashes-{}.log", std::process::id()));
+
+        install_panic_hook(crash_log.clone());
+
+        tokio::spawn(async { panic!("handler panicked") })
+            .await
+            .unwrap_err();
+        let handler_panic = take_last_panic();
+
+        tokio::spawn(supervised(async { panic!("tracker panicked") }))
+            .await
+            .unwrap_err();
+        let tracker_panic = take_last_panic();
+
+        // default hook is back for the rest of tests
+        let _ = panic::take_hook();
+
+        let log = std::fs::read_to_string(&crash_log).unwrap();
+        std::fs::remove_file(&crash_log).unwrap();
+
+        assert!(handler_panic.is_none());
+        assert_eq!(tracker_panic.unwrap().message, "tracker panicked");
+        assert!(log.contains("handler panicked") && log.contains("tracker panicked"));
+    }
+}
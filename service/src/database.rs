@@ -1,38 +1,164 @@
 use crate::entity::*;
-use sea_orm::{ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, Schema};
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, EntityTrait,
+    Schema, Statement,
+};
 
-static DATABASE_URL: &str = "sqlite://data.db?mode=rwc";
+pub const DEFAULT_DATABASE: &str = "data.db";
 
-pub async fn setup_database() -> DatabaseConnection {
-    let mut opt = ConnectOptions::new(DATABASE_URL.to_string());
-    opt.sqlx_logging(false)
-        .sqlx_logging_level(log::LevelFilter::Info);
+/// Columns added to entities after their tables were released, as (table, column, definition).
+/// Tables created from entities already have them, so existing columns are skipped.
+/// Database `user_version` is the number of applied migrations
+static MIGRATIONS: &[(&str, &str, &str)] = &[
+    ("event", "kind", "varchar(16) NOT NULL DEFAULT 'focus'"),
+    ("event", "in_schedule", "boolean NOT NULL DEFAULT true"),
+    ("event", "category", "text"),
+    ("activity", "domain", "text"),
+    ("event", "project", "text"),
+    ("usage_aggregate", "source", "text"),
+    ("usage_aggregate", "domain", "text"),
+    ("activity", "path", "text"),
+    ("daily_app_usage", "in_schedule", "boolean"),
+];
 
-    let db = Database::connect(opt).await.unwrap();
-    info!("Database connected");
+/// Tables summed from recorded data, their rows are cleared when a column is added so they are
+/// computed again with it at startup
+static DERIVED_TABLES: &[&str] = &["daily_app_usage"];
+
+pub async fn setup_database(path: &str) -> DatabaseConnection {
+    let db = connect(path, false).await;
 
     create_db_tables(&db).await;
 
     info!("Database tables created if they were not present");
 
+    migrate(&db).await;
+
+    db
+}
+
+/// Connection to database as is, without creating tables or applying migrations
+pub async fn connect(path: &str, read_only: bool) -> DatabaseConnection {
+    let mode = if read_only { "ro" } else { "rwc" };
+
+    let mut opt = ConnectOptions::new(format!("sqlite://{}?mode={}", path, mode));
+    opt.sqlx_logging(false)
+        .sqlx_logging_level(log::LevelFilter::Info);
+
+    let db = Database::connect(opt).await.unwrap();
+    info!("Database connected");
+
     db
 }
 
 pub async fn create_db_tables(db: &DatabaseConnection) {
+    create_table(db, event::Entity).await;
+    create_table(db, session::Entity).await;
+    create_table(db, violation::Entity).await;
+    create_table(db, goal::Entity).await;
+    create_table(db, webhook_delivery::Entity).await;
+    create_table(db, activity::Entity).await;
+    create_table(db, manual_entry::Entity).await;
+    create_table(db, event_edit::Entity).await;
+    create_table(db, usage_aggregate::Entity).await;
+    create_table(db, daily_app_usage::Entity).await;
+    create_table(db, application::Entity).await;
+
+    // deduplication of ingested activities
+    db.execute(Statement::from_string(
+        DbBackend::Sqlite,
+        "CREATE UNIQUE INDEX IF NOT EXISTS activity_source_client_id ON activity (source, client_id)"
+            .to_owned(),
+    ))
+    .await
+    .unwrap();
+
+    // range statistics read daily usage by day
+    db.execute(Statement::from_string(
+        DbBackend::Sqlite,
+        "CREATE INDEX IF NOT EXISTS daily_app_usage_day ON daily_app_usage (day)".to_owned(),
+    ))
+    .await
+    .unwrap();
+}
+
+async fn create_table<E: EntityTrait>(db: &DatabaseConnection, entity: E) {
     let builder = db.get_database_backend();
 
     let stmt = builder.build(
         Schema::new(DbBackend::Sqlite)
-            .create_table_from_entity(event::Entity)
-            .if_not_exists(),
-    );
-
-    let stmt2 = builder.build(
-        Schema::new(DbBackend::Sqlite)
-            .create_table_from_entity(session::Entity)
+            .create_table_from_entity(entity)
             .if_not_exists(),
     );
 
     db.execute(stmt).await.unwrap();
-    db.execute(stmt2).await.unwrap();
+}
+
+pub async fn migrate(db: &DatabaseConnection) {
+    let version = schema_version(db).await;
+
+    for (index, (table, column, definition)) in MIGRATIONS.iter().enumerate().skip(version) {
+        if !column_exists(db, table, column).await {
+            db.execute(Statement::from_string(
+                DbBackend::Sqlite,
+                format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            ))
+            .await
+            .unwrap();
+
+            info!("Added column {}.{}", table, column);
+
+            if DERIVED_TABLES.contains(table) {
+                db.execute(Statement::from_string(
+                    DbBackend::Sqlite,
+                    format!("DELETE FROM {}", table),
+                ))
+                .await
+                .unwrap();
+            }
+        }
+
+        set_schema_version(db, index + 1).await;
+    }
+}
+
+/// Schema version of database with every migration applied
+pub fn latest_schema_version() -> usize {
+    MIGRATIONS.len()
+}
+
+pub async fn schema_version(db: &DatabaseConnection) -> usize {
+    try_schema_version(db).await.unwrap()
+}
+
+pub async fn try_schema_version(db: &DatabaseConnection) -> Result<usize, DbErr> {
+    let row = db
+        .query_one(Statement::from_string(
+            DbBackend::Sqlite,
+            "PRAGMA user_version".to_owned(),
+        ))
+        .await?
+        .ok_or(DbErr::RecordNotFound("user_version".to_owned()))?;
+
+    Ok(row.try_get::<i32>("", "user_version")? as usize)
+}
+
+async fn set_schema_version(db: &DatabaseConnection, version: usize) {
+    db.execute(Statement::from_string(
+        DbBackend::Sqlite,
+        format!("PRAGMA user_version = {}", version),
+    ))
+    .await
+    .unwrap();
+}
+
+async fn column_exists(db: &DatabaseConnection, table: &str, column: &str) -> bool {
+    db.query_one(Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        "SELECT name FROM pragma_table_info(?) WHERE name = ?",
+        [table.into(), column.into()],
+    ))
+    .await
+    .unwrap()
+    .is_some()
 }
@@ -0,0 +1,635 @@
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use rocket::serde::{json::serde_json, Deserialize, Deserializer};
+use sea_orm::{
+    prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait,
+    DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+
+use crate::entity::*;
+use crate::usage;
+
+#[derive(Debug)]
+pub enum EditError {
+    NotFound(String),
+    /// Edit does not make sense for given events
+    Invalid(String),
+    /// Events were changed since the edit, so it can not be reverted
+    Conflict(String),
+    Db(DbErr),
+}
+
+impl From<DbErr> for EditError {
+    fn from(err: DbErr) -> Self {
+        EditError::Db(err)
+    }
+}
+
+/// New attribution of interval, missing fields stay as they are, `null` category or project is
+/// cleared
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Reassignment {
+    pub app_title: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    pub category: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    pub project: Option<Option<String>>,
+}
+
+/// Present field is `Some`, even when it is `null`
+fn deserialize_clearable<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+pub async fn reassign(
+    db: &DatabaseConnection,
+    id: i32,
+    reassignment: Reassignment,
+) -> Result<event_edit::Model, EditError> {
+    let txn = db.begin().await?;
+    let event = find_event(&txn, id).await?;
+
+    let mut changed = event.clone();
+    changed.app_title = reassignment.app_title.unwrap_or(changed.app_title);
+    changed.category = reassignment.category.unwrap_or(changed.category);
+    changed.project = reassignment.project.unwrap_or(changed.project);
+
+    if changed == event {
+        return Err(EditError::Invalid("Nothing to change".to_owned()));
+    }
+
+    let changed = event::ActiveModel::from(changed)
+        .reset_all()
+        .update(&txn)
+        .await?;
+
+    let edit = record_edit(&txn, event_edit::EditAction::Reassign, &[event], &[changed]).await?;
+    txn.commit().await?;
+
+    refresh_usage(db, &edit).await?;
+
+    Ok(edit)
+}
+
+/// Splits interval of event at given moment, the second part starts with copy of the event
+pub async fn split(
+    db: &DatabaseConnection,
+    id: i32,
+    at: DateTimeUtc,
+) -> Result<event_edit::Model, EditError> {
+    let txn = db.begin().await?;
+    let event = find_event(&txn, id).await?;
+
+    let end = interval_end(&txn, &event).await?;
+    if at <= event.timestamp || end.is_none_or(|end| at >= end) {
+        return Err(EditError::Invalid(format!(
+            "{} is not within interval of event {}",
+            at, id
+        )));
+    }
+
+    let copy = insert_copy(&txn, &event, at).await?;
+
+    let edit = record_edit(
+        &txn,
+        event_edit::EditAction::Split,
+        std::slice::from_ref(&event),
+        &[event.clone(), copy],
+    )
+    .await?;
+    txn.commit().await?;
+
+    refresh_usage(db, &edit).await?;
+
+    Ok(edit)
+}
+
+/// Merges interval of event with the next one of the same session, keeping attribution of the first
+pub async fn merge(db: &DatabaseConnection, id: i32) -> Result<event_edit::Model, EditError> {
+    let txn = db.begin().await?;
+    let event = find_event(&txn, id).await?;
+
+    let Some(next) = next_event(&txn, &event).await? else {
+        return Err(EditError::Invalid(format!(
+            "Event {} has no next interval to merge with",
+            id
+        )));
+    };
+
+    // markers of paused, off schedule or finished tracking are not recorded time
+    if let Some(marker) = [&event, &next].into_iter().find(|event| is_marker(event)) {
+        return Err(EditError::Invalid(format!(
+            "Event {} marks tracking stop and can not be merged",
+            marker.id
+        )));
+    }
+
+    event::Entity::delete_by_id(next.id).exec(&txn).await?;
+
+    let edit = record_edit(
+        &txn,
+        event_edit::EditAction::Merge,
+        &[event.clone(), next],
+        &[event],
+    )
+    .await?;
+    txn.commit().await?;
+
+    refresh_usage(db, &edit).await?;
+
+    Ok(edit)
+}
+
+/// Marks time within range as deleted, intervals crossing range bounds are split at them
+pub async fn delete_range(
+    db: &DatabaseConnection,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<event_edit::Model, EditError> {
+    if from >= to {
+        return Err(EditError::Invalid(
+            "Range end must be after its start".to_owned(),
+        ));
+    }
+
+    let txn = db.begin().await?;
+    let mut before = vec![];
+    let mut after = BTreeMap::new();
+
+    // time after the range keeps attribution of interval crossing its end
+    for at in [to, from] {
+        let covering = event::Entity::find()
+            .filter(event::Column::Timestamp.lt(at))
+            .order_by_desc(event::Column::Timestamp)
+            .order_by_desc(event::Column::Id)
+            .one(&txn)
+            .await?;
+
+        let Some(covering) = covering else {
+            continue;
+        };
+
+        if interval_end(&txn, &covering)
+            .await?
+            .is_some_and(|end| end > at)
+        {
+            let copy = insert_copy(&txn, &covering, at).await?;
+            after.insert(copy.id, copy);
+        }
+    }
+
+    let events = event::Entity::find()
+        .filter(event::Column::Timestamp.gte(from))
+        .filter(event::Column::Timestamp.lt(to))
+        .filter(event::Column::Kind.ne(event::EventKind::Deleted))
+        .all(&txn)
+        .await?;
+
+    for event in events {
+        // copies made by this edit did not exist before it
+        if !after.contains_key(&event.id) {
+            before.push(event.clone());
+        }
+
+        let mut record = event::ActiveModel::from(event);
+        record.kind = Set(event::EventKind::Deleted);
+
+        let deleted = record.update(&txn).await?;
+        after.insert(deleted.id, deleted);
+    }
+
+    if after.is_empty() {
+        return Err(EditError::Invalid(
+            "No recorded time within range".to_owned(),
+        ));
+    }
+
+    let after: Vec<event::Model> = after.into_values().collect();
+
+    let edit = record_edit(&txn, event_edit::EditAction::Delete, &before, &after).await?;
+    txn.commit().await?;
+
+    refresh_usage(db, &edit).await?;
+
+    Ok(edit)
+}
+
+/// Restores events as they were before the edit, if they were not changed since
+pub async fn revert(db: &DatabaseConnection, id: i32) -> Result<event_edit::Model, EditError> {
+    let txn = db.begin().await?;
+
+    let edit = event_edit::Entity::find_by_id(id)
+        .one(&txn)
+        .await?
+        .ok_or(EditError::NotFound(format!("Edit {} not found", id)))?;
+
+    if edit.reverted {
+        return Err(EditError::Conflict(format!(
+            "Edit {} is already reverted",
+            id
+        )));
+    }
+
+    let before: Vec<event::Model> = serde_json::from_value(edit.before.clone())
+        .map_err(|err| EditError::Invalid(err.to_string()))?;
+    let after: Vec<event::Model> = serde_json::from_value(edit.after.clone())
+        .map_err(|err| EditError::Invalid(err.to_string()))?;
+
+    for expected in &after {
+        let current = event::Entity::find_by_id(expected.id).one(&txn).await?;
+
+        if current.as_ref() != Some(expected) {
+            return Err(EditError::Conflict(format!(
+                "Event {} was changed after edit {}, revert later edits first",
+                expected.id, id
+            )));
+        }
+    }
+
+    let after_ids: Vec<i32> = after.iter().map(|event| event.id).collect();
+
+    event::Entity::delete_many()
+        .filter(event::Column::Id.is_in(after_ids))
+        .exec(&txn)
+        .await?;
+
+    if !before.is_empty() {
+        event::Entity::insert_many(before.into_iter().map(event::ActiveModel::from))
+            .exec_without_returning(&txn)
+            .await?;
+    }
+
+    let mut record = event_edit::ActiveModel::from(edit);
+    record.reverted = Set(true);
+
+    let edit = record.update(&txn).await?;
+    txn.commit().await?;
+
+    refresh_usage(db, &edit).await?;
+
+    Ok(edit)
+}
+
+/// Daily usage of days touched by edit is computed again
+async fn refresh_usage(db: &DatabaseConnection, edit: &event_edit::Model) -> Result<(), EditError> {
+    let mut events: Vec<event::Model> = serde_json::from_value(edit.before.clone())
+        .map_err(|err| EditError::Invalid(err.to_string()))?;
+    let after: Vec<event::Model> = serde_json::from_value(edit.after.clone())
+        .map_err(|err| EditError::Invalid(err.to_string()))?;
+
+    events.extend(after);
+
+    usage::refresh_for_events(db, &events).await?;
+
+    Ok(())
+}
+
+fn is_marker(event: &event::Model) -> bool {
+    matches!(
+        event.kind,
+        event::EventKind::Paused | event::EventKind::OffSchedule | event::EventKind::Shutdown
+    )
+}
+
+async fn find_event(conn: &impl ConnectionTrait, id: i32) -> Result<event::Model, EditError> {
+    event::Entity::find_by_id(id)
+        .one(conn)
+        .await?
+        .ok_or(EditError::NotFound(format!("Event {} not found", id)))
+}
+
+async fn next_event(
+    conn: &impl ConnectionTrait,
+    event: &event::Model,
+) -> Result<Option<event::Model>, DbErr> {
+    event::Entity::find()
+        .filter(event::Column::SessionId.eq(event.session_id))
+        .filter(
+            Condition::any()
+                .add(event::Column::Timestamp.gt(event.timestamp))
+                .add(
+                    Condition::all()
+                        .add(event::Column::Timestamp.eq(event.timestamp))
+                        .add(event::Column::Id.gt(event.id)),
+                ),
+        )
+        .order_by_asc(event::Column::Timestamp)
+        .order_by_asc(event::Column::Id)
+        .one(conn)
+        .await
+}
+
+/// Start of the next event of the same session, now for the last event of current session.
+/// Last events of finished sessions have unknown end
+pub async fn interval_end(
+    conn: &impl ConnectionTrait,
+    event: &event::Model,
+) -> Result<Option<DateTimeUtc>, DbErr> {
+    if let Some(next) = next_event(conn, event).await? {
+        return Ok(Some(next.timestamp));
+    }
+
+    let open_session = session::Entity::find()
+        .order_by_desc(session::Column::Id)
+        .one(conn)
+        .await?
+        .map(|session| session.id);
+
+    Ok((open_session == Some(event.session_id)).then(Utc::now))
+}
+
+/// Copy of event starting at given moment, splits interval of the event
+pub async fn insert_copy(
+    conn: &impl ConnectionTrait,
+    event: &event::Model,
+    at: DateTimeUtc,
+) -> Result<event::Model, DbErr> {
+    let elapsed = (at - event.timestamp).num_milliseconds() as u32;
+
+    let record = event::ActiveModel {
+        path: Set(event.path.clone()),
+        title: Set(event.title.clone()),
+        timestamp: Set(at),
+        app_title: Set(event.app_title.clone()),
+        offset: Set(event.offset.wrapping_add(elapsed)),
+        session_id: Set(event.session_id),
+        kind: Set(event.kind),
+        in_schedule: Set(event.in_schedule),
+        category: Set(event.category.clone()),
+        project: Set(event.project.clone()),
+        ..Default::default()
+    };
+
+    record.insert(conn).await
+}
+
+async fn record_edit(
+    conn: &impl ConnectionTrait,
+    action: event_edit::EditAction,
+    before: &[event::Model],
+    after: &[event::Model],
+) -> Result<event_edit::Model, DbErr> {
+    let record = event_edit::ActiveModel {
+        action: Set(action),
+        before: Set(serde_json::to_value(before).unwrap()),
+        after: Set(serde_json::to_value(after).unwrap()),
+        timestamp: Set(Utc::now()),
+        reverted: Set(false),
+        ..Default::default()
+    };
+
+    record.insert(conn).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+    use sea_orm::Database;
+
+    use crate::database;
+
+    struct Recorded {
+        db: DatabaseConnection,
+        start: DateTimeUtc,
+        events: Vec<event::Model>,
+    }
+
+    /// Finished session with Code, Firefox and Slack focused for 10 minutes each, then shutdown
+    /// marker, and current session started afterwards
+    async fn setup() -> Recorded {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let start = Utc::now() - Duration::hours(2);
+        let mut events = vec![];
+
+        let session = session::ActiveModel {
+            datetime: Set(start),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        for (minutes, app_title, kind) in [
+            (0, "code.exe", event::EventKind::Focus),
+            (10, "firefox.exe", event::EventKind::Focus),
+            (20, "slack.exe", event::EventKind::Focus),
+            (30, "", event::EventKind::Shutdown),
+        ] {
+            let record = event::ActiveModel {
+                path: Set(app_title.to_owned()),
+                title: Set(String::new()),
+                timestamp: Set(start + Duration::minutes(minutes)),
+                app_title: Set(app_title.to_owned()),
+                offset: Set(0),
+                session_id: Set(session.id),
+                kind: Set(kind),
+                in_schedule: Set(true),
+                category: Set(Some("work".to_owned())),
+                project: Set(Some("tracker".to_owned())),
+                ..Default::default()
+            };
+
+            events.push(record.insert(&db).await.unwrap());
+        }
+
+        session::ActiveModel {
+            datetime: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        Recorded { db, start, events }
+    }
+
+    async fn all_events(db: &DatabaseConnection) -> Vec<event::Model> {
+        event::Entity::find()
+            .order_by_asc(event::Column::Timestamp)
+            .order_by_asc(event::Column::Id)
+            .all(db)
+            .await
+            .unwrap()
+    }
+
+    fn timeline(start: DateTimeUtc, events: &[event::Model]) -> Vec<(i64, &str, event::EventKind)> {
+        events
+            .iter()
+            .map(|event| {
+                (
+                    (event.timestamp - start).num_minutes(),
+                    event.app_title.as_str(),
+                    event.kind,
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reassign_clears_null_fields_and_keeps_missing_ones() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+        let id = recorded.events[0].id;
+
+        let reassignment: Reassignment =
+            serde_json::from_str(r#"{"category": null, "project": "other"}"#).unwrap();
+        reassign(db, id, reassignment).await.unwrap();
+
+        let event = find_event(db, id).await.unwrap();
+        assert_eq!(event.app_title, "code.exe");
+        assert_eq!(event.category, None);
+        assert_eq!(event.project.as_deref(), Some("other"));
+
+        let reassignment: Reassignment = serde_json::from_str("{}").unwrap();
+        assert!(matches!(
+            reassign(db, id, reassignment).await,
+            Err(EditError::Invalid(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn split_and_revert_round_trip() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+        let start = recorded.start;
+
+        let edit = split(db, recorded.events[1].id, start + Duration::minutes(15))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            timeline(start, &all_events(db).await),
+            vec![
+                (0, "code.exe", event::EventKind::Focus),
+                (10, "firefox.exe", event::EventKind::Focus),
+                (15, "firefox.exe", event::EventKind::Focus),
+                (20, "slack.exe", event::EventKind::Focus),
+                (30, "", event::EventKind::Shutdown),
+            ]
+        );
+
+        // split outside of interval is rejected
+        assert!(matches!(
+            split(db, recorded.events[1].id, start + Duration::minutes(25)).await,
+            Err(EditError::Invalid(_))
+        ));
+
+        revert(db, edit.id).await.unwrap();
+
+        assert_eq!(all_events(db).await, recorded.events);
+    }
+
+    #[tokio::test]
+    async fn merge_and_revert_round_trip() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+
+        let edit = merge(db, recorded.events[0].id).await.unwrap();
+
+        assert_eq!(
+            timeline(recorded.start, &all_events(db).await),
+            vec![
+                (0, "code.exe", event::EventKind::Focus),
+                (20, "slack.exe", event::EventKind::Focus),
+                (30, "", event::EventKind::Shutdown),
+            ]
+        );
+
+        revert(db, edit.id).await.unwrap();
+
+        assert_eq!(all_events(db).await, recorded.events);
+    }
+
+    #[tokio::test]
+    async fn merge_rejects_marker_events() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+
+        let mut record = event::ActiveModel::from(recorded.events[1].clone());
+        record.kind = Set(event::EventKind::Paused);
+        record.update(db).await.unwrap();
+
+        let before = all_events(db).await;
+
+        // marker is either merged event or the next one
+        for event in &recorded.events[0..3] {
+            assert!(matches!(
+                merge(db, event.id).await,
+                Err(EditError::Invalid(_))
+            ));
+        }
+
+        assert_eq!(all_events(db).await, before);
+    }
+
+    #[tokio::test]
+    async fn delete_range_and_revert_round_trip() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+        let start = recorded.start;
+
+        let edit = delete_range(
+            db,
+            start + Duration::minutes(5),
+            start + Duration::minutes(25),
+        )
+        .await
+        .unwrap();
+
+        // time after range keeps app of interval crossing its end
+        assert_eq!(
+            timeline(start, &all_events(db).await),
+            vec![
+                (0, "code.exe", event::EventKind::Focus),
+                (5, "code.exe", event::EventKind::Deleted),
+                (10, "firefox.exe", event::EventKind::Deleted),
+                (20, "slack.exe", event::EventKind::Deleted),
+                (25, "slack.exe", event::EventKind::Focus),
+                (30, "", event::EventKind::Shutdown),
+            ]
+        );
+
+        revert(db, edit.id).await.unwrap();
+
+        assert_eq!(all_events(db).await, recorded.events);
+    }
+
+    #[tokio::test]
+    async fn revert_is_refused_after_later_edit() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+        let id = recorded.events[1].id;
+
+        let first = split(db, id, recorded.start + Duration::minutes(15))
+            .await
+            .unwrap();
+        let reassignment = Reassignment {
+            app_title: Some("renamed.exe".to_owned()),
+            ..Default::default()
+        };
+        let second = reassign(db, id, reassignment).await.unwrap();
+
+        assert!(matches!(
+            revert(db, first.id).await,
+            Err(EditError::Conflict(_))
+        ));
+
+        revert(db, second.id).await.unwrap();
+        revert(db, first.id).await.unwrap();
+
+        assert!(matches!(
+            revert(db, first.id).await,
+            Err(EditError::Conflict(_))
+        ));
+        assert_eq!(all_events(db).await, recorded.events);
+    }
+}
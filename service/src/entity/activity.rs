@@ -0,0 +1,29 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+
+/// Activity reported by external source, like editor or browser
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "activity")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// Id supplied by source, unique within source
+    pub client_id: String,
+    pub source: String,
+    pub timestamp: DateTimeUtc,
+    pub end: DateTimeUtc,
+    pub app: Option<String>,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub domain: Option<String>,
+    /// Path of url, with query when it is kept
+    pub path: Option<String>,
+    pub project: Option<String>,
+    pub metadata: Option<Json>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,28 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+
+/// Metadata read from executable file, cached by tracker so it is not read on every focus change
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "application")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub path: String,
+    /// Product description, or executable file name when it is missing
+    pub app_title: String,
+    pub publisher: Option<String>,
+    pub version: Option<String>,
+    /// Stable key of application icon, derived from path
+    pub icon_key: String,
+    /// Modification time and size of executable when metadata was read, it is read again once they change
+    pub file_modified: Option<DateTimeUtc>,
+    pub file_size: Option<i64>,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,25 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+
+/// Counted time of closed intervals per local day, app and category, kept up to date by service
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "daily_app_usage")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// Start of local day
+    pub day: DateTimeUtc,
+    /// Description for manual entries
+    pub app_title: String,
+    pub category: Option<String>,
+    /// Whether time was within tracking schedule, None for manual entries and retention aggregates
+    pub in_schedule: Option<bool>,
+    /// Milliseconds
+    pub duration: u32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -15,8 +15,35 @@ pub struct Model {
     pub app_title: String,
     pub offset: u32,
     pub session_id: i32,
+    pub kind: EventKind,
+    pub in_schedule: bool,
+    pub category: Option<String>,
+    /// Set by manual correction
+    pub project: Option<String>,
 }
-// TODO: separate application into another table
+
+/// Only `Focus` events are counted in statistics, others just finish previous app interval
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum EventKind {
+    #[sea_orm(string_value = "focus")]
+    Focus,
+    #[sea_orm(string_value = "excluded")]
+    Excluded,
+    #[sea_orm(string_value = "paused")]
+    Paused,
+    #[sea_orm(string_value = "off_schedule")]
+    OffSchedule,
+    /// Time removed by manual correction
+    #[sea_orm(string_value = "deleted")]
+    Deleted,
+    /// Tracking stopped on service shutdown
+    #[sea_orm(string_value = "shutdown")]
+    Shutdown,
+}
+// TODO: reference `application` by id instead of storing app title and path in every event,
+// metadata of executables is already kept in that table
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
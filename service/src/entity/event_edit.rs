@@ -0,0 +1,38 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+
+/// Audit record of manual correction of events, keeps affected events as they were before and
+/// after the edit, so it can be reverted
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "event_edit")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub action: EditAction,
+    /// Affected events before the edit
+    pub before: Json,
+    /// Affected events after the edit, events removed by it are missing
+    pub after: Json,
+    pub timestamp: DateTimeUtc,
+    pub reverted: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum EditAction {
+    #[sea_orm(string_value = "reassign")]
+    Reassign,
+    #[sea_orm(string_value = "split")]
+    Split,
+    #[sea_orm(string_value = "merge")]
+    Merge,
+    #[sea_orm(string_value = "delete")]
+    Delete,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,53 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "goal")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    pub target: GoalTarget,
+    /// Application title or executable file name for `app` target, category name for `category` target
+    pub target_name: String,
+    pub comparison: GoalComparison,
+    pub minutes: u32,
+    pub period: GoalPeriod,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum GoalTarget {
+    #[sea_orm(string_value = "app")]
+    App,
+    #[sea_orm(string_value = "category")]
+    Category,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum GoalComparison {
+    /// Time limit, reaching target is a breach
+    #[sea_orm(string_value = "at_most")]
+    AtMost,
+    #[sea_orm(string_value = "at_least")]
+    AtLeast,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum GoalPeriod {
+    #[sea_orm(string_value = "day")]
+    Day,
+    #[sea_orm(string_value = "week")]
+    Week,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
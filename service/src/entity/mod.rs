@@ -1,2 +1,11 @@
+pub mod activity;
+pub mod application;
+pub mod daily_app_usage;
 pub mod event;
+pub mod event_edit;
+pub mod goal;
+pub mod manual_entry;
 pub mod session;
+pub mod usage_aggregate;
+pub mod violation;
+pub mod webhook_delivery;
@@ -0,0 +1,39 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+
+/// Focus time of events removed by retention policy, summed per period
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "usage_aggregate")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub period: AggregatePeriod,
+    pub start: DateTimeUtc,
+    pub app_title: String,
+    pub title: String,
+    pub category: Option<String>,
+    pub project: Option<String>,
+    /// Source of activity attributed to aggregated time, `None` for time without one
+    pub source: Option<String>,
+    pub domain: Option<String>,
+    /// Milliseconds
+    pub duration: u32,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[sea_orm(rs_type = "String", db_type = "String(Some(8))")]
+pub enum AggregatePeriod {
+    #[sea_orm(string_value = "hour")]
+    Hour,
+    #[sea_orm(string_value = "day")]
+    Day,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,37 @@
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[sea_orm(table_name = "violation")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// Name of violated forbidden rule
+    pub rule: String,
+    pub path: String,
+    pub title: String,
+    pub app_title: String,
+    pub timestamp: DateTimeUtc,
+    /// Application was closed by strict rule
+    pub enforced: bool,
+    pub session_id: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::session::Entity",
+        from = "Column::SessionId",
+        to = "super::session::Column::Id"
+    )]
+    Session,
+}
+
+impl Related<super::session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Session.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,215 @@
+use chrono::Duration;
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::{
+    prelude::{DateTimeUtc, Json},
+    sea_query::OnConflict,
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+};
+
+use crate::entity::*;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewActivity {
+    /// Client supplied id, activity with already stored id of the same source is skipped
+    pub id: String,
+    pub source: String,
+    pub timestamp: DateTimeUtc,
+    /// Milliseconds, either duration or end is required
+    pub duration: Option<u32>,
+    pub end: Option<DateTimeUtc>,
+    pub app: Option<String>,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    /// Taken from url when missing
+    pub domain: Option<String>,
+    /// Path of url, like "/docs/chrono", stored as given
+    #[serde(default)]
+    pub path: Option<String>,
+    pub project: Option<String>,
+    pub metadata: Option<Json>,
+}
+
+impl NewActivity {
+    /// End given directly or by duration, error when it is missing, ambiguous or before start
+    pub fn end(&self) -> Result<DateTimeUtc, String> {
+        let end = match (self.duration, self.end) {
+            (Some(duration), None) => self
+                .timestamp
+                .checked_add_signed(Duration::milliseconds(duration as i64))
+                .ok_or(format!("Activity {} lasts out of time range", self.id))?,
+            (None, Some(end)) => end,
+            _ => return Err(format!("Activity {} needs either duration or end", self.id)),
+        };
+
+        if end < self.timestamp {
+            return Err(format!("Activity {} ends before its timestamp", self.id));
+        }
+
+        Ok(end)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct IngestResult {
+    pub accepted: u64,
+    pub duplicates: u64,
+}
+
+#[derive(Debug)]
+pub enum IngestError {
+    /// Activity makes no sense, e.g. it ends before it starts, nothing of the batch is stored
+    Invalid(String),
+    Db(DbErr),
+}
+
+impl From<DbErr> for IngestError {
+    fn from(err: DbErr) -> Self {
+        IngestError::Db(err)
+    }
+}
+
+pub async fn ingest(
+    db: &DatabaseConnection,
+    activities: Vec<NewActivity>,
+) -> Result<IngestResult, IngestError> {
+    let txn = db.begin().await?;
+    let mut result = IngestResult::default();
+
+    for activity in activities {
+        let end = activity.end().map_err(IngestError::Invalid)?;
+
+        let domain = activity.domain.or_else(|| {
+            let url = url::Url::parse(activity.url.as_ref()?).ok()?;
+            url.host_str().map(String::from)
+        });
+
+        let record = activity::ActiveModel {
+            client_id: Set(activity.id),
+            source: Set(activity.source),
+            timestamp: Set(activity.timestamp),
+            end: Set(end),
+            app: Set(activity.app),
+            title: Set(activity.title),
+            url: Set(activity.url),
+            domain: Set(domain),
+            path: Set(activity.path),
+            project: Set(activity.project),
+            metadata: Set(activity.metadata),
+            ..Default::default()
+        };
+
+        let inserted = activity::Entity::insert(record)
+            .on_conflict(
+                OnConflict::columns([activity::Column::Source, activity::Column::ClientId])
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(&txn)
+            .await?;
+
+        match inserted {
+            0 => result.duplicates += 1,
+            _ => result.accepted += 1,
+        }
+    }
+
+    txn.commit().await?;
+
+    Ok(result)
+}
+
+/// Focus event of window focused at given moment, if it was tracked
+pub async fn focused_event_at(
+    db: &DatabaseConnection,
+    at: DateTimeUtc,
+) -> Result<Option<event::Model>, DbErr> {
+    let event = event::Entity::find()
+        .filter(event::Column::Timestamp.lte(at))
+        .order_by_desc(event::Column::Timestamp)
+        .order_by_desc(event::Column::Id)
+        .one(db)
+        .await?;
+
+    Ok(event.filter(|event| event.kind == event::EventKind::Focus))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Utc;
+    use sea_orm::Database;
+
+    use crate::database;
+
+    fn new_activity(id: &str, duration: Option<u32>, end: Option<DateTimeUtc>) -> NewActivity {
+        NewActivity {
+            id: id.to_owned(),
+            source: "test".to_owned(),
+            timestamp: Utc::now(),
+            duration,
+            end,
+            app: None,
+            title: None,
+            url: Some("https://docs.rs/chrono".to_owned()),
+            domain: None,
+            path: None,
+            project: None,
+            metadata: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn activities_are_stored_once_with_end() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let by_duration = new_activity("first", Some(60_000), None);
+        let mut by_end = new_activity("second", None, None);
+        by_end.end = Some(by_end.timestamp);
+
+        let result = ingest(&db, vec![by_duration.clone(), by_end, by_duration.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!((result.accepted, result.duplicates), (2, 1));
+
+        let stored = activity::Entity::find()
+            .filter(activity::Column::ClientId.eq("first"))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(stored.end, by_duration.timestamp + Duration::minutes(1));
+        assert_eq!(stored.domain.as_deref(), Some("docs.rs"));
+    }
+
+    #[tokio::test]
+    async fn invalid_activity_rejects_whole_batch() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let valid = new_activity("valid", Some(1000), None);
+        let ends_before = new_activity(
+            "ends before",
+            None,
+            Some(valid.timestamp - Duration::seconds(1)),
+        );
+
+        for invalid in [
+            ends_before,
+            new_activity("no end", None, None),
+            new_activity("both", Some(1000), Some(valid.timestamp)),
+        ] {
+            let result = ingest(&db, vec![valid.clone(), invalid]).await;
+
+            assert!(matches!(result, Err(IngestError::Invalid(_))));
+        }
+
+        assert!(activity::Entity::find().all(&db).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,22 @@
+//! Tracking, storage and statistics of focused windows, used by service binary and by command
+//! line client reading database of stopped service
+
+#[macro_use]
+extern crate rocket;
+
+pub mod admin;
+pub mod config;
+pub mod crash;
+pub mod database;
+pub mod edits;
+pub mod entity;
+pub mod ingest;
+pub mod logging;
+pub mod metrics;
+pub mod notifier;
+pub mod purge;
+pub mod server;
+pub mod service;
+pub mod statistics;
+pub mod usage;
+pub mod webhooks;
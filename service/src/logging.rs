@@ -0,0 +1,315 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::{Mutex, RwLock},
+};
+
+use log::LevelFilter;
+use once_cell::sync::Lazy;
+use rocket::serde::{
+    json::{self, json},
+    Deserialize, Serialize,
+};
+
+use crate::config::{LogFormat, LoggingConfig};
+
+static LEVELS: Lazy<RwLock<Levels>> = Lazy::new(|| {
+    RwLock::new(Levels {
+        level: LevelFilter::Info,
+        modules: BTreeMap::new(),
+    })
+});
+
+/// Levels of log as strings, the same as in config
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct LogLevels {
+    pub level: String,
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+struct Levels {
+    level: LevelFilter,
+    modules: BTreeMap<String, LevelFilter>,
+}
+
+impl Levels {
+    fn parse(levels: &LogLevels) -> Result<Self, String> {
+        let modules = levels
+            .modules
+            .iter()
+            .map(|(module, level)| Ok((module.clone(), parse_level(level)?)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self {
+            level: parse_level(&levels.level)?,
+            modules,
+        })
+    }
+
+    /// Level of the longest matching module, rocket logs are dropped unless configured
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let module = self
+            .modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module.as_str()
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len());
+
+        match module {
+            Some((_, level)) => *level,
+            None if target.starts_with("rocket") || target == "_" => LevelFilter::Off,
+            None => self.level,
+        }
+    }
+
+    fn max(&self) -> LevelFilter {
+        self.modules
+            .values()
+            .copied()
+            .chain([self.level])
+            .max()
+            .unwrap()
+    }
+
+    fn to_strings(&self) -> LogLevels {
+        LogLevels {
+            level: self.level.to_string().to_lowercase(),
+            modules: self
+                .modules
+                .iter()
+                .map(|(module, level)| (module.clone(), level.to_string().to_lowercase()))
+                .collect(),
+        }
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level).map_err(|_| format!("Unknown log level {}", level))
+}
+
+/// Sets up global logger, `console` is used when log file is not configured
+pub fn setup(config: &LoggingConfig, console: impl Into<fern::Output>) -> Result<(), String> {
+    let levels = Levels::parse(&LogLevels {
+        level: config.level.clone(),
+        modules: config.modules.clone(),
+    })?;
+
+    let output = match &config.file {
+        Some(path) => {
+            let file = RotatingFile::open(
+                PathBuf::from(path),
+                config.max_size_mb * 1024 * 1024,
+                config.max_files,
+            )
+            .map_err(|err| format!("Could not open log file {}: {}", path, err))?;
+            let file = Mutex::new(file);
+
+            fern::Output::call(move |record| {
+                let line = format!("{}\n", record.args());
+
+                if let Err(err) = file.lock().unwrap().write_line(line.as_bytes()) {
+                    eprintln!("Could not write log file: {}", err);
+                }
+            })
+        }
+        None => console.into(),
+    };
+
+    let dispatch = match config.format {
+        LogFormat::Text => fern::Dispatch::new().format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        }),
+        LogFormat::Json => fern::Dispatch::new().format(|out, message, record| {
+            let line = json!({
+                "timestamp": chrono::Local::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": message.to_string(),
+            });
+
+            out.finish(format_args!("{}", json::to_string(&line).unwrap()))
+        }),
+    };
+
+    // levels are checked by filter, so they can be changed at runtime
+    dispatch
+        .level(LevelFilter::Trace)
+        .filter(|meta| meta.level() <= LEVELS.read().unwrap().level_for(meta.target()))
+        .chain(output)
+        .apply()
+        .map_err(|err| err.to_string())?;
+
+    apply_levels(levels);
+
+    Ok(())
+}
+
+pub fn levels() -> LogLevels {
+    LEVELS.read().unwrap().to_strings()
+}
+
+/// Replaces levels of running logger, config file is not changed
+pub fn set_levels(levels: &LogLevels) -> Result<LogLevels, String> {
+    let levels = Levels::parse(levels)?;
+    let result = levels.to_strings();
+
+    apply_levels(levels);
+
+    Ok(result)
+}
+
+fn apply_levels(levels: Levels) {
+    log::set_max_level(levels.max());
+
+    *LEVELS.write().unwrap() = levels;
+}
+
+/// Log file which is renamed to `<file>.1` once it grows over max size, older rotated files are
+/// shifted and the oldest one is removed
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: u32,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: u32) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    /// Whole line is written into the same file
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated(self.max_files));
+
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated(index);
+
+                if from.exists() {
+                    fs::rename(from, self.rotated(index + 1))?;
+                }
+            }
+
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+
+    fn rotated(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+
+        path.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_module_wins() {
+        let levels = Levels::parse(&LogLevels {
+            level: "info".to_owned(),
+            modules: BTreeMap::from([
+                ("service".to_owned(), "warn".to_owned()),
+                ("service::webhooks".to_owned(), "trace".to_owned()),
+                ("rocket".to_owned(), "error".to_owned()),
+            ]),
+        })
+        .unwrap();
+
+        assert_eq!(levels.level_for("service::webhooks"), LevelFilter::Trace);
+        assert_eq!(
+            levels.level_for("service::webhooks::retry"),
+            LevelFilter::Trace
+        );
+        assert_eq!(levels.level_for("service::webhooks_old"), LevelFilter::Warn);
+        assert_eq!(levels.level_for("service::server"), LevelFilter::Warn);
+        assert_eq!(levels.level_for("rocket::server"), LevelFilter::Error);
+        assert_eq!(levels.level_for("_"), LevelFilter::Off);
+        assert_eq!(levels.level_for("sqlx::query"), LevelFilter::Info);
+        assert_eq!(levels.max(), LevelFilter::Trace);
+
+        assert!(Levels::parse(&LogLevels {
+            level: "loud".to_owned(),
+            modules: BTreeMap::new(),
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn file_is_rotated_between_lines() {
+        let dir = std::env::temp_dir().join(format!("productivity-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("service.log");
+
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_line(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("service.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("service.log.2")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.join("service.log.3").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,19 +1,92 @@
-use std::backtrace::Backtrace;
-use std::panic;
+use std::path::PathBuf;
+use std::sync::Arc;
 
+use clap::{Parser, Subcommand, ValueEnum};
 use dotenv::dotenv;
+use rocket::{Build, Rocket};
+use sea_orm::{prelude::DateTimeUtc, DatabaseConnection};
 
-use tokio::join;
+use tokio::{join, sync::watch};
+
+use productivity_service::{
+    admin, config, crash, database, logging, notifier, purge, server, service, statistics, usage,
+};
 
 extern crate dotenv;
 
 #[macro_use]
 extern crate rocket;
 
-mod database;
-mod entity;
-mod server;
-mod service;
+#[derive(Parser)]
+#[command(about = "Tracks focused windows and serves statistics")]
+struct Cli {
+    /// Path of sqlite database file
+    #[arg(long, global = true, default_value = database::DEFAULT_DATABASE)]
+    database: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Track focused windows and serve api, default command
+    Run {
+        #[arg(long, value_enum, default_value_t = Mode::All)]
+        mode: Mode,
+    },
+    /// Create missing tables and apply database migrations
+    Migrate,
+    /// Write recorded data into JSON file
+    Export { path: PathBuf },
+    /// Load data written by export into new database
+    Import { path: PathBuf },
+    /// Permanently remove events matching every given criteria
+    Purge {
+        /// RFC 3339 datetime or date
+        #[arg(long, value_parser = statistics::parse_datetime)]
+        from: Option<DateTimeUtc>,
+        /// RFC 3339 datetime or date
+        #[arg(long, value_parser = statistics::parse_datetime)]
+        to: Option<DateTimeUtc>,
+        /// App title or executable name
+        #[arg(long)]
+        app: Option<String>,
+        /// Regex matched against window title
+        #[arg(long)]
+        title: Option<String>,
+        /// Only count matching events
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Compact database file
+    Vacuum,
+    /// Verify database integrity and consistency of sessions and events
+    Check,
+    /// Apply current privacy rules to already recorded window titles
+    Redact,
+    /// Compute daily usage from recorded data again
+    RebuildUsage,
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Mode {
+    /// Tracking and api server
+    All,
+    /// Tracking only, without api server
+    Tracker,
+    /// Read-only api server over existing database, without tracking
+    Server,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print effective configuration as TOML
+    Print,
+}
 
 // TODO: server security, unauthorized access might be dangerous
 // TODO: custom default port
@@ -21,70 +94,413 @@ mod service;
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
     dotenv().ok();
-    setup_logger().unwrap();
 
-    panic::set_hook(Box::new(|info| {
-        let stacktrace = Backtrace::force_capture();
-        println!("Got panic. @info:{}\n@stackTrace:{}", info, stacktrace);
-        std::process::abort();
-    }));
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Run { mode: Mode::All });
 
-    let db = database::setup_database().await;
+    let config = config::load_config();
 
-    info!("Database setup completed");
+    // output of one-off commands is kept apart from log
+    match command {
+        Command::Run { .. } => logging::setup(&config.logging, std::io::stdout()).unwrap(),
+        _ => {
+            let logging = config::LoggingConfig {
+                format: config::LogFormat::Text,
+                file: None,
+                ..config.logging.clone()
+            };
 
-    let mut service = service::Service::new(db.clone()).await;
-    let service_job = service.spin_loop();
+            logging::setup(&logging, std::io::stderr()).unwrap()
+        }
+    }
 
-    let server = rocket::build()
-        .manage(db.clone())
-        .mount("/", routes![server::serve_files])
-        .mount(
-            "/api",
-            routes![
-                server::get_events,
-                server::get_current_session,
-                server::get_current_session_statistics,
-                server::get_current_session_events
-            ],
-        )
-        .launch();
+    crash::install_panic_hook(crash::crash_log_path(&config.logging));
 
-    info!("Joining service and server");
+    info!("Config loaded from {}", config::config_path());
 
-    let (_, server) = join!(service_job, server);
+    if let Command::Config {
+        command: ConfigCommand::Print,
+    } = command
+    {
+        print!("{}", toml::to_string_pretty(&config).unwrap());
+        return Ok(());
+    }
 
-    server.unwrap();
+    if let Command::Migrate = command {
+        let db = database::connect(&cli.database, false).await;
+        let version = database::schema_version(&db).await;
 
-    info!("Service and server finished their work, shutting down...");
+        database::create_db_tables(&db).await;
+        database::migrate(&db).await;
+
+        info!(
+            "Schema migrated from version {} to {}",
+            version,
+            database::schema_version(&db).await
+        );
+
+        db.close().await.unwrap();
+
+        return Ok(());
+    }
+
+    if let Command::Run { mode } = command {
+        run(config, &cli.database, mode).await;
+        return Ok(());
+    }
+
+    // database is checked and exported as is, without creating tables or migrating it
+    let db = match command {
+        Command::Check | Command::Export { .. } => database::connect(&cli.database, true).await,
+        _ => {
+            let db = database::setup_database(&cli.database).await;
+
+            info!("Database setup completed");
+
+            db
+        }
+    };
+
+    let result = run_command(&db, &config, command).await;
 
     db.close().await.unwrap();
 
+    if let Err(err) = result {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+
     Ok(())
 }
 
-fn drop_rocket(meta: &log::Metadata) -> bool {
-    let name = meta.target();
-    if name.starts_with("rocket") || name.eq("_") {
-        return false;
+/// Runs tracking and api server halves of given mode until both of them finish. Failure of one
+/// half is logged and does not stop the other
+async fn run(config: config::Config, database: &str, mode: Mode) {
+    info!("Running in {:?} mode", mode);
+
+    // server-only mode reads database as is, it might be a copy made for analysis
+    let db = match mode {
+        Mode::Server => database::connect(database, true).await,
+        Mode::All | Mode::Tracker => {
+            let db = database::setup_database(database).await;
+
+            info!("Database setup completed");
+
+            usage::rebuild_if_empty(&db).await.unwrap();
+
+            db
+        }
+    };
+
+    let tracking_control = Arc::new(service::tracking::TrackingControl::new());
+
+    let notifier = Arc::new(notifier::Notifier::new(&config.webhooks, db.clone()));
+
+    let tracker_monitor = Arc::new(service::supervisor::TrackerMonitor::new());
+
+    let (shutdown_tx, shutdown) = watch::channel(false);
+
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutdown requested");
+        shutdown_tx.send(true).unwrap();
+    });
+
+    // tracker runs in its own task, so its panic (e.g. on invalid config) leaves server running
+    let tracker = tokio::spawn({
+        let db = db.clone();
+        let config = config.clone();
+        let tracking_control = tracking_control.clone();
+        let notifier = notifier.clone();
+        let tracker_monitor = tracker_monitor.clone();
+        let shutdown = shutdown.clone();
+
+        async move {
+            if mode == Mode::Server {
+                return;
+            }
+
+            let service = service::Service::new(
+                db.clone(),
+                &config,
+                tracking_control,
+                notifier.clone(),
+                Box::new(service::windows_source::WindowsSource::new()),
+                Box::new(service::windows_service::WindowsService {}),
+                tracker_monitor.clone(),
+            )
+            .await;
+
+            let goal_watcher =
+                tokio::spawn(service::goals::GoalWatcher::new(db.clone(), notifier).run());
+
+            let retention_job =
+                tokio::spawn(service::retention::RetentionJob::new(db, &config.retention).run());
+
+            let supervisor = tokio::spawn(crash::supervised(service::supervisor::supervise(
+                service,
+                tracker_monitor,
+                shutdown,
+            )));
+
+            if let Err(err) = supervisor.await {
+                error!("Tracker supervisor failed: {}", err);
+            }
+
+            goal_watcher.abort();
+            retention_job.abort();
+        }
+    });
+
+    let tracker = async {
+        if let Err(err) = tracker.await {
+            error!("Tracker failed: {}", err);
+            tracker_monitor.failed();
+        }
+    };
+
+    let server = async {
+        if mode == Mode::Tracker {
+            return;
+        }
+
+        let server = build_server(
+            db.clone(),
+            tracking_control.clone(),
+            tracker_monitor.clone(),
+            notifier.clone(),
+            config.clone(),
+            mode == Mode::Server,
+        );
+
+        let server = match server.ignite().await {
+            Ok(server) => server,
+            Err(err) => {
+                error!("Server failed: {}", err);
+                return;
+            }
+        };
+
+        let handle = server.shutdown();
+        let mut shutdown = shutdown.clone();
+
+        tokio::spawn(async move {
+            if shutdown.changed().await.is_ok() {
+                handle.notify();
+            }
+        });
+
+        match server.launch().await {
+            Ok(_) => info!("Server finished"),
+            Err(err) => error!("Server failed: {}", err),
+        }
+    };
+
+    info!("Joining service and server");
+
+    join!(tracker, server);
+
+    info!("Service and server finished their work, shutting down...");
+
+    db.close().await.unwrap();
+}
+
+/// Resolves on Ctrl+C, or when console window is closed or system shuts down
+async fn shutdown_signal() {
+    #[cfg(windows)]
+    {
+        let mut close = tokio::signal::windows::ctrl_close().unwrap();
+        let mut shutdown = tokio::signal::windows::ctrl_shutdown().unwrap();
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = close.recv() => {}
+            _ = shutdown.recv() => {}
+        }
     }
-    true
+
+    #[cfg(not(windows))]
+    tokio::signal::ctrl_c().await.unwrap();
 }
 
-pub fn setup_logger() -> Result<(), fern::InitError> {
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "{}[{}][{}] {}",
-                chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
-                record.target(),
-                record.level(),
-                message
-            ))
-        })
-        .level(log::LevelFilter::Info)
-        .filter(drop_rocket)
-        .chain(std::io::stdout())
-        .apply()?;
+/// Api server, read-only one has no routes changing recorded data
+fn build_server(
+    db: DatabaseConnection,
+    tracking_control: Arc<service::tracking::TrackingControl>,
+    tracker_monitor: Arc<service::supervisor::TrackerMonitor>,
+    notifier: Arc<notifier::Notifier>,
+    config: config::Config,
+    read_only: bool,
+) -> Rocket<Build> {
+    let mut api = routes![
+        server::get_events,
+        server::get_current_session,
+        server::get_current_session_statistics,
+        server::get_current_session_events,
+        server::tracking::get_tracking_status,
+        server::tracking::get_tracker_health,
+        server::notifications::get_violations,
+        server::notifications::get_stream,
+        server::goals::get_goals,
+        server::goals::get_goals_progress,
+        server::statistics::get_statistics,
+        server::entries::get_entries,
+        server::entries::get_timer,
+        server::edits::get_edits,
+        server::health::get_health,
+        server::health::get_liveness,
+        server::health::get_readiness,
+        server::logging::get_log_levels,
+        server::logging::set_log_levels
+    ];
+
+    if read_only {
+        info!("Server is read-only, only reading routes are mounted");
+    } else {
+        api.extend(routes![
+            server::tracking::pause_tracking,
+            server::tracking::resume_tracking,
+            server::goals::create_goal,
+            server::goals::delete_goal,
+            server::ingest::post_ingest,
+            server::browser::post_tab_change,
+            server::entries::create_entry,
+            server::entries::delete_entry,
+            server::entries::start_timer,
+            server::entries::stop_timer,
+            server::edits::reassign_event,
+            server::edits::split_event,
+            server::edits::merge_event,
+            server::edits::delete_events,
+            server::edits::revert_edit,
+            server::purge::post_purge
+        ]);
+    }
+
+    // shutdown on Ctrl+C is coordinated with tracking
+    let figment = rocket::Config::figment().merge(("shutdown.ctrlc", false));
+
+    let server = rocket::custom(figment)
+        .manage(db)
+        .manage(tracking_control)
+        .manage(tracker_monitor.metrics())
+        .manage(tracker_monitor)
+        .manage(notifier)
+        .manage(service::privacy::TitlePrivacy::new(&config.privacy).unwrap())
+        .manage(config)
+        .manage(server::health::Uptime::new())
+        .attach(server::logging::RequestLogger)
+        .mount(
+            "/",
+            routes![server::serve_files, server::metrics::get_metrics],
+        )
+        .mount("/api", api);
+
+    if read_only {
+        return server;
+    }
+
+    // WakaTime plugins should use this path as api url
+    server.mount(
+        "/api/wakatime",
+        routes![
+            server::wakatime::post_heartbeat,
+            server::wakatime::post_heartbeats_bulk
+        ],
+    )
+}
+
+/// Runs one-off command against set up database
+async fn run_command(
+    db: &DatabaseConnection,
+    config: &config::Config,
+    command: Command,
+) -> Result<(), String> {
+    match command {
+        Command::Export { path } => {
+            let export = admin::export(db, &path).await?;
+
+            info!(
+                "Exported {} sessions and {} events into {}",
+                export.sessions.len(),
+                export.events.len(),
+                path.display()
+            );
+        }
+        Command::Import { path } => {
+            let export = admin::import(db, &path).await?;
+
+            info!(
+                "Imported {} sessions and {} events from {}",
+                export.sessions.len(),
+                export.events.len(),
+                path.display()
+            );
+        }
+        Command::Purge {
+            from,
+            to,
+            app,
+            title,
+            dry_run,
+        } => {
+            let filter = purge::PurgeFilter {
+                from,
+                to,
+                app,
+                title,
+            };
+            let result = purge::purge(db, &filter, dry_run)
+                .await
+                .map_err(|err| match err {
+                    purge::PurgeError::Invalid(message) => message,
+                    purge::PurgeError::Db(err) => err.to_string(),
+                })?;
+
+            match result.dry_run {
+                true => info!(
+                    "{} events, {} aggregates, {} violations and {} activities would be purged",
+                    result.events, result.aggregates, result.violations, result.activities
+                ),
+                false => info!(
+                    "Purged {} events, {} aggregates, {} violations and {} activities",
+                    result.events, result.aggregates, result.violations, result.activities
+                ),
+            }
+        }
+        Command::Vacuum => {
+            purge::vacuum(db).await.map_err(|err| err.to_string())?;
+
+            info!("Database vacuumed");
+        }
+        Command::Check => {
+            let problems = admin::check(db).await?;
+
+            for problem in &problems {
+                println!("{}", problem);
+            }
+
+            if !problems.is_empty() {
+                return Err(format!("Check found {} problems", problems.len()));
+            }
+
+            info!("Database is consistent");
+        }
+        Command::Redact => {
+            let privacy = service::privacy::TitlePrivacy::new(&config.privacy)?;
+            let changed = service::Service::redact_history(db, &privacy)
+                .await
+                .map_err(|err| err.to_string())?;
+
+            info!("Redacted titles of {} records", changed);
+        }
+        Command::RebuildUsage => {
+            let days = usage::rebuild(db).await.map_err(|err| err.to_string())?;
+
+            info!("Daily usage rebuilt for {} days", days);
+        }
+        Command::Run { .. } | Command::Migrate | Command::Config { .. } => unreachable!(),
+    }
+
     Ok(())
 }
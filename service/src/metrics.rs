@@ -0,0 +1,318 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Upper bounds of histogram buckets, seconds
+static LATENCY_BUCKETS: [f64; 11] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// Focus change dropped without being recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RejectReason {
+    Paused,
+    OffSchedule,
+}
+
+impl RejectReason {
+    fn label(&self) -> &'static str {
+        match self {
+            RejectReason::Paused => "paused",
+            RejectReason::OffSchedule => "off_schedule",
+        }
+    }
+}
+
+/// Step of processing focus change which failed, each one is logged with `error!`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureStage {
+    Foreground,
+    InsertEvent,
+    DailyUsage,
+    InsertViolation,
+    Enforce,
+    /// Event could not be written into journal while database was unavailable, it is lost
+    Journal,
+}
+
+impl FailureStage {
+    fn label(&self) -> &'static str {
+        match self {
+            FailureStage::Foreground => "foreground",
+            FailureStage::InsertEvent => "insert_event",
+            FailureStage::DailyUsage => "daily_usage",
+            FailureStage::InsertViolation => "insert_violation",
+            FailureStage::Enforce => "enforce",
+            FailureStage::Journal => "journal",
+        }
+    }
+}
+
+/// Cumulative histogram of durations with fixed buckets
+struct Histogram {
+    /// Observations per bucket, the last one is `+Inf`
+    buckets: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: (0..=LATENCY_BUCKETS.len())
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let index = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+
+        let mut cumulative = 0;
+
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+
+            let bound = match LATENCY_BUCKETS.get(index) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_owned(),
+            };
+
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative).unwrap();
+        }
+
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+
+        writeln!(out, "{}_sum {}", name, sum).unwrap();
+        writeln!(out, "{}_count {}", name, self.count.load(Ordering::Relaxed)).unwrap();
+    }
+}
+
+/// Counters and histograms of tracker, shared with api and rendered in Prometheus text format
+pub struct Metrics {
+    processed: AtomicU64,
+    journaled: AtomicU64,
+    rejected: Mutex<BTreeMap<RejectReason, u64>>,
+    failed: Mutex<BTreeMap<FailureStage, u64>>,
+    processing_latency: Histogram,
+    db_write_latency: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            processed: AtomicU64::new(0),
+            journaled: AtomicU64::new(0),
+            rejected: Mutex::new(BTreeMap::new()),
+            failed: Mutex::new(BTreeMap::new()),
+            processing_latency: Histogram::new(),
+            db_write_latency: Histogram::new(),
+        }
+    }
+
+    pub fn processed(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn journaled(&self) {
+        self.journaled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rejected(&self, reason: RejectReason) {
+        *self.rejected.lock().unwrap().entry(reason).or_insert(0) += 1;
+    }
+
+    pub fn failed(&self, stage: FailureStage) {
+        *self.failed.lock().unwrap().entry(stage).or_insert(0) += 1;
+    }
+
+    /// Time from receiving focus change to its event being recorded
+    pub fn observe_processing(&self, duration: Duration) {
+        self.processing_latency.observe(duration);
+    }
+
+    pub fn observe_db_write(&self, duration: Duration) {
+        self.db_write_latency.observe(duration);
+    }
+
+    /// Prometheus text exposition, time per category is given in milliseconds
+    pub fn render(&self, time_per_category: &HashMap<String, u64>) -> String {
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "# HELP productivity_events_processed_total Focus changes recorded into database"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE productivity_events_processed_total counter").unwrap();
+        writeln!(
+            out,
+            "productivity_events_processed_total {}",
+            self.processed.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "# HELP productivity_events_journaled_total Events written into journal while database was unavailable"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE productivity_events_journaled_total counter").unwrap();
+        writeln!(
+            out,
+            "productivity_events_journaled_total {}",
+            self.journaled.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "# HELP productivity_events_rejected_total Focus changes dropped while paused or out of schedule"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE productivity_events_rejected_total counter").unwrap();
+        for (reason, count) in self.rejected.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "productivity_events_rejected_total{{reason=\"{}\"}} {}",
+                reason.label(),
+                count
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "# HELP productivity_events_failed_total Failures of processing focus changes"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE productivity_events_failed_total counter").unwrap();
+        for (stage, count) in self.failed.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "productivity_events_failed_total{{stage=\"{}\"}} {}",
+                stage.label(),
+                count
+            )
+            .unwrap();
+        }
+
+        self.processing_latency.render(
+            &mut out,
+            "productivity_event_processing_seconds",
+            "Time of processing focus change until its event is recorded",
+        );
+        self.db_write_latency.render(
+            &mut out,
+            "productivity_db_write_seconds",
+            "Time of inserting record into database",
+        );
+
+        writeln!(
+            out,
+            "# HELP productivity_category_time_today_seconds Time per category within current local day"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE productivity_category_time_today_seconds gauge").unwrap();
+
+        let mut categories: Vec<_> = time_per_category.iter().collect();
+        categories.sort();
+
+        for (category, duration) in categories {
+            let category = match category.is_empty() {
+                true => "uncategorized",
+                false => category,
+            };
+
+            writeln!(
+                out,
+                "productivity_category_time_today_seconds{{category=\"{}\"}} {}",
+                escape_label(category),
+                *duration as f64 / 1000.0
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let metrics = Metrics::new();
+
+        metrics.observe_db_write(Duration::from_micros(500));
+        metrics.observe_db_write(Duration::from_millis(20));
+        metrics.observe_db_write(Duration::from_secs(10));
+
+        let out = metrics.render(&HashMap::new());
+
+        assert!(out.contains("productivity_db_write_seconds_bucket{le=\"0.001\"} 1\n"));
+        assert!(out.contains("productivity_db_write_seconds_bucket{le=\"0.025\"} 2\n"));
+        assert!(out.contains("productivity_db_write_seconds_bucket{le=\"2.5\"} 2\n"));
+        assert!(out.contains("productivity_db_write_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(out.contains("productivity_db_write_seconds_count 3\n"));
+        assert!(out.contains("productivity_db_write_seconds_sum 10.0205\n"));
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        let metrics = Metrics::new();
+
+        metrics.rejected(RejectReason::Paused);
+        metrics.failed(FailureStage::InsertEvent);
+
+        let time_per_category =
+            HashMap::from([("say \"hi\"".to_owned(), 1500), (String::new(), 250)]);
+
+        let out = metrics.render(&time_per_category);
+
+        assert!(out.contains("productivity_events_rejected_total{reason=\"paused\"} 1\n"));
+        assert!(out.contains("productivity_events_failed_total{stage=\"insert_event\"} 1\n"));
+        assert!(out.contains(
+            "productivity_category_time_today_seconds{category=\"say \\\"hi\\\"\"} 1.5\n"
+        ));
+        assert!(out.contains(
+            "productivity_category_time_today_seconds{category=\"uncategorized\"} 0.25\n"
+        ));
+    }
+}
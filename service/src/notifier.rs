@@ -0,0 +1,87 @@
+use rocket::serde::{json, Serialize};
+use sea_orm::{prelude::DateTimeUtc, DatabaseConnection};
+use tokio::sync::broadcast;
+
+use crate::config::WebhookConfig;
+use crate::entity::*;
+use crate::service::{goals::GoalProgress, tracking::TrackingStatus};
+use crate::webhooks::WebhookDispatcher;
+
+static CHANNEL_CAPACITY: usize = 64;
+
+/// Live tracker notifications, sent to API stream subscribers and webhooks
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum Notification {
+    /// Newly recorded foreground window event
+    Focus(event::Model),
+    SessionStarted(session::Model),
+    /// Tracker is shut down, session gets no more events
+    SessionEnded {
+        session: session::Model,
+        finished_at: DateTimeUtc,
+    },
+    /// No keyboard or mouse input since `since` for idle threshold
+    Idle {
+        since: DateTimeUtc,
+    },
+    /// Input is received again after idle time
+    Active {
+        idle_since: DateTimeUtc,
+        active_at: DateTimeUtc,
+    },
+    TrackingPaused(TrackingStatus),
+    TrackingResumed(TrackingStatus),
+    Violation(violation::Model),
+    /// Goal progress reached threshold percentage of its target within current period
+    GoalThreshold {
+        threshold: u32,
+        progress: GoalProgress,
+    },
+}
+
+impl Notification {
+    /// Same as `type` field of serialized notification
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Notification::Focus(_) => "focus",
+            Notification::SessionStarted(_) => "session_started",
+            Notification::SessionEnded { .. } => "session_ended",
+            Notification::Idle { .. } => "idle",
+            Notification::Active { .. } => "active",
+            Notification::TrackingPaused(_) => "tracking_paused",
+            Notification::TrackingResumed(_) => "tracking_resumed",
+            Notification::Violation(_) => "violation",
+            Notification::GoalThreshold { .. } => "goal_threshold",
+        }
+    }
+}
+
+pub struct Notifier {
+    sender: broadcast::Sender<Notification>,
+    webhooks: WebhookDispatcher,
+}
+
+impl Notifier {
+    pub fn new(webhooks: &[WebhookConfig], db: DatabaseConnection) -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+        Self {
+            sender,
+            webhooks: WebhookDispatcher::new(webhooks, db),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.sender.subscribe()
+    }
+
+    pub fn notify(&self, notification: Notification) {
+        let payload = json::to_string(&notification).unwrap();
+
+        self.webhooks.dispatch(notification.event_type(), payload);
+
+        // error only means there are no stream subscribers at the moment
+        let _ = self.sender.send(notification);
+    }
+}
@@ -0,0 +1,729 @@
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+use rocket::serde::{json::serde_json, Deserialize, Serialize};
+use sea_orm::{
+    prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    DbErr, EntityTrait, QueryFilter, QueryOrder, Set, Statement, TransactionTrait,
+};
+
+use crate::edits;
+use crate::entity::*;
+use crate::service::filter::matches_app;
+use crate::statistics;
+use crate::usage;
+
+/// Criteria of purged events, event has to match all given ones
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PurgeFilter {
+    pub from: Option<DateTimeUtc>,
+    pub to: Option<DateTimeUtc>,
+    /// App title or executable name, case-insensitive
+    pub app: Option<String>,
+    /// Regex matched against window title
+    pub title: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PurgeResult {
+    /// Amount of matching events, removed unless it is dry run
+    pub events: u64,
+    /// Amount of matching aggregates made by retention policy
+    pub aggregates: u64,
+    /// Amount of matching forbidden rule violations
+    pub violations: u64,
+    /// Amount of matching activities reported by external sources
+    pub activities: u64,
+    pub dry_run: bool,
+}
+
+#[derive(Debug)]
+pub enum PurgeError {
+    /// Criteria are missing or malformed, nothing is purged
+    Invalid(String),
+    Db(DbErr),
+}
+
+impl From<DbErr> for PurgeError {
+    fn from(err: DbErr) -> Self {
+        PurgeError::Db(err)
+    }
+}
+
+/// Rows matching purge besides events
+struct PurgedRows {
+    aggregates: Vec<i32>,
+    violations: Vec<i32>,
+    activities: Vec<i32>,
+}
+
+/// Permanently removes matching events together with edit history and webhook deliveries
+/// mentioning them, aggregates of already rolled up events, violations and external activities,
+/// and vacuums database so removed data does not stay in the file. Time of removed events stays
+/// deleted instead of being added to previous interval
+pub async fn purge(
+    db: &DatabaseConnection,
+    filter: &PurgeFilter,
+    dry_run: bool,
+) -> Result<PurgeResult, PurgeError> {
+    if filter.from.is_none()
+        && filter.to.is_none()
+        && filter.app.is_none()
+        && filter.title.is_none()
+    {
+        return Err(PurgeError::Invalid(
+            "At least one purge criteria is required".to_owned(),
+        ));
+    }
+
+    let title = match &filter.title {
+        Some(pattern) => Some(Regex::new(pattern).map_err(|err| {
+            PurgeError::Invalid(format!("Invalid title regex {}: {}", pattern, err))
+        })?),
+        None => None,
+    };
+
+    let mut query = event::Entity::find()
+        .order_by_asc(event::Column::Timestamp)
+        .order_by_asc(event::Column::Id);
+
+    if let Some(from) = filter.from {
+        query = query.filter(event::Column::Timestamp.gte(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(event::Column::Timestamp.lt(to));
+    }
+
+    let events = query.all(db).await?;
+
+    let is_match = |event: &event::Model| {
+        filter
+            .app
+            .as_ref()
+            .is_none_or(|app| matches_app(app, &event.app_title, &event.path))
+            && title
+                .as_ref()
+                .is_none_or(|title| title.is_match(&event.title))
+    };
+
+    let matched: Vec<bool> = events.iter().map(is_match).collect();
+
+    let count = matched.iter().filter(|matched| **matched).count() as u64;
+
+    let mut query = usage_aggregate::Entity::find();
+
+    if let Some(from) = filter.from {
+        query = query.filter(usage_aggregate::Column::Start.gte(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(usage_aggregate::Column::Start.lt(to));
+    }
+
+    let aggregates: Vec<usage_aggregate::Model> = query
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|aggregate| {
+            filter
+                .app
+                .as_ref()
+                .is_none_or(|app| matches_app(app, &aggregate.app_title, ""))
+                && title
+                    .as_ref()
+                    .is_none_or(|title| title.is_match(&aggregate.title))
+        })
+        .collect();
+
+    let aggregate_ids: Vec<i32> = aggregates.iter().map(|aggregate| aggregate.id).collect();
+
+    let mut query = violation::Entity::find();
+
+    if let Some(from) = filter.from {
+        query = query.filter(violation::Column::Timestamp.gte(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(violation::Column::Timestamp.lt(to));
+    }
+
+    let violation_ids: Vec<i32> = query
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|violation| {
+            filter
+                .app
+                .as_ref()
+                .is_none_or(|app| matches_app(app, &violation.app_title, &violation.path))
+                && title
+                    .as_ref()
+                    .is_none_or(|title| title.is_match(&violation.title))
+        })
+        .map(|violation| violation.id)
+        .collect();
+
+    // activities overlapping range, title pattern is matched against title and url
+    let mut query = activity::Entity::find();
+
+    if let Some(from) = filter.from {
+        query = query.filter(activity::Column::End.gt(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(activity::Column::Timestamp.lt(to));
+    }
+
+    let activity_ids: Vec<i32> = query
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|activity| {
+            filter.app.as_ref().is_none_or(|app| {
+                activity
+                    .app
+                    .as_ref()
+                    .is_some_and(|activity_app| matches_app(app, activity_app, ""))
+            }) && title.as_ref().is_none_or(|title| {
+                [&activity.title, &activity.url]
+                    .into_iter()
+                    .flatten()
+                    .any(|value| title.is_match(value))
+            })
+        })
+        .map(|activity| activity.id)
+        .collect();
+
+    let result = PurgeResult {
+        events: count,
+        aggregates: aggregate_ids.len() as u64,
+        violations: violation_ids.len() as u64,
+        activities: activity_ids.len() as u64,
+        dry_run,
+    };
+
+    let rows = PurgedRows {
+        aggregates: aggregate_ids,
+        violations: violation_ids,
+        activities: activity_ids,
+    };
+
+    if dry_run
+        || (count == 0
+            && rows.aggregates.is_empty()
+            && rows.violations.is_empty()
+            && rows.activities.is_empty())
+    {
+        return Ok(result);
+    }
+
+    // last event before range, its interval might last within the range
+    let previous = match filter.from {
+        Some(from) => event::Entity::find()
+            .filter(event::Column::Timestamp.lt(from))
+            .order_by_desc(event::Column::Timestamp)
+            .order_by_desc(event::Column::Id)
+            .one(db)
+            .await?
+            .map(|previous| {
+                let split_at = is_match(&previous).then_some(from);
+                (previous, split_at)
+            }),
+        None => None,
+    };
+
+    let changed = changed_range(db, previous.as_ref(), &events, &matched, &aggregates).await?;
+
+    remove(db, previous, &events, &matched, filter.to, rows).await?;
+
+    if let Some((from, to)) = changed {
+        usage::refresh_days(db, from, to).await?;
+    }
+
+    vacuum(db).await?;
+
+    Ok(result)
+}
+
+/// Time whose daily usage is changed by purge, from the first purged moment until the end of the
+/// last purged interval or aggregate
+async fn changed_range(
+    db: &DatabaseConnection,
+    previous: Option<&(event::Model, Option<DateTimeUtc>)>,
+    events: &[event::Model],
+    matched: &[bool],
+    aggregates: &[usage_aggregate::Model],
+) -> Result<Option<(DateTimeUtc, DateTimeUtc)>, DbErr> {
+    let mut ranges = vec![];
+
+    if let Some((previous, Some(from))) = previous {
+        if let Some(end) = edits::interval_end(db, previous).await? {
+            ranges.push((*from, end));
+        }
+    }
+
+    let mut last_matched: HashMap<i32, &event::Model> = HashMap::new();
+
+    for (event, matched) in events.iter().zip(matched) {
+        if *matched {
+            ranges.push((event.timestamp, event.timestamp));
+            last_matched.insert(event.session_id, event);
+        }
+    }
+
+    for event in last_matched.into_values() {
+        if let Some(end) = edits::interval_end(db, event).await? {
+            ranges.push((event.timestamp, end));
+        }
+    }
+
+    for aggregate in aggregates {
+        ranges.push((aggregate.start, statistics::aggregate_end(aggregate)));
+    }
+
+    let from = ranges.iter().map(|(start, _)| *start).min();
+    let to = ranges.iter().map(|(_, end)| *end).max();
+
+    Ok(from.zip(to))
+}
+
+async fn remove(
+    db: &DatabaseConnection,
+    previous: Option<(event::Model, Option<DateTimeUtc>)>,
+    events: &[event::Model],
+    matched: &[bool],
+    to: Option<DateTimeUtc>,
+    rows: PurgedRows,
+) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    let mut purged_ids = HashSet::new();
+    // whether the last seen event of session was purged
+    let mut last_purged: HashMap<i32, bool> = HashMap::new();
+
+    // matching interval crossing range end keeps its time after it
+    let mut last_in_range: HashMap<i32, (&event::Model, bool)> = HashMap::new();
+
+    for (event, matched) in events.iter().zip(matched) {
+        last_in_range.insert(event.session_id, (event, *matched));
+    }
+
+    let mut resumed = vec![];
+
+    if let Some(to) = to {
+        for (event, matched) in last_in_range.into_values() {
+            if matched
+                && edits::interval_end(&txn, event)
+                    .await?
+                    .is_some_and(|end| end > to)
+            {
+                resumed.push(event);
+            }
+        }
+    }
+
+    // matching interval crossing range start is finished at it, instead of taking purged time
+    if let Some((previous, split_at)) = previous {
+        let mut split = false;
+
+        if let Some(from) = split_at {
+            split = edits::interval_end(&txn, &previous)
+                .await?
+                .is_some_and(|end| end > from);
+        }
+
+        if let Some(from) = split_at.filter(|_| split) {
+            let record = event::ActiveModel {
+                path: Set(String::new()),
+                title: Set(String::new()),
+                timestamp: Set(from),
+                app_title: Set(String::new()),
+                offset: Set(previous.offset),
+                session_id: Set(previous.session_id),
+                kind: Set(event::EventKind::Deleted),
+                in_schedule: Set(previous.in_schedule),
+                category: Set(None),
+                project: Set(None),
+                ..Default::default()
+            };
+            record.insert(&txn).await?;
+        }
+
+        last_purged.insert(previous.session_id, split);
+    }
+
+    for (event, matched) in events.iter().zip(matched) {
+        let previous_purged = last_purged.insert(event.session_id, *matched);
+
+        if !matched {
+            continue;
+        }
+
+        purged_ids.insert(event.id);
+
+        match previous_purged {
+            // first purged event becomes blank marker, so previous interval does not take its time
+            Some(false) => {
+                let mut record: event::ActiveModel = event.clone().into();
+                record.path = Set(String::new());
+                record.title = Set(String::new());
+                record.app_title = Set(String::new());
+                record.category = Set(None);
+                record.project = Set(None);
+                record.kind = Set(event::EventKind::Deleted);
+                record.update(&txn).await?;
+            }
+            _ => {
+                event::Entity::delete_by_id(event.id).exec(&txn).await?;
+            }
+        }
+    }
+
+    if let Some(to) = to {
+        for event in resumed {
+            edits::insert_copy(&txn, event, to).await?;
+        }
+    }
+
+    // edit history keeps copies of events, edits mentioning purged events can not be reverted anyway
+    for edit in event_edit::Entity::find().all(&txn).await? {
+        let mentions_purged = [&edit.before, &edit.after].into_iter().any(|snapshot| {
+            serde_json::from_value::<Vec<event::Model>>(snapshot.clone())
+                .unwrap_or_default()
+                .iter()
+                .any(|event| purged_ids.contains(&event.id))
+        });
+
+        if mentions_purged {
+            event_edit::Entity::delete_by_id(edit.id).exec(&txn).await?;
+        }
+    }
+
+    // webhook deliveries keep notifications with whole events and violations
+    let violation_ids: HashSet<i32> = rows.violations.iter().copied().collect();
+
+    for delivery in webhook_delivery::Entity::find().all(&txn).await? {
+        let id = serde_json::from_str::<serde_json::Value>(&delivery.payload)
+            .ok()
+            .and_then(|payload| payload.get("id")?.as_i64())
+            .map(|id| id as i32);
+
+        let purged = match (delivery.event_type.as_str(), id) {
+            ("focus", Some(id)) => purged_ids.contains(&id),
+            ("violation", Some(id)) => violation_ids.contains(&id),
+            _ => false,
+        };
+
+        if purged {
+            webhook_delivery::Entity::delete_by_id(delivery.id)
+                .exec(&txn)
+                .await?;
+        }
+    }
+
+    usage_aggregate::Entity::delete_many()
+        .filter(usage_aggregate::Column::Id.is_in(rows.aggregates))
+        .exec(&txn)
+        .await?;
+
+    violation::Entity::delete_many()
+        .filter(violation::Column::Id.is_in(rows.violations))
+        .exec(&txn)
+        .await?;
+
+    activity::Entity::delete_many()
+        .filter(activity::Column::Id.is_in(rows.activities))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await
+}
+
+pub async fn vacuum(db: &DatabaseConnection) -> Result<(), DbErr> {
+    db.execute(Statement::from_string(
+        db.get_database_backend(),
+        "VACUUM".to_owned(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, Utc};
+    use sea_orm::Database;
+
+    use crate::database;
+    use crate::notifier::Notification;
+
+    struct Recorded {
+        db: DatabaseConnection,
+        start: DateTimeUtc,
+        events: Vec<event::Model>,
+        violations: Vec<violation::Model>,
+    }
+
+    async fn insert_event(
+        db: &DatabaseConnection,
+        session_id: i32,
+        app_title: &str,
+        title: &str,
+        timestamp: DateTimeUtc,
+    ) -> event::Model {
+        event::ActiveModel {
+            path: Set(format!("{}.exe", app_title.to_lowercase())),
+            title: Set(title.to_owned()),
+            timestamp: Set(timestamp),
+            app_title: Set(app_title.to_owned()),
+            offset: Set(0),
+            session_id: Set(session_id),
+            kind: Set(event::EventKind::Focus),
+            in_schedule: Set(true),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap()
+    }
+
+    async fn insert_delivery(db: &DatabaseConnection, notification: &Notification) {
+        let payload = serde_json::to_value(notification).unwrap();
+
+        webhook_delivery::ActiveModel {
+            url: Set("http://localhost/hook".to_owned()),
+            event_type: Set(payload["type"].as_str().unwrap().to_owned()),
+            payload: Set(payload.to_string()),
+            attempt: Set(1),
+            delivered: Set(true),
+            timestamp: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    /// Session still open with Code and Firefox used in turns every 10 minutes within last hour
+    async fn setup() -> Recorded {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let start = Utc::now() - Duration::hours(1);
+
+        let session = session::ActiveModel {
+            datetime: Set(start),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let mut events = vec![];
+
+        for (minutes, app_title, title) in [
+            (0, "Code", "main.rs"),
+            (10, "Firefox", "Bank account"),
+            (20, "Code", "lib.rs"),
+            (30, "Firefox", "Bank statement"),
+        ] {
+            let timestamp = start + Duration::minutes(minutes);
+            events.push(insert_event(&db, session.id, app_title, title, timestamp).await);
+        }
+
+        let mut violations = vec![];
+
+        for (minutes, app_title, title) in [(0, "Code", "main.rs"), (10, "Firefox", "Bank account")]
+        {
+            let violation = violation::ActiveModel {
+                rule: Set("focus".to_owned()),
+                path: Set(format!("{}.exe", app_title.to_lowercase())),
+                title: Set(title.to_owned()),
+                app_title: Set(app_title.to_owned()),
+                timestamp: Set(start + Duration::minutes(minutes)),
+                enforced: Set(false),
+                session_id: Set(session.id),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+
+            violations.push(violation);
+        }
+
+        for (minutes, app, title) in [(1, "Code", "main.rs"), (11, "Firefox", "Bank account")] {
+            activity::ActiveModel {
+                client_id: Set(format!("{}-{}", app, minutes)),
+                source: Set("test".to_owned()),
+                timestamp: Set(start + Duration::minutes(minutes)),
+                end: Set(start + Duration::minutes(minutes + 5)),
+                app: Set(Some(app.to_owned())),
+                title: Set(Some(title.to_owned())),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+
+        insert_delivery(&db, &Notification::Focus(events[0].clone())).await;
+        insert_delivery(&db, &Notification::Focus(events[1].clone())).await;
+        insert_delivery(&db, &Notification::Violation(violations[1].clone())).await;
+
+        Recorded {
+            db,
+            start,
+            events,
+            violations,
+        }
+    }
+
+    fn filter(start: DateTimeUtc) -> PurgeFilter {
+        PurgeFilter {
+            from: Some(start + Duration::minutes(5)),
+            to: Some(start + Duration::minutes(35)),
+            app: Some("firefox".to_owned()),
+            title: Some("^Bank".to_owned()),
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_only_counts() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+
+        let result = purge(db, &filter(recorded.start), true).await.unwrap();
+
+        assert!(result.dry_run);
+        assert_eq!(result.events, 2);
+        assert_eq!(result.violations, 1);
+        assert_eq!(result.activities, 1);
+        assert_eq!(result.aggregates, 0);
+
+        assert_eq!(
+            event::Entity::find().all(db).await.unwrap(),
+            recorded.events
+        );
+        assert_eq!(violation::Entity::find().all(db).await.unwrap().len(), 2);
+        assert_eq!(activity::Entity::find().all(db).await.unwrap().len(), 2);
+        assert_eq!(
+            webhook_delivery::Entity::find()
+                .all(db)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn purge_removes_matching_data() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+        let start = recorded.start;
+
+        let result = purge(db, &filter(start), false).await.unwrap();
+
+        assert!(!result.dry_run);
+        assert_eq!(result.events, 2);
+
+        let events = event::Entity::find()
+            .order_by_asc(event::Column::Timestamp)
+            .all(db)
+            .await
+            .unwrap();
+
+        let timeline: Vec<_> = events
+            .iter()
+            .map(|event| {
+                (
+                    (event.timestamp - start).num_minutes(),
+                    event.kind,
+                    event.app_title.as_str(),
+                )
+            })
+            .collect();
+
+        // time after range end still belongs to the last app
+        assert_eq!(
+            timeline,
+            [
+                (0, event::EventKind::Focus, "Code"),
+                (10, event::EventKind::Deleted, ""),
+                (20, event::EventKind::Focus, "Code"),
+                (30, event::EventKind::Deleted, ""),
+                (35, event::EventKind::Focus, "Firefox"),
+            ]
+        );
+        assert!(events.iter().all(|event| !event.title.starts_with("Bank")
+            || event.timestamp >= start + Duration::minutes(35)));
+
+        let violations = violation::Entity::find().all(db).await.unwrap();
+        assert_eq!(violations, [recorded.violations[0].clone()]);
+
+        let activities = activity::Entity::find().all(db).await.unwrap();
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].app.as_deref(), Some("Code"));
+
+        let deliveries = webhook_delivery::Entity::find().all(db).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert!(deliveries[0]
+            .payload
+            .contains(&format!("\"id\":{}", recorded.events[0].id)));
+    }
+
+    async fn daily_usage(db: &DatabaseConnection) -> Vec<(DateTimeUtc, String, u32)> {
+        let mut rows: Vec<_> = daily_app_usage::Entity::find()
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| (row.day, row.app_title, row.duration))
+            .collect();
+
+        rows.sort();
+        rows
+    }
+
+    #[tokio::test]
+    async fn purge_refreshes_daily_usage_of_changed_days() {
+        let recorded = setup().await;
+        let db = &recorded.db;
+        let start = recorded.start;
+
+        // usage of the day before is out of purge range and stays as it is
+        let session = session::ActiveModel {
+            datetime: Set(start - Duration::days(1)),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+
+        insert_event(db, session.id, "Code", "old.rs", start - Duration::days(1)).await;
+        insert_event(
+            db,
+            session.id,
+            "Firefox",
+            "Bank",
+            start - Duration::hours(23),
+        )
+        .await;
+
+        usage::rebuild(db).await.unwrap();
+        let before = daily_usage(db).await;
+
+        purge(db, &filter(start), false).await.unwrap();
+        let refreshed = daily_usage(db).await;
+
+        usage::rebuild(db).await.unwrap();
+
+        assert_eq!(refreshed, daily_usage(db).await);
+        assert_ne!(refreshed, before);
+    }
+}
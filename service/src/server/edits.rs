@@ -0,0 +1,132 @@
+use rocket::{
+    http::Status,
+    response::status::Custom,
+    serde::{json::Json, Deserialize},
+    State,
+};
+use sea_orm::{prelude::DateTimeUtc, DatabaseConnection, EntityTrait, QueryOrder};
+
+use crate::edits::{self, EditError, Reassignment};
+use crate::entity::*;
+use crate::server::logging::RequestId;
+use crate::server::statistics::QueryDateTime;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SplitRequest {
+    at: DateTimeUtc,
+}
+
+fn edit_error(request: &RequestId, err: EditError) -> Custom<String> {
+    match err {
+        EditError::NotFound(message) => Custom(Status::NotFound, message),
+        EditError::Invalid(message) => Custom(Status::BadRequest, message),
+        EditError::Conflict(message) => Custom(Status::Conflict, message),
+        EditError::Db(err) => {
+            error!(
+                "[request {}] Database error on editing events: {}",
+                request.id, err
+            );
+            Custom(Status::InternalServerError, err.to_string())
+        }
+    }
+}
+
+/// Changes app, category or project of event interval
+#[patch("/events/<id>", data = "<reassignment>")]
+pub async fn reassign_event(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    id: i32,
+    reassignment: Json<Reassignment>,
+) -> Result<Json<event_edit::Model>, Custom<String>> {
+    info!("[request {}] PATCH /events/{} hit", request.id, id);
+
+    let edit = edits::reassign(db, id, reassignment.into_inner())
+        .await
+        .map_err(|err| edit_error(&request, err))?;
+
+    Ok(Json(edit))
+}
+
+#[post("/events/<id>/split", data = "<split>")]
+pub async fn split_event(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    id: i32,
+    split: Json<SplitRequest>,
+) -> Result<Json<event_edit::Model>, Custom<String>> {
+    info!("[request {}] POST /events/{}/split hit", request.id, id);
+
+    let edit = edits::split(db, id, split.at)
+        .await
+        .map_err(|err| edit_error(&request, err))?;
+
+    Ok(Json(edit))
+}
+
+/// Merges event interval with the next one
+#[post("/events/<id>/merge")]
+pub async fn merge_event(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    id: i32,
+) -> Result<Json<event_edit::Model>, Custom<String>> {
+    info!("[request {}] POST /events/{}/merge hit", request.id, id);
+
+    let edit = edits::merge(db, id)
+        .await
+        .map_err(|err| edit_error(&request, err))?;
+
+    Ok(Json(edit))
+}
+
+/// Soft deletes time within range, it can be restored by reverting the edit
+#[delete("/events?<from>&<to>")]
+pub async fn delete_events(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    from: QueryDateTime,
+    to: QueryDateTime,
+) -> Result<Json<event_edit::Model>, Custom<String>> {
+    info!("[request {}] DELETE /events hit", request.id);
+
+    let edit = edits::delete_range(db, from.0, to.0)
+        .await
+        .map_err(|err| edit_error(&request, err))?;
+
+    Ok(Json(edit))
+}
+
+#[get("/edits")]
+pub async fn get_edits(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Json<Vec<event_edit::Model>> {
+    info!("[request {}] GET /edits hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let edits = event_edit::Entity::find()
+        .order_by_desc(event_edit::Column::Id)
+        .all(db)
+        .await
+        .unwrap();
+
+    Json(edits)
+}
+
+#[post("/edits/<id>/revert")]
+pub async fn revert_edit(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    id: i32,
+) -> Result<Json<event_edit::Model>, Custom<String>> {
+    info!("[request {}] POST /edits/{}/revert hit", request.id, id);
+
+    let edit = edits::revert(db, id)
+        .await
+        .map_err(|err| edit_error(&request, err))?;
+
+    Ok(Json(edit))
+}
@@ -0,0 +1,220 @@
+use chrono::Utc;
+use rocket::{
+    response::status::{BadRequest, NotFound},
+    serde::{json::Json, Deserialize},
+    State,
+};
+use sea_orm::{
+    prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder, Set,
+};
+
+use crate::entity::*;
+use crate::server::logging::RequestId;
+use crate::server::statistics::QueryDateTime;
+use crate::usage;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct EntryRequest {
+    start: DateTimeUtc,
+    end: DateTimeUtc,
+    description: String,
+    project: Option<String>,
+    category: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TimerRequest {
+    description: String,
+    project: Option<String>,
+    category: Option<String>,
+}
+
+/// Manual entries overlapping range, all by default
+#[get("/entries?<from>&<to>")]
+pub async fn get_entries(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    from: Option<QueryDateTime>,
+    to: Option<QueryDateTime>,
+) -> Json<Vec<manual_entry::Model>> {
+    info!("[request {}] GET /entries hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let mut query = manual_entry::Entity::find().order_by_asc(manual_entry::Column::Start);
+
+    if let Some(from) = from {
+        query = query.filter(
+            Condition::any()
+                .add(manual_entry::Column::End.gt(from.0))
+                .add(manual_entry::Column::End.is_null()),
+        );
+    }
+
+    if let Some(to) = to {
+        query = query.filter(manual_entry::Column::Start.lt(to.0));
+    }
+
+    let entries = query.all(db).await.unwrap();
+
+    Json(entries)
+}
+
+#[post("/entries", data = "<new_entry>")]
+pub async fn create_entry(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    new_entry: Json<EntryRequest>,
+) -> Result<Json<manual_entry::Model>, BadRequest<String>> {
+    info!("[request {}] POST /entries hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let new_entry = new_entry.into_inner();
+
+    if new_entry.end <= new_entry.start {
+        return Err(BadRequest(Some(
+            "Entry end must be after its start".to_owned(),
+        )));
+    }
+
+    let record = manual_entry::ActiveModel {
+        start: Set(new_entry.start),
+        end: Set(Some(new_entry.end)),
+        description: Set(new_entry.description),
+        project: Set(new_entry.project),
+        category: Set(new_entry.category),
+        ..Default::default()
+    };
+
+    let entry = record.insert(db).await.unwrap();
+
+    usage::refresh_days(db, entry.start, new_entry.end)
+        .await
+        .unwrap();
+
+    info!("[request {}] Created manual entry {:?}", request.id, entry);
+
+    Ok(Json(entry))
+}
+
+#[delete("/entries/<id>")]
+pub async fn delete_entry(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    id: i32,
+) -> Result<(), NotFound<String>> {
+    info!("[request {}] DELETE /entries/{} hit", request.id, id);
+
+    let db = db as &DatabaseConnection;
+
+    let Some(entry) = manual_entry::Entity::find_by_id(id).one(db).await.unwrap() else {
+        return Err(NotFound(format!("Entry {} not found", id)));
+    };
+
+    manual_entry::Entity::delete_by_id(id)
+        .exec(db)
+        .await
+        .unwrap();
+
+    // running timer is not in daily usage yet
+    if let Some(end) = entry.end {
+        usage::refresh_days(db, entry.start, end).await.unwrap();
+    }
+
+    Ok(())
+}
+
+/// Currently running timer, if any
+#[get("/timer")]
+pub async fn get_timer(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Json<Option<manual_entry::Model>> {
+    info!("[request {}] GET /timer hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    Json(running_timer(db).await)
+}
+
+/// Starts timer, running one is stopped first
+#[post("/timer/start", data = "<timer>")]
+pub async fn start_timer(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    timer: Json<TimerRequest>,
+) -> Json<manual_entry::Model> {
+    info!("[request {}] POST /timer/start hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let timer = timer.into_inner();
+    let now = Utc::now();
+
+    if let Some(running) = running_timer(db).await {
+        stop(db, running, now).await;
+    }
+
+    let record = manual_entry::ActiveModel {
+        start: Set(now),
+        end: Set(None),
+        description: Set(timer.description),
+        project: Set(timer.project),
+        category: Set(timer.category),
+        ..Default::default()
+    };
+
+    let entry = record.insert(db).await.unwrap();
+
+    info!("[request {}] Started timer {:?}", request.id, entry);
+
+    Json(entry)
+}
+
+#[post("/timer/stop")]
+pub async fn stop_timer(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Result<Json<manual_entry::Model>, NotFound<String>> {
+    info!("[request {}] POST /timer/stop hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let Some(running) = running_timer(db).await else {
+        return Err(NotFound("No timer is running".to_owned()));
+    };
+
+    let entry = stop(db, running, Utc::now()).await;
+
+    info!("[request {}] Stopped timer {:?}", request.id, entry);
+
+    Ok(Json(entry))
+}
+
+async fn running_timer(db: &DatabaseConnection) -> Option<manual_entry::Model> {
+    manual_entry::Entity::find()
+        .filter(manual_entry::Column::End.is_null())
+        .order_by_desc(manual_entry::Column::Start)
+        .one(db)
+        .await
+        .unwrap()
+}
+
+async fn stop(
+    db: &DatabaseConnection,
+    entry: manual_entry::Model,
+    at: DateTimeUtc,
+) -> manual_entry::Model {
+    let mut record: manual_entry::ActiveModel = entry.into();
+    record.end = Set(Some(at));
+
+    let entry = record.update(db).await.unwrap();
+
+    usage::refresh_days(db, entry.start, at).await.unwrap();
+
+    entry
+}
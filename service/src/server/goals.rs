@@ -0,0 +1,97 @@
+use rocket::{
+    response::status::NotFound,
+    serde::{json::Json, Deserialize},
+    State,
+};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, Set};
+
+use crate::entity::*;
+use crate::server::logging::RequestId;
+use crate::service::goals::{self, GoalProgress};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GoalRequest {
+    name: String,
+    target: goal::GoalTarget,
+    target_name: String,
+    comparison: goal::GoalComparison,
+    minutes: u32,
+    period: goal::GoalPeriod,
+}
+
+#[get("/goals")]
+pub async fn get_goals(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Json<Vec<goal::Model>> {
+    info!("[request {}] GET /goals hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let goals = goal::Entity::find().all(db).await.unwrap();
+
+    Json(goals)
+}
+
+#[post("/goals", data = "<new_goal>")]
+pub async fn create_goal(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    new_goal: Json<GoalRequest>,
+) -> Json<goal::Model> {
+    info!("[request {}] POST /goals hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let new_goal = new_goal.into_inner();
+
+    let record = goal::ActiveModel {
+        name: Set(new_goal.name),
+        target: Set(new_goal.target),
+        target_name: Set(new_goal.target_name),
+        comparison: Set(new_goal.comparison),
+        minutes: Set(new_goal.minutes),
+        period: Set(new_goal.period),
+        ..Default::default()
+    };
+
+    let goal = record.insert(db).await.unwrap();
+
+    info!("[request {}] Created goal {:?}", request.id, goal);
+
+    Json(goal)
+}
+
+#[delete("/goals/<id>")]
+pub async fn delete_goal(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    id: i32,
+) -> Result<(), NotFound<String>> {
+    info!("[request {}] DELETE /goals/{} hit", request.id, id);
+
+    let db = db as &DatabaseConnection;
+
+    let result = goal::Entity::delete_by_id(id).exec(db).await.unwrap();
+
+    if result.rows_affected == 0 {
+        return Err(NotFound(format!("Goal {} not found", id)));
+    }
+
+    Ok(())
+}
+
+#[get("/goals/progress")]
+pub async fn get_goals_progress(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Json<Vec<GoalProgress>> {
+    info!("[request {}] GET /goals/progress hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let progress = goals::goals_progress(db).await.unwrap();
+
+    Json(progress)
+}
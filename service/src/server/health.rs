@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use rocket::{
+    http::Status,
+    response::status::Custom,
+    serde::{json::Json, Serialize},
+    State,
+};
+use sea_orm::{
+    prelude::DateTimeUtc, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr,
+    EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Statement, TransactionTrait,
+};
+
+use crate::database;
+use crate::entity::*;
+use crate::server::logging::RequestId;
+use crate::service::supervisor::{TrackerMonitor, TrackerState};
+
+/// Readiness waits this long for write lock of database
+static WRITE_LOCK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Moment api server was built, uptime is counted from it
+pub struct Uptime {
+    started_at: DateTimeUtc,
+}
+
+impl Default for Uptime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uptime {
+    pub fn new() -> Self {
+        Self {
+            started_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Health {
+    /// "ok" when service is ready, "degraded" otherwise
+    status: &'static str,
+    tracker: TrackerState,
+    tracker_restarts: u32,
+    /// Focus changes sent by source and not recorded yet
+    queue_depth: usize,
+    /// Fields read from database are `None` when it is unavailable, see `problems`
+    last_event_at: Option<DateTimeUtc>,
+    events_last_minute: Option<u64>,
+    events_last_hour: Option<u64>,
+    /// Bytes
+    database_size: Option<i64>,
+    schema_version: Option<usize>,
+    latest_schema_version: usize,
+    started_at: DateTimeUtc,
+    /// Seconds
+    uptime: i64,
+    current_session_id: Option<i32>,
+    /// Reasons of service not being ready and errors of reading diagnostics
+    problems: Vec<String>,
+}
+
+/// Diagnostics of tracker and database, answered even when database is unavailable
+#[get("/health")]
+pub async fn get_health(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    monitor: &State<Arc<TrackerMonitor>>,
+    uptime: &State<Uptime>,
+) -> Json<Health> {
+    info!("[request {}] GET /health hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let tracker = monitor.health();
+    let mut problems = readiness_problems(db, monitor).await;
+    let ready = problems.is_empty();
+    let now = Utc::now();
+
+    let last_event = event::Entity::find()
+        .order_by_desc(event::Column::Timestamp)
+        .one(db)
+        .await;
+
+    let current_session = super::current_session_query().one(db).await;
+
+    let events_last_minute = count_events_since(db, now - Duration::minutes(1)).await;
+    let events_last_hour = count_events_since(db, now - Duration::hours(1)).await;
+    let database_size = database_size(db).await;
+    let schema_version = database::try_schema_version(db).await;
+
+    Json(Health {
+        status: if ready { "ok" } else { "degraded" },
+        tracker: tracker.state,
+        tracker_restarts: tracker.restarts,
+        queue_depth: monitor.queue_depth(),
+        last_event_at: collect(&mut problems, "last event", last_event)
+            .flatten()
+            .map(|event| event.timestamp),
+        events_last_minute: collect(&mut problems, "events of last minute", events_last_minute),
+        events_last_hour: collect(&mut problems, "events of last hour", events_last_hour),
+        database_size: collect(&mut problems, "database size", database_size),
+        schema_version: collect(&mut problems, "schema version", schema_version),
+        latest_schema_version: database::latest_schema_version(),
+        started_at: uptime.started_at,
+        uptime: (now - uptime.started_at).num_seconds(),
+        current_session_id: collect(&mut problems, "current session", current_session)
+            .flatten()
+            .map(|session| session.id),
+        problems,
+    })
+}
+
+/// Value of diagnostic, its error is added to problems
+fn collect<T>(problems: &mut Vec<String>, name: &str, result: Result<T, DbErr>) -> Option<T> {
+    result
+        .inspect_err(|err| problems.push(format!("Could not read {}: {}", name, err)))
+        .ok()
+}
+
+/// Liveness probe, server answering is enough
+#[get("/health/live")]
+pub async fn get_liveness() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe, fails while database is unavailable or locked, or tracker is not running
+#[get("/health/ready")]
+pub async fn get_readiness(
+    db: &State<DatabaseConnection>,
+    monitor: &State<Arc<TrackerMonitor>>,
+) -> Custom<String> {
+    let problems = readiness_problems(db, monitor).await;
+
+    if problems.is_empty() {
+        return Custom(Status::Ok, "ok".to_owned());
+    }
+
+    Custom(Status::ServiceUnavailable, problems.join("\n"))
+}
+
+async fn readiness_problems(db: &DatabaseConnection, monitor: &TrackerMonitor) -> Vec<String> {
+    let mut problems = vec![];
+    let state = monitor.health().state;
+
+    if let Err(err) = db
+        .query_one(Statement::from_string(
+            DbBackend::Sqlite,
+            "SELECT 1".to_owned(),
+        ))
+        .await
+    {
+        problems.push(format!("Database is unavailable: {}", err));
+    } else if state != TrackerState::Disabled {
+        // database opened read-only in server mode is never written
+        if let Err(err) = check_writable(db).await {
+            problems.push(format!("Database is not writable: {}", err));
+        }
+    }
+
+    // disabled tracker is expected in server-only mode
+    match state {
+        TrackerState::Disabled | TrackerState::Running => {}
+        TrackerState::Restarting => problems.push("Tracker is restarting after crash".to_owned()),
+        TrackerState::Finished => problems.push("Tracker is finished".to_owned()),
+    }
+
+    problems
+}
+
+/// Takes write lock within transaction which is rolled back, so lock held by other process is
+/// noticed. Statement writing no rows is enough for sqlite to take the lock
+async fn check_writable(db: &DatabaseConnection) -> Result<(), String> {
+    let check = async {
+        let txn = db.begin().await?;
+
+        txn.execute(Statement::from_string(
+            DbBackend::Sqlite,
+            "UPDATE session SET id = id WHERE 0".to_owned(),
+        ))
+        .await?;
+
+        txn.rollback().await
+    };
+
+    match tokio::time::timeout(WRITE_LOCK_TIMEOUT, check).await {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(_) => Err("write lock is held by another connection".to_owned()),
+    }
+}
+
+async fn count_events_since(db: &DatabaseConnection, since: DateTimeUtc) -> Result<u64, DbErr> {
+    event::Entity::find()
+        .filter(event::Column::Timestamp.gte(since))
+        .count(db)
+        .await
+}
+
+/// Size of database file, counted by sqlite so it is known for any connection
+async fn database_size(db: &DatabaseConnection) -> Result<i64, DbErr> {
+    let row = db
+        .query_one(Statement::from_string(
+            DbBackend::Sqlite,
+            "SELECT page_count * page_size AS size FROM pragma_page_count(), pragma_page_size()"
+                .to_owned(),
+        ))
+        .await?
+        .ok_or(DbErr::RecordNotFound("page count".to_owned()))?;
+
+    row.try_get("", "size")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn write_lock_of_other_connection_is_noticed() {
+        let path =
+            std::env::temp_dir().join(format!("productivity-health-{}.db", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+
+        let db = database::setup_database(&path).await;
+        let other = database::connect(&path, false).await;
+
+        assert_eq!(check_writable(&db).await, Ok(()));
+
+        let txn = other.begin().await.unwrap();
+        txn.execute(Statement::from_string(
+            DbBackend::Sqlite,
+            "DELETE FROM session".to_owned(),
+        ))
+        .await
+        .unwrap();
+
+        assert!(check_writable(&db).await.is_err());
+
+        txn.rollback().await.unwrap();
+
+        assert_eq!(check_writable(&db).await, Ok(()));
+
+        db.close().await.unwrap();
+        other.close().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
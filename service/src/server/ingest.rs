@@ -0,0 +1,38 @@
+use rocket::{http::Status, response::status::Custom, serde::json::Json, State};
+use sea_orm::DatabaseConnection;
+
+use crate::ingest::{self, IngestError, IngestResult, NewActivity};
+use crate::server::logging::RequestId;
+
+/// Stores batch of activities reported by external sources, invalid activity rejects whole batch
+#[post("/ingest", data = "<activities>")]
+pub async fn post_ingest(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    activities: Json<Vec<NewActivity>>,
+) -> Result<Json<IngestResult>, Custom<String>> {
+    info!("[request {}] POST /ingest hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let result = match ingest::ingest(db, activities.into_inner()).await {
+        Ok(result) => result,
+        Err(IngestError::Invalid(message)) => {
+            return Err(Custom(Status::UnprocessableEntity, message));
+        }
+        Err(IngestError::Db(err)) => {
+            error!(
+                "[request {}] Database error on ingesting activities: {}",
+                request.id, err
+            );
+            return Err(Custom(Status::InternalServerError, err.to_string()));
+        }
+    };
+
+    info!(
+        "[request {}] Ingested {} activities, skipped {} duplicates",
+        request.id, result.accepted, result.duplicates
+    );
+
+    Ok(Json(result))
+}
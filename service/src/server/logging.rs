@@ -0,0 +1,106 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{Header, Status},
+    request::{FromRequest, Outcome},
+    response::status::Custom,
+    serde::json::Json,
+    Data, Request, Response,
+};
+
+use crate::logging::{self, LogLevels};
+
+static REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Id of request, taken from `X-Request-Id` header or generated, handlers can include it into
+/// their logs
+#[derive(Clone, Debug)]
+pub struct RequestId {
+    pub id: String,
+    started_at: Instant,
+}
+
+impl RequestId {
+    fn of<'a>(request: &'a Request<'_>) -> &'a RequestId {
+        request.local_cache(|| RequestId {
+            id: match request.headers().get_one(REQUEST_ID_HEADER) {
+                Some(id) => id.to_owned(),
+                None => NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed).to_string(),
+            },
+            started_at: Instant::now(),
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId::of(request).clone())
+    }
+}
+
+/// Logs every request and its response with request id, which is returned in `X-Request-Id`
+pub struct RequestLogger;
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request logger",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = RequestId::of(request);
+
+        info!("[request {}] {} {}", id.id, request.method(), request.uri());
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let id = RequestId::of(request);
+
+        info!(
+            "[request {}] {} in {}ms",
+            id.id,
+            response.status(),
+            id.started_at.elapsed().as_millis()
+        );
+
+        response.set_header(Header::new(REQUEST_ID_HEADER, id.id.clone()));
+    }
+}
+
+#[get("/logging")]
+pub async fn get_log_levels(request: RequestId) -> Json<LogLevels> {
+    info!("[request {}] GET /logging hit", request.id);
+
+    Json(logging::levels())
+}
+
+/// Replaces levels of running logger until restart, config file is not changed
+#[put("/logging", data = "<levels>")]
+pub async fn set_log_levels(
+    request: RequestId,
+    levels: Json<LogLevels>,
+) -> Result<Json<LogLevels>, Custom<String>> {
+    info!("[request {}] PUT /logging hit", request.id);
+
+    let levels =
+        logging::set_levels(&levels).map_err(|err| Custom(Status::UnprocessableEntity, err))?;
+
+    info!(
+        "[request {}] Log levels changed to {:?}",
+        request.id, levels
+    );
+
+    Ok(Json(levels))
+}
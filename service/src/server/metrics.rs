@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use rocket::{
+    http::{ContentType, Status},
+    response::status::Custom,
+    State,
+};
+use sea_orm::DatabaseConnection;
+
+use crate::metrics::Metrics;
+use crate::statistics;
+
+use super::statistics::{range_durations, GroupBy};
+
+/// Tracker counters and today's time per category in Prometheus text format
+#[get("/metrics")]
+pub async fn get_metrics(
+    db: &State<DatabaseConnection>,
+    metrics: &State<Arc<Metrics>>,
+) -> Result<(ContentType, String), Custom<String>> {
+    let now = Utc::now();
+
+    // counted as in statistics, including interval of focused app which is still open
+    let time_per_category = range_durations(
+        db.inner(),
+        statistics::day_start(now),
+        now,
+        GroupBy::Category,
+        None,
+    )
+    .await
+    .map_err(|err| Custom(Status::InternalServerError, err.to_string()))?;
+
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+
+    Ok((content_type, metrics.render(&time_per_category)))
+}
@@ -9,56 +9,86 @@ use std::{
 };
 
 use crate::entity::*;
+use crate::server::logging::RequestId;
+use crate::statistics::{Interval, Segment};
+use chrono::Utc;
 use rocket::{serde::json::Json, State};
 use sea_orm::{
     prelude::DateTimeUtc, sea_query::Query, ColumnTrait, Condition, DatabaseConnection, DbErr,
-    EntityTrait, QueryFilter, Select,
+    EntityTrait, QueryFilter, QueryOrder, Select,
 };
 
 extern crate rocket;
 
+pub mod browser;
+pub mod edits;
+pub mod entries;
+pub mod goals;
+pub mod health;
+pub mod ingest;
+pub mod logging;
+pub mod metrics;
+pub mod notifications;
+pub mod purge;
+pub mod statistics;
+pub mod tracking;
+pub mod wakatime;
+
 // TODO: sanitize dangerous characters
 #[get("/<path>")]
-pub async fn serve_files(path: PathBuf) -> Result<NamedFile, std::io::Error> {
+pub async fn serve_files(request: RequestId, path: PathBuf) -> Result<NamedFile, std::io::Error> {
     let static_folder = std::env::var("STATIC_FOLDER").unwrap();
 
     let file = NamedFile::open(Path::new(&static_folder).join(path)).await;
-    info!("FILE!! {:?}", file);
+    info!("[request {}] FILE!! {:?}", request.id, file);
     file
 }
 
 #[get("/event")]
-pub async fn get_events(db: &State<DatabaseConnection>) -> Json<Vec<event::Model>> {
-    info!("GET /event hit");
+pub async fn get_events(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Json<Vec<event::Model>> {
+    info!("[request {}] GET /event hit", request.id);
 
     let db = db as &DatabaseConnection;
 
     let events = event::Entity::find().all(db).await.unwrap();
 
-    info!("Returning {} events from GET /event", events.len());
+    info!(
+        "[request {}] Returning {} events from GET /event",
+        request.id,
+        events.len()
+    );
 
     Json(events)
 }
 
 #[get("/session/current")]
-pub async fn get_current_session(db: &State<DatabaseConnection>) -> Json<session::Model> {
-    info!("GET /session/current hit");
+pub async fn get_current_session(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Json<session::Model> {
+    info!("[request {}] GET /session/current hit", request.id);
 
     let db = db as &DatabaseConnection;
 
     let session = current_session(&db).await.unwrap();
 
     info!(
-        "Returning {:?} current session from GET /session/current",
-        session
+        "[request {}] Returning {:?} current session from GET /session/current",
+        request.id, session
     );
 
     Json(session)
 }
 
 #[get("/session/current/events")]
-pub async fn get_current_session_events(db: &State<DatabaseConnection>) -> Json<Vec<event::Model>> {
-    info!("GET /service/current/events hit");
+pub async fn get_current_session_events(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Json<Vec<event::Model>> {
+    info!("[request {}] GET /service/current/events hit", request.id);
 
     let db = db as &DatabaseConnection;
 
@@ -66,47 +96,117 @@ pub async fn get_current_session_events(db: &State<DatabaseConnection>) -> Json<
 
     let events = event::Entity::find()
         .filter(event::Column::SessionId.eq(session.id))
+        .order_by_asc(event::Column::Timestamp)
+        .order_by_asc(event::Column::Id)
         .all(db)
         .await
         .unwrap();
 
     info!(
-        "Returning {} events from GET /service/current/events",
+        "[request {}] Returning {} events from GET /service/current/events",
+        request.id,
         events.len()
     );
 
     Json(events)
 }
 
-#[get("/session/current/statistics")]
+/// Time spent within tracking schedule or outside of it, both are counted by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField)]
+pub enum ScheduleFilter {
+    #[field(value = "in")]
+    In,
+    #[field(value = "out")]
+    Out,
+}
+
+impl ScheduleFilter {
+    pub fn matches(self, in_schedule: bool) -> bool {
+        match self {
+            ScheduleFilter::In => in_schedule,
+            ScheduleFilter::Out => !in_schedule,
+        }
+    }
+}
+
+#[get("/session/current/statistics?<schedule>")]
 pub async fn get_current_session_statistics(
+    request: RequestId,
     db: &State<DatabaseConnection>,
+    schedule: Option<ScheduleFilter>,
 ) -> Result<Json<SessionStatisticsResponse>, NotFound<String>> {
-    info!("GET /session/current/statistics hit");
+    info!(
+        "[request {}] GET /session/current/statistics hit",
+        request.id
+    );
 
     let db = db as &DatabaseConnection;
 
-    let session = current_session_query()
+    match current_session_statistics(db, schedule, Utc::now())
+        .await
+        .unwrap()
+    {
+        Some(statistics) => Ok(Json(statistics)),
+        None => Err(NotFound("No events found for current session".to_owned())),
+    }
+}
+
+/// Time per app and visited apps of the latest session, `None` when it has no events yet. Also
+/// used by command line client over database of stopped service
+pub async fn current_session_statistics(
+    db: &DatabaseConnection,
+    schedule: Option<ScheduleFilter>,
+    now: DateTimeUtc,
+) -> Result<Option<SessionStatisticsResponse>, DbErr> {
+    let mut sessions = current_session_query()
         .find_with_related(event::Entity)
         .all(db)
-        .await
-        .unwrap();
+        .await?;
 
-    let (session, events) = &session[0];
+    let Some((session, events)) = sessions.first_mut() else {
+        return Ok(None);
+    };
+
+    // events split by manual correction are inserted later than their neighbours
+    events.sort_by_key(|event| (event.timestamp, event.id));
 
     if events.is_empty() {
-        return Err(NotFound("No events found for current session".to_owned()));
+        return Ok(None);
     };
 
-    let total_time_in_apps = events.last().unwrap().offset - events.first().unwrap().offset;
+    let manual = crate::statistics::load_manual_intervals(db, events[0].timestamp, now).await?;
+
+    // manual entries override automatic tracking for time they cover, as in statistics
+    let intervals: Vec<Interval> =
+        crate::statistics::build_intervals(events.clone(), Some(session.id), now)
+            .into_iter()
+            .filter(|interval| is_counted(&interval.event, schedule))
+            .collect();
+    let segments = crate::statistics::attribute_activities(&intervals, &[]);
+    let segments = crate::statistics::exclude_manual(segments, &manual);
+
+    let time_per_app = calculate_session_apps_time(&segments);
+
+    let mut app_visited_entries = calculate_visited_app_entries(&segments, now);
+
+    let total_time_in_apps: u32 = time_per_app.iter().map(|(_, time)| time).sum();
 
-    let avg_time_in_app = total_time_in_apps / (events.len() as u32);
+    let avg_time_in_app = total_time_in_apps / (app_visited_entries.len().max(1) as u32);
 
-    let time_per_app = calculate_session_apps_time(events);
+    // manual entries have no schedule, so they are shown only in unfiltered timeline
+    if schedule.is_none() {
+        app_visited_entries.extend(manual.into_iter().map(|interval| AppVisitEntry {
+            start: interval.start,
+            finish: interval.entry.end.map(|_| interval.end),
+            duration: interval.duration().num_milliseconds() as u32,
+            app_title: interval.entry.description,
+            manual: true,
+        }));
 
-    let app_visited_entries = calculate_visited_app_entries(events);
+        app_visited_entries.sort_by_key(|entry| entry.start);
+    }
 
-    Ok(Json(SessionStatisticsResponse {
+    Ok(Some(SessionStatisticsResponse {
         session: session.clone(),
         time_per_app,
         avg_time_in_app,
@@ -117,77 +217,58 @@ pub async fn get_current_session_statistics(
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
-struct AppVisitEntry {
-    start: DateTimeUtc,
-    finish: Option<DateTimeUtc>,
-    duration: u32,
-    app_title: String,
+pub struct AppVisitEntry {
+    pub start: DateTimeUtc,
+    pub finish: Option<DateTimeUtc>,
+    pub duration: u32,
+    pub app_title: String,
+    /// Entered by user, app title holds entry description
+    pub manual: bool,
 }
 
-fn calculate_session_apps_time(events: &Vec<event::Model>) -> Vec<(String, u32)> {
-    let mut time_per_app: HashMap<String, u32> = HashMap::new();
-
-    let mut prev_offset = 0;
-    let mut prev_app = String::from("");
-    let mut prev_entry = 0;
-
-    for event in events {
-        if !prev_app.is_empty() {
-            let spent = event.offset - prev_offset;
-            time_per_app.insert(prev_app.clone(), prev_entry + spent);
-        };
+fn is_counted(event: &event::Model, schedule: Option<ScheduleFilter>) -> bool {
+    event.kind == event::EventKind::Focus
+        && schedule.is_none_or(|schedule| schedule.matches(event.in_schedule))
+}
 
-        let entry = *time_per_app.entry(event.app_title.clone()).or_insert(0);
+fn calculate_session_apps_time(segments: &[Segment]) -> Vec<(String, u32)> {
+    let mut time_per_app: HashMap<String, u32> = HashMap::new();
 
-        prev_offset = event.offset;
-        prev_app = event.app_title.clone();
-        prev_entry = entry;
+    for segment in segments {
+        *time_per_app
+            .entry(segment.event.app_title.clone())
+            .or_insert(0) += segment.duration().num_milliseconds() as u32;
     }
 
-    let mut time_per_app: Vec<(String, u32)> = time_per_app
-        .into_iter()
-        .map(|(key, val)| (key, val))
-        .collect();
+    let mut time_per_app: Vec<(String, u32)> = time_per_app.into_iter().collect();
 
     time_per_app.sort_by(|a, b| b.1.cmp(&a.1));
 
     time_per_app
 }
 
-fn calculate_visited_app_entries(events: &Vec<event::Model>) -> Vec<AppVisitEntry> {
-    let mut app_visited_entries: Vec<AppVisitEntry> = vec![];
-    for window in events.windows(2) {
-        // start and finish might be the same app after alt+tab or smth, need to distinguish that somehow
-        let start = window.first().unwrap();
-        let finish = window.last().unwrap();
-
-        app_visited_entries.push(AppVisitEntry {
-            start: start.timestamp,
-            finish: Some(finish.timestamp),
-            duration: (finish.timestamp - start.timestamp).num_milliseconds() as u32,
-            app_title: start.app_title.clone(),
-        });
-    }
-
-    let last_event = events.last().unwrap();
-    app_visited_entries.push(AppVisitEntry {
-        start: last_event.timestamp,
-        finish: None,
-        duration: 0,
-        app_title: last_event.app_title.clone(),
-    });
-
-    app_visited_entries
+/// Interval of the last event lasts until `now`, it has no finish yet
+fn calculate_visited_app_entries(segments: &[Segment], now: DateTimeUtc) -> Vec<AppVisitEntry> {
+    segments
+        .iter()
+        .map(|segment| AppVisitEntry {
+            start: segment.start,
+            finish: (segment.end < now).then_some(segment.end),
+            duration: segment.duration().num_milliseconds() as u32,
+            app_title: segment.event.app_title.clone(),
+            manual: false,
+        })
+        .collect()
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct SessionStatisticsResponse {
-    session: session::Model,
-    time_per_app: Vec<(String, u32)>,
-    app_visited_entries: Vec<AppVisitEntry>,
-    avg_time_in_app: u32,
-    total_time_in_apps: u32,
+    pub session: session::Model,
+    pub time_per_app: Vec<(String, u32)>,
+    pub app_visited_entries: Vec<AppVisitEntry>,
+    pub avg_time_in_app: u32,
+    pub total_time_in_apps: u32,
 }
 
 async fn current_session(db: &DatabaseConnection) -> Result<session::Model, DbErr> {
@@ -207,3 +288,80 @@ fn current_session_query() -> Select<session::Entity> {
         ),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+
+    fn event(id: i32, app_title: &str, timestamp: DateTimeUtc) -> event::Model {
+        event::Model {
+            id,
+            path: format!("{}.exe", app_title),
+            title: String::new(),
+            timestamp,
+            app_title: app_title.to_owned(),
+            offset: 0,
+            session_id: 1,
+            kind: event::EventKind::Focus,
+            in_schedule: true,
+            category: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn session_time_excludes_manual_entries() {
+        let now = Utc::now();
+        let start = now - Duration::minutes(60);
+
+        let events = vec![
+            event(1, "code", start),
+            event(2, "firefox", start + Duration::minutes(30)),
+        ];
+
+        let manual = crate::statistics::build_manual_intervals(
+            vec![manual_entry::Model {
+                id: 1,
+                start: start + Duration::minutes(20),
+                end: Some(start + Duration::minutes(40)),
+                description: "Meeting".to_owned(),
+                project: None,
+                category: None,
+            }],
+            start,
+            now,
+            now,
+        );
+
+        let intervals = crate::statistics::build_intervals(events, Some(1), now);
+        let segments = crate::statistics::attribute_activities(&intervals, &[]);
+        let segments = crate::statistics::exclude_manual(segments, &manual);
+
+        let mut time_per_app = calculate_session_apps_time(&segments);
+        time_per_app.sort();
+
+        assert_eq!(
+            time_per_app,
+            vec![
+                ("code".to_owned(), 20 * 60 * 1000),
+                ("firefox".to_owned(), 20 * 60 * 1000),
+            ]
+        );
+
+        let entries = calculate_visited_app_entries(&segments, now);
+        let timeline: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.app_title.as_str(), entry.start, entry.finish))
+            .collect();
+
+        assert_eq!(
+            timeline,
+            vec![
+                ("code", start, Some(start + Duration::minutes(20))),
+                ("firefox", start + Duration::minutes(40), None),
+            ]
+        );
+    }
+}
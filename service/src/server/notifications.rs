@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use rocket::{
+    response::stream::{Event, EventStream},
+    serde::json::Json,
+    tokio::{select, sync::broadcast::error::RecvError},
+    Shutdown, State,
+};
+use sea_orm::{DatabaseConnection, EntityTrait};
+
+use crate::entity::*;
+use crate::notifier::Notifier;
+use crate::server::logging::RequestId;
+
+#[get("/violation")]
+pub async fn get_violations(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+) -> Json<Vec<violation::Model>> {
+    info!("[request {}] GET /violation hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let violations = violation::Entity::find().all(db).await.unwrap();
+
+    info!(
+        "[request {}] Returning {} violations from GET /violation",
+        request.id,
+        violations.len()
+    );
+
+    Json(violations)
+}
+
+/// Server-sent events stream of live notifications
+#[get("/stream")]
+pub async fn get_stream(
+    request: RequestId,
+    notifier: &State<Arc<Notifier>>,
+    mut end: Shutdown,
+) -> EventStream![] {
+    info!("[request {}] GET /stream hit", request.id);
+
+    let mut receiver = notifier.subscribe();
+
+    EventStream! {
+        loop {
+            let notification = select! {
+                notification = receiver.recv() => match notification {
+                    Ok(notification) => notification,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut end => break,
+            };
+
+            yield Event::json(&notification);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use rocket::{http::Status, response::status::Custom, serde::json::Json, State};
+use sea_orm::DatabaseConnection;
+
+use crate::purge::{self, PurgeError, PurgeFilter, PurgeResult};
+use crate::server::logging::RequestId;
+
+/// Permanently removes matching events, only counts them when `dry_run` is set
+#[post("/purge?<dry_run>", data = "<filter>")]
+pub async fn post_purge(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    filter: Json<PurgeFilter>,
+    dry_run: Option<bool>,
+) -> Result<Json<PurgeResult>, Custom<String>> {
+    info!("[request {}] POST /purge hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let result = match purge::purge(db, &filter, dry_run.unwrap_or(false)).await {
+        Ok(result) => result,
+        Err(PurgeError::Invalid(message)) => {
+            return Err(Custom(Status::BadRequest, message));
+        }
+        Err(PurgeError::Db(err)) => {
+            error!(
+                "[request {}] Database error on purging events: {}",
+                request.id, err
+            );
+            return Err(Custom(Status::InternalServerError, err.to_string()));
+        }
+    };
+
+    info!(
+        "[request {}] Purge of {:?} matched {} events",
+        request.id, filter, result.events
+    );
+
+    Ok(Json(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::{http::ContentType, local::asynchronous::Client};
+    use sea_orm::{ConnectionTrait, Database, DbBackend, Statement};
+
+    use crate::database;
+
+    async fn post(client: &Client, body: &str) -> Status {
+        client
+            .post("/api/purge?dry_run=true")
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch()
+            .await
+            .status()
+    }
+
+    #[tokio::test]
+    async fn only_invalid_criteria_are_bad_request() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let server = rocket::build()
+            .manage(db.clone())
+            .mount("/api", routes![post_purge]);
+        let client = Client::untracked(server).await.unwrap();
+
+        assert_eq!(post(&client, "{}").await, Status::BadRequest);
+        assert_eq!(post(&client, r#"{"title": "("}"#).await, Status::BadRequest);
+        assert_eq!(post(&client, r#"{"app": "code.exe"}"#).await, Status::Ok);
+
+        db.execute(Statement::from_string(
+            DbBackend::Sqlite,
+            "DROP TABLE event".to_owned(),
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(
+            post(&client, r#"{"app": "code.exe"}"#).await,
+            Status::InternalServerError
+        );
+    }
+}
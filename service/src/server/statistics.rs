@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use chrono::prelude::*;
+use rocket::{
+    form::{self, FromFormField, ValueField},
+    serde::{json::Json, Serialize},
+    State,
+};
+use sea_orm::{prelude::DateTimeUtc, DatabaseConnection, DbErr};
+
+use crate::entity::*;
+use crate::server::logging::RequestId;
+use crate::server::ScheduleFilter;
+use crate::statistics::{self, ManualInterval, Segment};
+use crate::usage;
+
+/// RFC 3339 datetime, or date which means start of local day
+#[derive(Clone, Copy, Debug)]
+pub struct QueryDateTime(pub DateTimeUtc);
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for QueryDateTime {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        statistics::parse_datetime(field.value)
+            .map(QueryDateTime)
+            .map_err(|err| form::Error::validation(err).into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum GroupBy {
+    App,
+    Category,
+    Project,
+    /// Source of attributed activity, "os" for time without one, "manual" for manual entries
+    Source,
+    /// Website reported by browser extension
+    Domain,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StatisticsGroup {
+    key: String,
+    /// Milliseconds
+    duration: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StatisticsResponse {
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+    group_by: GroupBy,
+    /// Milliseconds
+    total: u64,
+    groups: Vec<StatisticsGroup>,
+}
+
+/// Time spent within range, today by default, grouped by app by default. Manual entries override
+/// automatic tracking for time they cover. Filtered by schedule only tracked time within tracking
+/// schedule or outside of it is counted
+#[get("/statistics?<from>&<to>&<group_by>&<schedule>")]
+pub async fn get_statistics(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    from: Option<QueryDateTime>,
+    to: Option<QueryDateTime>,
+    group_by: Option<GroupBy>,
+    schedule: Option<ScheduleFilter>,
+) -> Json<StatisticsResponse> {
+    info!("[request {}] GET /statistics hit", request.id);
+
+    let db = db as &DatabaseConnection;
+
+    let to = to.map_or(Utc::now(), |to| to.0);
+    let from = from.map_or(statistics::day_start(to), |from| from.0);
+    let group_by = group_by.unwrap_or(GroupBy::App);
+
+    let durations = range_durations(db, from, to, group_by, schedule)
+        .await
+        .unwrap();
+
+    let mut groups: Vec<StatisticsGroup> = durations
+        .into_iter()
+        .map(|(key, duration)| StatisticsGroup { key, duration })
+        .collect();
+
+    groups.sort_by_key(|group| std::cmp::Reverse(group.duration));
+
+    let total = groups.iter().map(|group| group.duration).sum();
+
+    info!(
+        "[request {}] Returning {} statistics groups from GET /statistics",
+        request.id,
+        groups.len()
+    );
+
+    Json(StatisticsResponse {
+        from,
+        to,
+        group_by,
+        total,
+        groups,
+    })
+}
+
+fn group_key(segment: &Segment, group_by: GroupBy) -> String {
+    let key = match group_by {
+        GroupBy::App => Some(segment.event.app_title.clone()),
+        GroupBy::Category => segment.event.category.clone(),
+        // project set by manual correction wins over reported one
+        GroupBy::Project => segment.event.project.clone().or_else(|| {
+            segment
+                .activity
+                .and_then(|activity| activity.project.clone())
+        }),
+        GroupBy::Domain => segment
+            .activity
+            .and_then(|activity| activity.domain.clone()),
+        GroupBy::Source => Some(
+            segment
+                .activity
+                .map_or("os".to_owned(), |activity| activity.source.clone()),
+        ),
+    };
+
+    key.unwrap_or_default()
+}
+
+/// Manual entries are shown by their description in place of app
+fn manual_group_key(interval: &ManualInterval, group_by: GroupBy) -> String {
+    let key = match group_by {
+        GroupBy::App => Some(interval.entry.description.clone()),
+        GroupBy::Category => interval.entry.category.clone(),
+        GroupBy::Project => interval.entry.project.clone(),
+        GroupBy::Domain => None,
+        GroupBy::Source => Some("manual".to_owned()),
+    };
+
+    key.unwrap_or_default()
+}
+
+fn aggregate_group_key(aggregate: &usage_aggregate::Model, group_by: GroupBy) -> String {
+    let key = match group_by {
+        GroupBy::App => Some(aggregate.app_title.clone()),
+        GroupBy::Category => aggregate.category.clone(),
+        GroupBy::Project => aggregate.project.clone(),
+        GroupBy::Domain => aggregate.domain.clone(),
+        GroupBy::Source => Some(aggregate.source.clone().unwrap_or("os".to_owned())),
+    };
+
+    key.unwrap_or_default()
+}
+
+/// Milliseconds per group within range. Whole days already summed in daily usage table are read
+/// from it when grouping by app or category, the rest is computed from recorded data
+pub async fn range_durations(
+    db: &DatabaseConnection,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+    group_by: GroupBy,
+    schedule: Option<ScheduleFilter>,
+) -> Result<HashMap<String, u64>, DbErr> {
+    let days = match group_by {
+        GroupBy::App | GroupBy::Category => usage::materialized_days(db, from, to).await?,
+        _ => None,
+    };
+
+    let Some((days_start, days_end)) = days else {
+        return compute_durations(db, from, to, group_by, schedule).await;
+    };
+
+    let by_category = group_by == GroupBy::Category;
+    let mut durations = usage::load_groups(db, days_start, days_end, by_category, schedule).await?;
+
+    for (start, end) in [(from, days_start), (days_end, to)] {
+        for (key, duration) in compute_durations(db, start, end, group_by, schedule).await? {
+            *durations.entry(key).or_insert(0) += duration;
+        }
+    }
+
+    Ok(durations)
+}
+
+/// Milliseconds per group within range, computed from recorded data. Manual entries and
+/// retention aggregates have no schedule, so they are not counted when filtered by it, manual
+/// entries still override tracked time they cover
+pub async fn compute_durations(
+    db: &DatabaseConnection,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+    group_by: GroupBy,
+    schedule: Option<ScheduleFilter>,
+) -> Result<HashMap<String, u64>, DbErr> {
+    if from >= to {
+        return Ok(HashMap::new());
+    }
+
+    let mut intervals = statistics::load_intervals(db, from, to).await?;
+    let activities = statistics::load_activities(db, from, to).await?;
+    let manual = statistics::load_manual_intervals(db, from, to).await?;
+    let mut aggregates = statistics::load_aggregates(db, from, to).await?;
+
+    if let Some(schedule) = schedule {
+        intervals.retain(|interval| schedule.matches(interval.event.in_schedule));
+        aggregates.clear();
+    }
+
+    let segments = statistics::attribute_activities(&intervals, &activities);
+    let segments = statistics::exclude_manual(segments, &manual);
+
+    let counted_manual = match schedule {
+        Some(_) => &[][..],
+        None => &manual[..],
+    };
+
+    Ok(sum_groups(&segments, counted_manual, &aggregates, group_by))
+}
+
+fn sum_groups(
+    segments: &[Segment],
+    manual: &[ManualInterval],
+    aggregates: &[usage_aggregate::Model],
+    group_by: GroupBy,
+) -> HashMap<String, u64> {
+    let mut durations: HashMap<String, u64> = HashMap::new();
+
+    for segment in segments {
+        *durations.entry(group_key(segment, group_by)).or_insert(0) +=
+            segment.duration().num_milliseconds() as u64;
+    }
+
+    for interval in manual {
+        *durations
+            .entry(manual_group_key(interval, group_by))
+            .or_insert(0) += interval.duration().num_milliseconds() as u64;
+    }
+
+    for aggregate in aggregates {
+        *durations
+            .entry(aggregate_group_key(aggregate, group_by))
+            .or_insert(0) += aggregate.duration as u64;
+    }
+
+    durations
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use chrono::Duration;
+use rocket::{serde::json::Json, State};
+
+use crate::server::logging::RequestId;
+use crate::service::{
+    supervisor::{TrackerHealth, TrackerMonitor},
+    tracking::{TrackingControl, TrackingStatus},
+};
+
+/// Pauses tracking until resume, or for given amount of minutes
+#[post("/tracking/pause?<minutes>")]
+pub async fn pause_tracking(
+    request: RequestId,
+    control: &State<Arc<TrackingControl>>,
+    minutes: Option<u32>,
+) -> Json<TrackingStatus> {
+    info!("[request {}] POST /tracking/pause hit", request.id);
+
+    let status = control.pause(minutes.map(|minutes| Duration::minutes(minutes as i64)));
+
+    info!(
+        "[request {}] Tracking paused until {:?}",
+        request.id, status.resume_at
+    );
+
+    Json(status)
+}
+
+#[post("/tracking/resume")]
+pub async fn resume_tracking(
+    request: RequestId,
+    control: &State<Arc<TrackingControl>>,
+) -> Json<TrackingStatus> {
+    info!("[request {}] POST /tracking/resume hit", request.id);
+
+    Json(control.resume())
+}
+
+#[get("/tracking/status")]
+pub async fn get_tracking_status(
+    request: RequestId,
+    control: &State<Arc<TrackingControl>>,
+) -> Json<TrackingStatus> {
+    info!("[request {}] GET /tracking/status hit", request.id);
+
+    Json(control.status())
+}
+
+/// State of supervised tracker, amount of its restarts and the latest crash
+#[get("/tracking/health")]
+pub async fn get_tracker_health(
+    request: RequestId,
+    monitor: &State<Arc<TrackerMonitor>>,
+) -> Json<TrackerHealth> {
+    info!("[request {}] GET /tracking/health hit", request.id);
+
+    Json(monitor.health())
+}
@@ -0,0 +1,399 @@
+use chrono::{Duration, TimeZone, Utc};
+use rocket::{
+    http::Status,
+    response::status::Custom,
+    serde::{
+        json::{self, Json},
+        Deserialize, Serialize,
+    },
+    State,
+};
+use sea_orm::DatabaseConnection;
+
+use crate::ingest::{self, IngestError, NewActivity};
+use crate::server::logging::RequestId;
+use crate::service::filter::matches_app;
+
+static SOURCE: &str = "wakatime";
+
+/// Heartbeat is attributed to the editor until the next one, but at most for this long
+static HEARTBEAT_TIMEOUT_MINUTES: i64 = 15;
+
+/// Executables of editors by plugin name from user agent, e.g. `vscode-wakatime/24.2.0`
+static EDITORS: &[(&str, &[&str])] = &[
+    (
+        "vscode",
+        &["Code.exe", "Code - Insiders.exe", "VSCodium.exe"],
+    ),
+    (
+        "intellij",
+        &[
+            "idea64.exe",
+            "pycharm64.exe",
+            "webstorm64.exe",
+            "clion64.exe",
+            "rider64.exe",
+            "goland64.exe",
+            "phpstorm64.exe",
+            "rustrover64.exe",
+        ],
+    ),
+    ("sublime", &["sublime_text.exe"]),
+    ("vim", &["vim.exe", "gvim.exe", "nvim.exe", "nvim-qt.exe"]),
+    ("visualstudio", &["devenv.exe"]),
+    ("notepadpp", &["notepad++.exe"]),
+];
+
+/// Subset of WakaTime heartbeat sent by editor plugins
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Heartbeat {
+    /// File path, domain or app name, depending on type
+    entity: String,
+    #[serde(rename = "type")]
+    entity_type: Option<String>,
+    category: Option<String>,
+    /// Unix timestamp in seconds
+    time: f64,
+    project: Option<String>,
+    branch: Option<String>,
+    language: Option<String>,
+    is_write: Option<bool>,
+    lineno: Option<u32>,
+    cursorpos: Option<u32>,
+    user_agent: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HeartbeatData {
+    id: String,
+    #[serde(flatten)]
+    heartbeat: Heartbeat,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HeartbeatResponse {
+    data: HeartbeatData,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum BulkHeartbeatResult {
+    Stored(HeartbeatResponse),
+    Failed { error: String },
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BulkHeartbeatResponse {
+    /// Response and status code per heartbeat
+    responses: Vec<(BulkHeartbeatResult, u16)>,
+}
+
+#[post("/users/current/heartbeats", data = "<heartbeat>")]
+pub async fn post_heartbeat(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    heartbeat: Json<Heartbeat>,
+) -> Result<Custom<Json<HeartbeatResponse>>, Custom<String>> {
+    info!(
+        "[request {}] POST /users/current/heartbeats hit",
+        request.id
+    );
+
+    let db = db as &DatabaseConnection;
+
+    let response = store_heartbeat(db, heartbeat.into_inner()).await?;
+
+    Ok(Custom(Status::Created, Json(response)))
+}
+
+#[post("/users/current/heartbeats.bulk", data = "<heartbeats>")]
+pub async fn post_heartbeats_bulk(
+    request: RequestId,
+    db: &State<DatabaseConnection>,
+    heartbeats: Json<Vec<Heartbeat>>,
+) -> Custom<Json<BulkHeartbeatResponse>> {
+    info!(
+        "[request {}] POST /users/current/heartbeats.bulk hit",
+        request.id
+    );
+
+    let db = db as &DatabaseConnection;
+
+    let mut responses = vec![];
+
+    for heartbeat in heartbeats.into_inner() {
+        let response = match store_heartbeat(db, heartbeat).await {
+            Ok(response) => (BulkHeartbeatResult::Stored(response), Status::Created.code),
+            Err(Custom(status, error)) => (BulkHeartbeatResult::Failed { error }, status.code),
+        };
+
+        responses.push(response);
+    }
+
+    info!(
+        "[request {}] Stored {} heartbeats",
+        request.id,
+        responses.len()
+    );
+
+    Custom(Status::Created, Json(BulkHeartbeatResponse { responses }))
+}
+
+/// Editor plugin name from user agent, like `vscode` of `vscode-wakatime/24.2.0`
+fn editor_plugin(user_agent: Option<&str>) -> Option<&str> {
+    user_agent?
+        .split_whitespace()
+        .rev()
+        .find_map(|part| part.split('/').next()?.strip_suffix("-wakatime"))
+        .filter(|plugin| !plugin.is_empty())
+}
+
+/// Executables of editor plugin, `None` when plugin is unknown
+fn editor_executables(plugin: &str) -> Option<&'static [&'static str]> {
+    EDITORS
+        .iter()
+        .find(|(name, _)| plugin.eq_ignore_ascii_case(name))
+        .map(|(_, executables)| *executables)
+}
+
+/// App coding activity is attributed to. It is the focused app when it is the editor which sent
+/// heartbeat, otherwise activity is kept for the editor itself, so it is not counted for time of
+/// other focused app. Editor unknown by executable is kept under its plugin name, heartbeat
+/// without plugin stays unattributed
+async fn heartbeat_app(
+    db: &DatabaseConnection,
+    heartbeat: &Heartbeat,
+    timestamp: chrono::DateTime<Utc>,
+) -> Result<String, Custom<String>> {
+    // no app has such title, activity is not attributed to any focus interval
+    let Some(plugin) = editor_plugin(heartbeat.user_agent.as_deref()) else {
+        return Ok(SOURCE.to_owned());
+    };
+
+    let Some(executables) = editor_executables(plugin) else {
+        return Ok(plugin.to_owned());
+    };
+
+    let focused = ingest::focused_event_at(db, timestamp)
+        .await
+        .map_err(|err| Custom(Status::InternalServerError, err.to_string()))?;
+
+    let app = match focused {
+        Some(focused)
+            if executables
+                .iter()
+                .any(|executable| matches_app(executable, &focused.app_title, &focused.path)) =>
+        {
+            focused.app_title
+        }
+        _ => executables[0].to_owned(),
+    };
+
+    Ok(app)
+}
+
+async fn store_heartbeat(
+    db: &DatabaseConnection,
+    heartbeat: Heartbeat,
+) -> Result<HeartbeatResponse, Custom<String>> {
+    let timestamp = Some(heartbeat.time)
+        .filter(|time| time.is_finite())
+        .and_then(|time| Utc.timestamp_millis_opt((time * 1000.0) as i64).single())
+        .ok_or(Custom(
+            Status::BadRequest,
+            format!("Invalid heartbeat time {}", heartbeat.time),
+        ))?;
+
+    // same heartbeat might be resent by plugin, it is deduplicated by id
+    let id = format!("{}:{}", heartbeat.time, heartbeat.entity);
+
+    // links coding activity to the editor which was focused when heartbeat was sent
+    let app = Some(heartbeat_app(db, &heartbeat, timestamp).await?);
+
+    let activity = NewActivity {
+        id: id.clone(),
+        source: SOURCE.to_owned(),
+        timestamp,
+        duration: Some(Duration::minutes(HEARTBEAT_TIMEOUT_MINUTES).num_milliseconds() as u32),
+        end: None,
+        app,
+        title: Some(heartbeat.entity.clone()),
+        url: None,
+        domain: None,
+        path: None,
+        project: heartbeat.project.clone(),
+        metadata: Some(json::json!({
+            "type": heartbeat.entity_type,
+            "category": heartbeat.category,
+            "language": heartbeat.language,
+            "branch": heartbeat.branch,
+            "is_write": heartbeat.is_write,
+        })),
+    };
+
+    ingest::ingest(db, vec![activity])
+        .await
+        .map_err(|err| match err {
+            IngestError::Invalid(message) => Custom(Status::BadRequest, message),
+            IngestError::Db(err) => Custom(Status::InternalServerError, err.to_string()),
+        })?;
+
+    Ok(HeartbeatResponse {
+        data: HeartbeatData { id, heartbeat },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sea_orm::{ActiveModelTrait, Database, EntityTrait, Set};
+
+    use crate::database;
+    use crate::entity::*;
+
+    static VSCODE: &str =
+        "wakatime/v1.73.1 (windows-10.0.19045) go1.20.4 vscode/1.80.0 vscode-wakatime/24.2.0";
+    static EMACS: &str = "wakatime/v1.73.1 (windows-10.0.19045) go1.20.4 emacs-wakatime/1.0.2";
+
+    fn heartbeat(time: f64, user_agent: Option<&str>) -> Heartbeat {
+        Heartbeat {
+            entity: "C:\\projects\\crate\\main.rs".to_owned(),
+            entity_type: Some("file".to_owned()),
+            category: Some("coding".to_owned()),
+            time,
+            project: Some("crate".to_owned()),
+            branch: None,
+            language: Some("Rust".to_owned()),
+            is_write: None,
+            lineno: None,
+            cursorpos: None,
+            user_agent: user_agent.map(str::to_owned),
+        }
+    }
+
+    async fn focus(db: &DatabaseConnection, session_id: i32, app: &str, at: chrono::DateTime<Utc>) {
+        event::ActiveModel {
+            path: Set(app.to_owned()),
+            title: Set(String::new()),
+            timestamp: Set(at),
+            app_title: Set(app.trim_end_matches(".exe").to_owned()),
+            offset: Set(0),
+            session_id: Set(session_id),
+            kind: Set(event::EventKind::Focus),
+            in_schedule: Set(true),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn editor_is_taken_from_plugin() {
+        let executable = |user_agent| {
+            editor_plugin(Some(user_agent))
+                .and_then(editor_executables)
+                .map(|executables| executables[0])
+        };
+
+        assert_eq!(executable(VSCODE), Some("Code.exe"));
+        assert_eq!(
+            executable("wakatime/v1.73.1 IntelliJ/2023.1 intellij-wakatime/14.1.5"),
+            Some("idea64.exe")
+        );
+        assert_eq!(executable("wakatime/v1.73.1 emacs-wakatime/1.0.2"), None);
+        assert_eq!(
+            editor_plugin(Some("wakatime/v1.73.1 emacs-wakatime/1.0.2")),
+            Some("emacs")
+        );
+        assert_eq!(editor_plugin(Some("wakatime/v1.73.1")), None);
+        assert_eq!(editor_plugin(None), None);
+    }
+
+    /// Unknown editors and heartbeats without plugin are not attributed to focused app
+    #[tokio::test]
+    async fn heartbeat_is_attributed_to_focused_editor_only() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let start = Utc::now() - Duration::hours(1);
+
+        let session = session::ActiveModel {
+            datetime: Set(start),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        focus(&db, session.id, "Code.exe", start).await;
+        focus(
+            &db,
+            session.id,
+            "firefox.exe",
+            start + Duration::minutes(10),
+        )
+        .await;
+
+        let seconds = |at: chrono::DateTime<Utc>| at.timestamp_millis() as f64 / 1000.0;
+
+        let in_editor = seconds(start + Duration::minutes(5));
+        let in_browser = seconds(start + Duration::minutes(15));
+        let before_tracking = seconds(start - Duration::minutes(5));
+
+        store_heartbeat(&db, heartbeat(in_editor, Some(VSCODE)))
+            .await
+            .unwrap();
+        store_heartbeat(&db, heartbeat(in_browser, Some(VSCODE)))
+            .await
+            .unwrap();
+        store_heartbeat(&db, heartbeat(in_browser + 1.0, None))
+            .await
+            .unwrap();
+        store_heartbeat(&db, heartbeat(before_tracking, None))
+            .await
+            .unwrap();
+        store_heartbeat(&db, heartbeat(in_browser + 2.0, Some(EMACS)))
+            .await
+            .unwrap();
+
+        let apps: Vec<Option<String>> = activity::Entity::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|activity| activity.app)
+            .collect();
+
+        assert_eq!(
+            apps,
+            [
+                Some("Code".to_owned()),
+                Some("Code.exe".to_owned()),
+                Some(SOURCE.to_owned()),
+                Some(SOURCE.to_owned()),
+                Some("emacs".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_time_is_rejected() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        for time in [f64::NAN, 1e20] {
+            let Err(Custom(status, _)) = store_heartbeat(&db, heartbeat(time, Some(VSCODE))).await
+            else {
+                panic!("heartbeat with time {} is stored", time);
+            };
+
+            assert_eq!(status, Status::BadRequest);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::config::Category;
+
+use super::filter::WindowMatcher;
+
+/// Assigns configured category to tracked windows, first matching category wins
+pub struct Categorizer {
+    categories: Vec<(String, Vec<WindowMatcher>)>,
+}
+
+impl Categorizer {
+    pub fn new(categories: &[Category]) -> Result<Self, String> {
+        let categories = categories
+            .iter()
+            .map(|category| {
+                let matchers = category
+                    .matches
+                    .iter()
+                    .map(WindowMatcher::new)
+                    .collect::<Result<Vec<_>, String>>()?;
+
+                Ok((category.name.clone(), matchers))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self { categories })
+    }
+
+    pub fn categorize(&self, app_title: &str, path: &str, window_title: &str) -> Option<String> {
+        self.categories
+            .iter()
+            .find(|(_, matchers)| {
+                matchers
+                    .iter()
+                    .any(|matcher| matcher.matches(app_title, path, window_title))
+            })
+            .map(|(name, _)| name.clone())
+    }
+}
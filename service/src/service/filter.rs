@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+
+use crate::config::WindowMatch;
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// Compiled `WindowMatch` from config
+pub struct WindowMatcher {
+    app: Option<String>,
+    path: Option<Pattern>,
+    title: Option<Regex>,
+}
+
+impl WindowMatcher {
+    pub fn new(window: &WindowMatch) -> Result<Self, String> {
+        let path = match &window.path {
+            Some(path) => Some(
+                Pattern::new(path).map_err(|err| format!("Invalid path glob {}: {}", path, err))?,
+            ),
+            None => None,
+        };
+
+        let title = match &window.title {
+            Some(title) => Some(
+                Regex::new(title)
+                    .map_err(|err| format!("Invalid title regex {}: {}", title, err))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            app: window.app.clone(),
+            path,
+            title,
+        })
+    }
+
+    pub fn matches(&self, app_title: &str, path: &str, window_title: &str) -> bool {
+        // matcher without any field would match everything, most likely misconfiguration
+        if self.app.is_none() && self.path.is_none() && self.title.is_none() {
+            return false;
+        }
+
+        let app_matches = self
+            .app
+            .as_ref()
+            .is_none_or(|app| matches_app(app, app_title, path));
+
+        let path_matches = self
+            .path
+            .as_ref()
+            .is_none_or(|pattern| pattern.matches_with(path, MATCH_OPTIONS));
+
+        let title_matches = self
+            .title
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(window_title));
+
+        app_matches && path_matches && title_matches
+    }
+}
+
+/// Decides which windows should not be tracked, built from config exclusion rules
+pub struct ExclusionFilter {
+    rules: Vec<WindowMatcher>,
+}
+
+impl ExclusionFilter {
+    pub fn new(rules: &[WindowMatch]) -> Result<Self, String> {
+        let rules = rules
+            .iter()
+            .map(WindowMatcher::new)
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_excluded(&self, app_title: &str, path: &str, window_title: &str) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.matches(app_title, path, window_title))
+    }
+}
+
+/// Compares configured app with application title or its executable file name, case insensitive
+pub fn matches_app(app: &str, app_title: &str, path: &str) -> bool {
+    let file_name = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    app.eq_ignore_ascii_case(app_title) || app.eq_ignore_ascii_case(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(app: Option<&str>, path: Option<&str>, title: Option<&str>) -> WindowMatch {
+        WindowMatch {
+            app: app.map(str::to_owned),
+            path: path.map(str::to_owned),
+            title: title.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn app_matches_title_or_executable_name_ignoring_case() {
+        let filter = ExclusionFilter::new(&[rule(Some("KeePass.exe"), None, None)]).unwrap();
+
+        assert!(filter.is_excluded("keepass.exe", "", "Database"));
+        assert!(filter.is_excluded("KeePass", "keepass.exe", "Database"));
+        assert!(!filter.is_excluded("code.exe", "code.exe", "KeePass.exe"));
+    }
+
+    #[test]
+    fn path_glob_and_title_regex_are_matched() {
+        let filter = ExclusionFilter::new(&[
+            rule(None, Some("C:/Games/*"), None),
+            rule(None, None, Some("(?i)private browsing")),
+        ])
+        .unwrap();
+
+        assert!(filter.is_excluded("game.exe", "c:/games/game.exe", ""));
+        assert!(!filter.is_excluded("code.exe", "C:/Tools/code.exe", ""));
+        assert!(filter.is_excluded("firefox.exe", "", "Bank - Private Browsing"));
+        assert!(!filter.is_excluded("firefox.exe", "", "Bank"));
+    }
+
+    #[test]
+    fn every_field_of_rule_has_to_match() {
+        let filter =
+            ExclusionFilter::new(&[rule(Some("firefox.exe"), None, Some("^Bank"))]).unwrap();
+
+        assert!(filter.is_excluded("firefox.exe", "", "Bank statement"));
+        assert!(!filter.is_excluded("firefox.exe", "", "News"));
+        assert!(!filter.is_excluded("chrome.exe", "", "Bank statement"));
+    }
+
+    #[test]
+    fn empty_rule_matches_nothing() {
+        let filter = ExclusionFilter::new(&[rule(None, None, None)]).unwrap();
+
+        assert!(!filter.is_excluded("code.exe", "code.exe", "main.rs"));
+        assert!(!ExclusionFilter::new(&[])
+            .unwrap()
+            .is_excluded("code.exe", "code.exe", "main.rs"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!(ExclusionFilter::new(&[rule(None, Some("C:/[Games"), None)]).is_err());
+        assert!(ExclusionFilter::new(&[rule(None, None, Some("(unclosed"))]).is_err());
+    }
+}
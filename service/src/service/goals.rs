@@ -0,0 +1,457 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::prelude::*;
+use rocket::serde::Serialize;
+use sea_orm::{prelude::DateTimeUtc, DatabaseConnection, DbErr, EntityTrait};
+
+use crate::entity::*;
+use crate::notifier::{Notification, Notifier};
+use crate::statistics::{self, Interval, ManualInterval};
+
+use super::filter::matches_app;
+
+/// Percentages of goal target which produce notification when reached
+static THRESHOLDS: [u32; 2] = [80, 100];
+static EVALUATION_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GoalProgress {
+    pub goal: goal::Model,
+    pub period_start: DateTimeUtc,
+    /// Milliseconds spent in target within current period
+    pub spent: u32,
+    pub percent: u32,
+    /// Time limit is not exceeded or minimum time is already spent
+    pub met: bool,
+}
+
+impl GoalProgress {
+    fn reached_threshold(&self) -> Option<u32> {
+        THRESHOLDS
+            .iter()
+            .copied()
+            .filter(|threshold| self.percent >= *threshold)
+            .max()
+    }
+}
+
+/// Time within period counted as in statistics: focus intervals without time covered by manual
+/// entries, manual entries under their description, and aggregates of retention policy
+struct PeriodUsage {
+    start: DateTimeUtc,
+    intervals: Vec<Interval>,
+    manual: Vec<ManualInterval>,
+    aggregates: Vec<usage_aggregate::Model>,
+}
+
+impl PeriodUsage {
+    async fn load(
+        db: &DatabaseConnection,
+        start: DateTimeUtc,
+        now: DateTimeUtc,
+    ) -> Result<Self, DbErr> {
+        Ok(Self {
+            start,
+            intervals: statistics::load_intervals(db, start, now).await?,
+            manual: statistics::load_manual_intervals(db, start, now).await?,
+            aggregates: statistics::load_aggregates(db, start, now).await?,
+        })
+    }
+
+    /// Milliseconds spent in target of goal
+    fn spent(&self, goal: &goal::Model) -> i64 {
+        let segments = statistics::attribute_activities(&self.intervals, &[]);
+        let segments = statistics::exclude_manual(segments, &self.manual);
+
+        let tracked: i64 = segments
+            .iter()
+            .filter(|segment| {
+                matches_target(
+                    goal,
+                    &segment.event.app_title,
+                    &segment.event.path,
+                    segment.event.category.as_deref(),
+                )
+            })
+            .map(|segment| segment.duration().num_milliseconds())
+            .sum();
+
+        let manual: i64 = self
+            .manual
+            .iter()
+            .filter(|interval| {
+                matches_target(
+                    goal,
+                    &interval.entry.description,
+                    "",
+                    interval.entry.category.as_deref(),
+                )
+            })
+            .map(|interval| interval.duration().num_milliseconds())
+            .sum();
+
+        let aggregated: i64 = self
+            .aggregates
+            .iter()
+            .filter(|aggregate| {
+                matches_target(
+                    goal,
+                    &aggregate.app_title,
+                    "",
+                    aggregate.category.as_deref(),
+                )
+            })
+            .map(|aggregate| aggregate.duration as i64)
+            .sum();
+
+        tracked + manual + aggregated
+    }
+}
+
+fn matches_target(goal: &goal::Model, app_title: &str, path: &str, category: Option<&str>) -> bool {
+    match goal.target {
+        goal::GoalTarget::App => matches_app(&goal.target_name, app_title, path),
+        goal::GoalTarget::Category => category == Some(goal.target_name.as_str()),
+    }
+}
+
+pub async fn goals_progress(db: &DatabaseConnection) -> Result<Vec<GoalProgress>, DbErr> {
+    let goals = goal::Entity::find().all(db).await?;
+
+    let now = Utc::now();
+
+    let day = PeriodUsage::load(db, statistics::day_start(now), now).await?;
+    let week = PeriodUsage::load(db, statistics::week_start(now), now).await?;
+
+    let progress = goals
+        .into_iter()
+        .map(|goal| {
+            let usage = match goal.period {
+                goal::GoalPeriod::Day => &day,
+                goal::GoalPeriod::Week => &week,
+            };
+
+            calculate_progress(goal, usage)
+        })
+        .collect();
+
+    Ok(progress)
+}
+
+fn calculate_progress(goal: goal::Model, usage: &PeriodUsage) -> GoalProgress {
+    let spent = usage.spent(&goal);
+
+    let target = goal.minutes as i64 * 60 * 1000;
+    let percent = match target {
+        0 => 100,
+        _ => spent * 100 / target,
+    };
+
+    let met = match goal.comparison {
+        goal::GoalComparison::AtMost => spent < target,
+        goal::GoalComparison::AtLeast => spent >= target,
+    };
+
+    GoalProgress {
+        goal,
+        period_start: usage.start,
+        spent: spent as u32,
+        percent: percent as u32,
+        met,
+    }
+}
+
+/// Periodically evaluates goals and notifies when thresholds are crossed
+pub struct GoalWatcher {
+    db: DatabaseConnection,
+    notifier: Arc<Notifier>,
+    /// Highest notified threshold per goal within period
+    notified: HashMap<i32, (DateTimeUtc, u32)>,
+}
+
+impl GoalWatcher {
+    pub fn new(db: DatabaseConnection, notifier: Arc<Notifier>) -> Self {
+        Self {
+            db,
+            notifier,
+            notified: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        let mut interval = tokio::time::interval(EVALUATION_INTERVAL);
+
+        // thresholds crossed before startup are not notified again
+        let mut initial = true;
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.evaluate(initial).await {
+                error!("Error on evaluating goals: {}", err);
+                continue;
+            }
+
+            initial = false;
+        }
+    }
+
+    async fn evaluate(&mut self, silent: bool) -> Result<(), DbErr> {
+        for progress in goals_progress(&self.db).await? {
+            let Some(threshold) = progress.reached_threshold() else {
+                continue;
+            };
+
+            let previous = self
+                .notified
+                .get(&progress.goal.id)
+                .filter(|(period_start, _)| *period_start == progress.period_start)
+                .map(|(_, threshold)| *threshold);
+
+            if previous.is_some_and(|previous| previous >= threshold) {
+                continue;
+            }
+
+            self.notified
+                .insert(progress.goal.id, (progress.period_start, threshold));
+
+            if silent {
+                continue;
+            }
+
+            info!(
+                "Goal {} reached {}% of its target",
+                progress.goal.name, threshold
+            );
+
+            self.notifier.notify(Notification::GoalThreshold {
+                threshold,
+                progress,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn goal(
+        target: goal::GoalTarget,
+        target_name: &str,
+        comparison: goal::GoalComparison,
+        minutes: u32,
+        period: goal::GoalPeriod,
+    ) -> goal::Model {
+        goal::Model {
+            id: 1,
+            name: "goal".to_owned(),
+            target,
+            target_name: target_name.to_owned(),
+            comparison,
+            minutes,
+            period,
+        }
+    }
+
+    fn interval(
+        app_title: &str,
+        category: Option<&str>,
+        start: DateTimeUtc,
+        minutes: i64,
+    ) -> Interval {
+        Interval {
+            start,
+            end: start + chrono::Duration::minutes(minutes),
+            event: event::Model {
+                id: 0,
+                path: format!("{}.exe", app_title.to_lowercase()),
+                title: String::new(),
+                timestamp: start,
+                app_title: app_title.to_owned(),
+                offset: 0,
+                session_id: 1,
+                kind: event::EventKind::Focus,
+                in_schedule: true,
+                category: category.map(str::to_owned),
+                project: None,
+            },
+        }
+    }
+
+    /// Noon of current day, so intervals started at day start have ended
+    fn noon() -> DateTimeUtc {
+        statistics::day_start(Utc::now()) + chrono::Duration::hours(12)
+    }
+
+    fn usage(
+        start: DateTimeUtc,
+        intervals: Vec<Interval>,
+        manual: Vec<manual_entry::Model>,
+        aggregates: Vec<usage_aggregate::Model>,
+    ) -> PeriodUsage {
+        let now = noon();
+
+        PeriodUsage {
+            start,
+            intervals: statistics::clip_intervals(intervals, start, now),
+            manual: statistics::build_manual_intervals(manual, start, now, now),
+            aggregates,
+        }
+    }
+
+    #[test]
+    fn progress_counts_time_within_period() {
+        let now = noon();
+        let day_start = statistics::day_start(now);
+        let week_start = statistics::week_start(now);
+
+        // crossing start of day, only its part within current day is counted
+        let before_today = interval(
+            "Slack",
+            Some("chat"),
+            day_start - chrono::Duration::minutes(30),
+            40,
+        );
+        let mut paused = interval("Slack", Some("chat"), day_start, 60);
+        paused.event.kind = event::EventKind::Paused;
+
+        let intervals = vec![
+            interval(
+                "Slack",
+                Some("chat"),
+                week_start - chrono::Duration::hours(2),
+                60,
+            ),
+            before_today,
+            paused,
+            interval("Code", Some("work"), day_start, 20),
+        ];
+
+        let daily = calculate_progress(
+            goal(
+                goal::GoalTarget::App,
+                "slack",
+                goal::GoalComparison::AtMost,
+                20,
+                goal::GoalPeriod::Day,
+            ),
+            &usage(day_start, intervals.clone(), vec![], vec![]),
+        );
+
+        assert_eq!(daily.period_start, day_start);
+        assert_eq!(daily.spent, 10 * 60 * 1000);
+        assert_eq!(daily.percent, 50);
+        assert!(daily.met);
+        assert_eq!(daily.reached_threshold(), None);
+
+        let weekly = calculate_progress(
+            goal(
+                goal::GoalTarget::Category,
+                "chat",
+                goal::GoalComparison::AtLeast,
+                40,
+                goal::GoalPeriod::Week,
+            ),
+            &usage(week_start, intervals.clone(), vec![], vec![]),
+        );
+
+        let spent_in_week = intervals[1].end - intervals[1].start.max(week_start);
+
+        assert_eq!(weekly.period_start, week_start);
+        assert_eq!(weekly.spent, spent_in_week.num_milliseconds() as u32);
+        assert_eq!(weekly.met, spent_in_week >= chrono::Duration::minutes(40));
+    }
+
+    #[test]
+    fn progress_counts_manual_entries_and_aggregates_like_statistics() {
+        let day_start = statistics::day_start(noon());
+
+        let intervals = vec![interval("Slack", Some("chat"), day_start, 60)];
+
+        // manual entry overrides tracked time it covers
+        let manual = vec![manual_entry::Model {
+            id: 1,
+            start: day_start + chrono::Duration::minutes(10),
+            end: Some(day_start + chrono::Duration::minutes(25)),
+            description: "meeting".to_owned(),
+            project: None,
+            category: Some("chat".to_owned()),
+        }];
+
+        let aggregates = vec![usage_aggregate::Model {
+            id: 1,
+            period: usage_aggregate::AggregatePeriod::Hour,
+            start: day_start + chrono::Duration::hours(2),
+            app_title: "Slack".to_owned(),
+            title: String::new(),
+            category: Some("chat".to_owned()),
+            project: None,
+            source: None,
+            domain: None,
+            duration: 5 * 60 * 1000,
+        }];
+
+        let usage = usage(day_start, intervals, manual, aggregates);
+
+        let spent = |target, target_name| {
+            let goal = goal(
+                target,
+                target_name,
+                goal::GoalComparison::AtMost,
+                60,
+                goal::GoalPeriod::Day,
+            );
+
+            calculate_progress(goal, &usage).spent / (60 * 1000)
+        };
+
+        assert_eq!(spent(goal::GoalTarget::App, "slack"), 45 + 5);
+        assert_eq!(spent(goal::GoalTarget::App, "meeting"), 15);
+        assert_eq!(spent(goal::GoalTarget::Category, "chat"), 45 + 15 + 5);
+    }
+
+    #[test]
+    fn thresholds_follow_percent() {
+        let day_start = statistics::day_start(noon());
+        let usage = usage(
+            day_start,
+            vec![interval("Code", Some("work"), day_start, 9)],
+            vec![],
+            vec![],
+        );
+
+        let limit = calculate_progress(
+            goal(
+                goal::GoalTarget::Category,
+                "work",
+                goal::GoalComparison::AtMost,
+                10,
+                goal::GoalPeriod::Day,
+            ),
+            &usage,
+        );
+
+        assert_eq!(limit.percent, 90);
+        assert!(limit.met);
+        assert_eq!(limit.reached_threshold(), Some(80));
+
+        let minimum = calculate_progress(
+            goal(
+                goal::GoalTarget::App,
+                "code.exe",
+                goal::GoalComparison::AtLeast,
+                5,
+                goal::GoalPeriod::Day,
+            ),
+            &usage,
+        );
+
+        assert_eq!(minimum.percent, 180);
+        assert!(minimum.met);
+        assert_eq!(minimum.reached_threshold(), Some(100));
+    }
+}
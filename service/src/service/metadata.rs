@@ -0,0 +1,311 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::prelude::*;
+use sea_orm::{
+    prelude::DateTimeUtc, ActiveModelTrait, DatabaseConnection, DbErr, EntityTrait, NotSet,
+};
+use sha2::{Digest, Sha256};
+
+use crate::entity::*;
+
+use super::source::AppMetadata;
+
+/// Modification time and size of executable, metadata is read again once they change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<DateTimeUtc>,
+    size: Option<i64>,
+}
+
+impl FileStamp {
+    /// `None` when file is not accessible, cached metadata is kept then
+    fn of(path: &str) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+
+        Some(Self {
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            size: Some(metadata.len() as i64),
+        })
+    }
+
+    fn of_model(model: &application::Model) -> Self {
+        Self {
+            modified: model.file_modified,
+            size: model.file_size,
+        }
+    }
+}
+
+/// Cached metadata, the one which could not be saved is saved again on the next lookup
+#[derive(Clone, Debug)]
+struct Entry {
+    model: application::Model,
+    saved: bool,
+}
+
+/// Application metadata by executable path, persisted in application table so it survives restarts
+pub struct MetadataCache {
+    db: DatabaseConnection,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl MetadataCache {
+    pub async fn load(db: DatabaseConnection) -> Result<Self, DbErr> {
+        let entries = application::Entity::find()
+            .all(&db)
+            .await?
+            .into_iter()
+            .map(|model| (normalize_path(&model.path), Entry { model, saved: true }))
+            .collect();
+
+        Ok(Self {
+            db,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Cached metadata of executable, it is read with `read` when missing or file changed
+    pub async fn resolve(
+        &self,
+        path: &str,
+        read: impl FnOnce(&str) -> Result<AppMetadata, String>,
+    ) -> application::Model {
+        let key = normalize_path(path);
+        let stamp = FileStamp::of(path);
+        let cached = self.entries.lock().unwrap().get(&key).cloned();
+
+        let changed = cached.as_ref().is_some_and(|cached| {
+            stamp.is_some_and(|stamp| stamp != FileStamp::of_model(&cached.model))
+        });
+
+        let model = match cached {
+            Some(cached) if !changed && cached.saved => return cached.model,
+            // metadata which could not be saved before is not read again
+            Some(cached) if !changed => cached.model,
+            cached => {
+                if cached.is_some() {
+                    info!("Executable {} changed, reading its metadata again", path);
+                }
+
+                let metadata = read(path).unwrap_or_else(|err| {
+                    warn!("Could not read metadata of {}: {}", path, err);
+
+                    AppMetadata {
+                        app_title: file_name(path),
+                        publisher: None,
+                        version: None,
+                    }
+                });
+
+                application::Model {
+                    id: cached.map_or(0, |cached| cached.model.id),
+                    path: key.clone(),
+                    app_title: metadata.app_title,
+                    publisher: metadata.publisher,
+                    version: metadata.version,
+                    icon_key: icon_key(&key),
+                    file_modified: stamp.and_then(|stamp| stamp.modified),
+                    file_size: stamp.and_then(|stamp| stamp.size),
+                    updated_at: Utc::now(),
+                }
+            }
+        };
+
+        self.save(key, model).await
+    }
+
+    /// Inserts metadata without id, updates stored one
+    async fn save(&self, key: String, model: application::Model) -> application::Model {
+        let mut record = application::ActiveModel::from(model.clone()).reset_all();
+
+        let result = match model.id {
+            0 => {
+                record.id = NotSet;
+                record.insert(&self.db).await
+            }
+            _ => record.update(&self.db).await,
+        };
+
+        let entry = match result {
+            Ok(model) => Entry { model, saved: true },
+            Err(err) => {
+                // metadata is still cached in memory, it is saved again on the next lookup
+                error!("Error on saving metadata of {}: {}", key, err);
+
+                Entry {
+                    model,
+                    saved: false,
+                }
+            }
+        };
+
+        self.entries.lock().unwrap().insert(key, entry.clone());
+
+        entry.model
+    }
+}
+
+/// Windows paths are split the same way on every platform
+fn file_name(path: &str) -> String {
+    path.rsplit(['\\', '/']).next().unwrap_or(path).to_owned()
+}
+
+/// Executable paths are case insensitive on Windows, so the same application is cached and stored once
+fn normalize_path(path: &str) -> String {
+    path.to_lowercase()
+}
+
+/// Key of icon by normalized executable path
+fn icon_key(path: &str) -> String {
+    let hash = Sha256::digest(path.as_bytes());
+
+    hash.iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    use sea_orm::{ConnectionTrait, Database, DbBackend, Statement};
+
+    use crate::database;
+
+    fn metadata(app_title: &str) -> AppMetadata {
+        AppMetadata {
+            app_title: app_title.to_owned(),
+            publisher: Some("Microsoft Corporation".to_owned()),
+            version: Some("1.0".to_owned()),
+        }
+    }
+
+    #[tokio::test]
+    async fn metadata_is_read_once_and_survives_restart() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let reads = Cell::new(0);
+        let read = |_: &str| {
+            reads.set(reads.get() + 1);
+            Ok(metadata("Visual Studio Code"))
+        };
+
+        let cache = MetadataCache::load(db.clone()).await.unwrap();
+
+        let first = cache.resolve("C:\\missing\\Code.exe", read).await;
+        let second = cache.resolve("C:\\missing\\Code.exe", read).await;
+
+        assert_eq!(reads.get(), 1);
+        assert_eq!(first, second);
+        assert_eq!(first.app_title, "Visual Studio Code");
+
+        let restarted = MetadataCache::load(db.clone()).await.unwrap();
+        let third = restarted.resolve("C:\\missing\\Code.exe", read).await;
+
+        assert_eq!(reads.get(), 1);
+        assert_eq!(third, first);
+
+        let fallback = restarted
+            .resolve("C:\\missing\\tool.exe", |_| {
+                Err("no version info".to_owned())
+            })
+            .await;
+
+        assert_eq!(fallback.app_title, "tool.exe");
+    }
+
+    #[tokio::test]
+    async fn paths_differing_in_case_share_one_record() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let reads = Cell::new(0);
+        let read = |_: &str| {
+            reads.set(reads.get() + 1);
+            Ok(metadata("Visual Studio Code"))
+        };
+
+        let cache = MetadataCache::load(db.clone()).await.unwrap();
+
+        let first = cache.resolve("C:\\Missing\\Code.exe", read).await;
+        let second = cache.resolve("c:\\missing\\CODE.EXE", read).await;
+
+        assert_eq!(reads.get(), 1);
+        assert_eq!(first, second);
+        assert_eq!(first.path, "c:\\missing\\code.exe");
+
+        let restarted = MetadataCache::load(db.clone()).await.unwrap();
+        let third = restarted.resolve("C:\\MISSING\\Code.exe", read).await;
+
+        assert_eq!(reads.get(), 1);
+        assert_eq!(third, first);
+        assert_eq!(application::Entity::find().all(&db).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn metadata_which_could_not_be_saved_is_saved_on_next_lookup() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let cache = MetadataCache::load(db.clone()).await.unwrap();
+
+        db.execute(Statement::from_string(
+            DbBackend::Sqlite,
+            "DROP TABLE application".to_owned(),
+        ))
+        .await
+        .unwrap();
+
+        let reads = Cell::new(0);
+        let read = |_: &str| {
+            reads.set(reads.get() + 1);
+            Ok(metadata("Visual Studio Code"))
+        };
+
+        let unsaved = cache.resolve("C:\\missing\\Code.exe", read).await;
+
+        assert_eq!(unsaved.id, 0);
+        assert_eq!(unsaved.app_title, "Visual Studio Code");
+
+        database::create_db_tables(&db).await;
+
+        let saved = cache.resolve("C:\\missing\\Code.exe", read).await;
+
+        assert_eq!(reads.get(), 1);
+        assert_ne!(saved.id, 0);
+        assert_eq!(
+            application::Entity::find().all(&db).await.unwrap(),
+            vec![saved]
+        );
+    }
+
+    #[tokio::test]
+    async fn changed_executable_is_read_again() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let path =
+            std::env::temp_dir().join(format!("productivity-app-{}.exe", std::process::id()));
+        let path_str = path.to_string_lossy().into_owned();
+        std::fs::write(&path, "v1").unwrap();
+
+        let cache = MetadataCache::load(db.clone()).await.unwrap();
+
+        let first = cache.resolve(&path_str, |_| Ok(metadata("App"))).await;
+
+        std::fs::write(&path, "version 2").unwrap();
+
+        let second = cache.resolve(&path_str, |_| Ok(metadata("App 2"))).await;
+
+        assert_eq!(first.app_title, "App");
+        assert_eq!(second.app_title, "App 2");
+        assert_eq!(second.id, first.id);
+        assert_eq!(second.file_size, Some(9));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,41 +1,88 @@
 use chrono::prelude::*;
-use once_cell::sync::OnceCell;
-use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, Set};
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use rocket::serde::json::serde_json;
+use sea_orm::{
+    sea_query::{CaseStatement, Expr},
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver},
+    watch,
+};
 
+use crate::config::Config;
 use crate::entity::*;
+use crate::metrics::{FailureStage, Metrics, RejectReason};
+use crate::notifier::{Notification, Notifier};
+use crate::usage;
 
-use std::{path::Path, thread::JoinHandle};
-
-mod windows_service;
-
-use windows::{
-    w,
-    Win32::{
-        Foundation::{GetLastError, HANDLE, HWND, LPARAM, LRESULT, WPARAM},
-        UI::{
-            Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK},
-            WindowsAndMessaging::{
-                DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, PostQuitMessage,
-                RegisterClassW, CS_GLOBALCLASS, EVENT_SYSTEM_FOREGROUND, MSG, WM_CLOSE, WM_DESTROY,
-                WNDCLASSW,
-            },
-        },
-    },
-};
+use std::{sync::Arc, time::Instant};
+
+/// Idle time is read from source this often
+static IDLE_CHECK_SECONDS: u64 = 5;
+
+/// Rows checked by exclusion or privacy rules at once, so ids of updated rows stay within SQLite
+/// limit of bound variables
+static HIDE_PAGE_SIZE: u64 = 500;
 
-use self::windows_service::WindowsService;
+mod categories;
+pub mod filter;
+pub mod goals;
+pub mod metadata;
+pub mod privacy;
+pub mod retention;
+pub mod rules;
+pub mod schedule;
+pub mod source;
+pub mod supervisor;
+pub mod tracking;
+pub mod windows_service;
+pub mod windows_source;
+pub mod writer;
 
-thread_local! {
-    static TX: OnceCell<UnboundedSender<Option<RawWindowEvent>>>= OnceCell::new()
+use self::{
+    categories::Categorizer,
+    filter::ExclusionFilter,
+    metadata::MetadataCache,
+    privacy::TitlePrivacy,
+    rules::{CompiledForbiddenRule, Enforcer, ForbiddenRules},
+    schedule::Schedule,
+    source::{ActivitySource, FocusSender, ProcessedWindowEvent},
+    supervisor::TrackerMonitor,
+    tracking::TrackingControl,
+    writer::{EventWriter, EventWriterHandle, PendingEvent},
+};
+
+/// Reason of tracking loop finishing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopExit {
+    Shutdown,
+    /// Source stopped sending focus changes on its own, e.g. its thread crashed
+    SourceEnded,
 }
 
 pub struct Service {
-    tx: UnboundedSender<Option<RawWindowEvent>>,
-    cx: UnboundedReceiver<Option<RawWindowEvent>>,
-    thread_handle: Option<JoinHandle<()>>,
+    cx: UnboundedReceiver<Option<ProcessedWindowEvent>>,
+    source: Box<dyn ActivitySource>,
+    monitor: Arc<TrackerMonitor>,
+    metrics: Arc<Metrics>,
+    writer: EventWriterHandle,
     db: DatabaseConnection,
     session: session::Model,
+    filter: ExclusionFilter,
+    privacy: TitlePrivacy,
+    control: Arc<TrackingControl>,
+    schedule: Schedule,
+    record_outside_schedule: bool,
+    rules: ForbiddenRules,
+    enforcer: Box<dyn Enforcer>,
+    notifier: Arc<Notifier>,
+    categorizer: Categorizer,
+    metadata: MetadataCache,
+    /// Milliseconds without input after which user is idle, `None` when idle time is not detected
+    idle_threshold: Option<u32>,
+    /// Time of the last input while user is idle
+    idle_since: Option<DateTime<Utc>>,
 }
 
 // TODO: but still store plain events!
@@ -43,12 +90,36 @@ pub struct Service {
 // TODO: on upper task done, maybe setup tracking reasons for opening or closing, such as opening/closing application or just tabbing out
 
 impl Service {
-    pub async fn new(db: DatabaseConnection) -> Self {
-        let (tx, cx) = Self::setup_channel();
-        let thread_handle = Self::setup_event_messaging_thread(tx.clone());
+    pub async fn new(
+        db: DatabaseConnection,
+        config: &Config,
+        control: Arc<TrackingControl>,
+        notifier: Arc<Notifier>,
+        mut source: Box<dyn ActivitySource>,
+        enforcer: Box<dyn Enforcer>,
+        monitor: Arc<TrackerMonitor>,
+    ) -> Self {
+        let filter = ExclusionFilter::new(&config.filter.exclusions).unwrap();
+        let privacy = TitlePrivacy::new(&config.privacy).unwrap();
+        let schedule = Schedule::new(&config.schedule.windows).unwrap();
+        let rules = ForbiddenRules::new(&config.rules.forbidden).unwrap();
+        let categorizer = Categorizer::new(&config.categories).unwrap();
+
+        if config.filter.hide_history {
+            let hidden = Self::hide_excluded_history(&db, &filter).await.unwrap();
+            info!(
+                "Hidden {} already recorded events matching exclusion rules",
+                hidden
+            );
+        }
+
+        let metadata = MetadataCache::load(db.clone()).await.unwrap();
+
+        let (tx, cx) = unbounded_channel();
+        source.start(FocusSender::new(tx, monitor.queue_counter()));
 
         // TODO: Application might be restarted at some point, it is better to consiter it the same session when startup time is close to existing session
-        let startup_datetime = WindowsService::get_startup_timestamp();
+        let startup_datetime = source.startup_timestamp();
 
         let record = session::ActiveModel {
             datetime: Set(startup_datetime),
@@ -57,235 +128,1173 @@ impl Service {
 
         let session: session::Model = record.insert(&db).await.unwrap();
 
+        notifier.notify(Notification::SessionStarted(session.clone()));
+
+        let writer = EventWriter::spawn(
+            db.clone(),
+            &config.storage,
+            notifier.clone(),
+            monitor.metrics(),
+        );
+
         Self {
-            tx,
             cx,
-            thread_handle: Some(thread_handle),
+            source,
+            metrics: monitor.metrics(),
+            monitor,
+            writer,
             db,
             session,
+            filter,
+            privacy,
+            control,
+            schedule,
+            record_outside_schedule: config.schedule.record_outside,
+            rules,
+            enforcer,
+            notifier,
+            categorizer,
+            metadata,
+            idle_threshold: (config.idle.threshold_seconds > 0)
+                .then_some(config.idle.threshold_seconds.saturating_mul(1000)),
+            idle_since: None,
         }
     }
 
     // TODO: make iteration in loop with cx.recv() OR timeout, to track time in current window not only when its changed
-    pub async fn spin_loop(&mut self) {
-        while let Some(event) = self.cx.recv().await {
-            if event.is_none() {
-                break;
-            };
+    /// Records focus changes until source finishes or shutdown is requested, then closes interval
+    /// of focused app and stops source
+    pub async fn spin_loop(&mut self, mut shutdown: watch::Receiver<bool>) -> LoopExit {
+        let mut status_receiver = self.control.subscribe();
+        let mut paused = self.control.status().paused;
+        let mut idle_check =
+            tokio::time::interval(std::time::Duration::from_secs(IDLE_CHECK_SECONDS));
+        idle_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
-            // TODO: store processed_event in HashMap for optimisation purposes
-            let processed_event = Self::process_event(event.unwrap());
+        let exit = loop {
+            let resume_in = Self::duration_until(self.control.status().resume_at);
+            let schedule_edge_in = Self::duration_until(self.schedule.next_edge(Utc::now()));
 
-            if let Err(err) = processed_event {
-                error!("Error on processing event: {}", err);
-                continue;
+            // shutdown is checked first, focus changes sent before it are recorded on finish
+            tokio::select! {
+                biased;
+
+                Ok(()) = shutdown.changed() => {
+                    info!("Tracking shutdown requested");
+                    break LoopExit::Shutdown;
+                }
+                event = self.cx.recv() => {
+                    let Some(Some(event)) = event else {
+                        warn!("Activity source ended without shutdown request");
+                        break LoopExit::SourceEnded;
+                    };
+
+                    self.monitor.dequeued();
+
+                    self.handle_window_event(event, paused).await;
+                }
+                Ok(()) = status_receiver.changed() => {
+                    let status = status_receiver.borrow_and_update().clone();
+
+                    if status.paused == paused {
+                        continue;
+                    }
+
+                    paused = status.paused;
+
+                    if paused {
+                        info!("Tracking paused");
+                        self.insert_marker_event(event::EventKind::Paused).await;
+                        self.notifier.notify(Notification::TrackingPaused(status));
+                    } else {
+                        info!("Tracking resumed");
+                        self.notifier.notify(Notification::TrackingResumed(status));
+                        // window which is focused now would not produce foreground event by itself
+                        self.handle_foreground_window(paused).await;
+                    }
+                }
+                _ = idle_check.tick(), if self.idle_threshold.is_some() => {
+                    self.check_idle();
+                }
+                _ = tokio::time::sleep(resume_in.unwrap_or_default()), if resume_in.is_some() => {
+                    info!("Pause duration expired");
+                    self.control.resume();
+                }
+                _ = tokio::time::sleep(schedule_edge_in.unwrap_or_default()), if schedule_edge_in.is_some() => {
+                    if paused {
+                        continue;
+                    }
+
+                    // interval of focused window is split at schedule edge
+                    if self.is_recording_schedule() {
+                        info!("Tracking schedule edge reached, recording focused window");
+                        self.handle_foreground_window(paused).await;
+                    } else {
+                        info!("Tracking schedule finished");
+                        self.insert_marker_event(event::EventKind::OffSchedule).await;
+                    }
+                }
             }
+        };
 
-            let processed_event = processed_event.unwrap();
+        self.finish(exit).await;
 
-            // TODO: application might not have "Product name" in its metadata, in this case it's better to use executable name
-            let record = event::ActiveModel {
-                path: Set(processed_event.path),
-                title: Set(processed_event.window_title.clone()),
-                offset: Set(processed_event.offset),
-                timestamp: Set(Utc::now()),
-                session_id: Set(self.session.id),
-                app_title: Set(processed_event.app_title),
-                ..Default::default()
-            };
+        exit
+    }
+
+    /// Notifies about transitions between idle and active user by time since the last input
+    fn check_idle(&mut self) {
+        let Some(threshold) = self.idle_threshold else {
+            return;
+        };
 
-            let insert_record_result = event::Entity::insert(record).exec(&self.db).await;
+        let idle_time = match self.source.idle_time() {
+            Ok(idle_time) => idle_time,
+            Err(err) => {
+                warn!("Could not read idle time: {}", err);
+                return;
+            }
+        };
 
-            if let Err(err) = insert_record_result {
-                error!("Error on inserting event into database: {}", err);
-                continue;
+        let last_input = Utc::now() - chrono::Duration::milliseconds(idle_time as i64);
+
+        match (idle_time >= threshold, self.idle_since) {
+            (true, None) => {
+                info!("User is idle since {}", last_input);
+                self.idle_since = Some(last_input);
+                self.notifier
+                    .notify(Notification::Idle { since: last_input });
             }
+            (false, Some(idle_since)) => {
+                info!("User is active again");
+                self.idle_since = None;
+                self.notifier.notify(Notification::Active {
+                    idle_since,
+                    active_at: last_input,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Starts source again after it ended, focused window is recorded as it does not produce
+    /// focus change by itself
+    pub async fn restart_source(&mut self) {
+        let (tx, cx) = unbounded_channel();
+
+        self.cx = cx;
+        self.source
+            .start(FocusSender::new(tx, self.monitor.queue_counter()));
+
+        self.handle_foreground_window(self.control.status().paused)
+            .await;
+    }
+
+    /// Records focus changes already sent by source and shutdown marker, so interval of focused
+    /// app is closed at this moment, and waits until they are written. Session ends on shutdown,
+    /// after ended source it continues once the source is restarted
+    async fn finish(&mut self, exit: LoopExit) {
+        self.source.stop();
+
+        let paused = self.control.status().paused;
+
+        while let Ok(Some(event)) = self.cx.try_recv() {
+            self.monitor.dequeued();
+
+            self.handle_window_event(event, paused).await;
+        }
+
+        self.insert_marker_event(event::EventKind::Shutdown).await;
+        self.writer.flush().await;
+
+        info!("Tracking finished, focused app interval is closed");
+
+        if exit == LoopExit::Shutdown {
+            self.end_session();
         }
     }
 
-    #[allow(dead_code)]
-    pub fn stop_loop(&mut self) {
-        self.tx.send(None).unwrap();
-        self.thread_handle.take().unwrap().join().unwrap();
+    /// Notifies that no more events are recorded within current session
+    fn end_session(&self) {
+        self.notifier.notify(Notification::SessionEnded {
+            session: self.session.clone(),
+            finished_at: Utc::now(),
+        });
+    }
+
+    fn reject_reason(&self, paused: bool) -> Option<RejectReason> {
+        if paused {
+            return Some(RejectReason::Paused);
+        }
+
+        (!self.is_recording_schedule()).then_some(RejectReason::OffSchedule)
+    }
+
+    fn is_recording_schedule(&self) -> bool {
+        self.record_outside_schedule || self.schedule.contains(Utc::now())
     }
 
-    fn process_event(event: RawWindowEvent) -> Result<ProcessedWindowEvent, String> {
-        let window_title = WindowsService::get_window_title(event.event_id)?;
-        let pid = WindowsService::get_process_id(event.event_id)?;
-        let process_handle = WindowsService::get_process_handle(pid)?;
-        let path = WindowsService::get_process_executable_path(process_handle)?;
+    fn duration_until(at: Option<DateTime<Utc>>) -> Option<std::time::Duration> {
+        at.map(|at| {
+            (at - Utc::now())
+                .to_std()
+                .unwrap_or(std::time::Duration::ZERO)
+        })
+    }
 
-        let app_title = match WindowsService::get_app_title(path.clone()) {
-            Ok(title) => title,
-            Err(_) => {
-                let path = Path::new(&path);
-                String::from(path.file_name().unwrap().to_str().unwrap())
+    async fn handle_foreground_window(&self, paused: bool) {
+        match self.source.foreground() {
+            Ok(event) => self.handle_window_event(event, paused).await,
+            Err(err) => {
+                self.metrics.failed(FailureStage::Foreground);
+                error!("Error on processing foreground window: {}", err);
             }
+        }
+    }
+
+    /// Forbidden rules are checked for every window which is not excluded. While paused or out of
+    /// schedule the event is dropped afterwards, single marker event is already recorded
+    async fn handle_window_event(&self, mut processed_event: ProcessedWindowEvent, paused: bool) {
+        let received_at = Instant::now();
+
+        let application = self
+            .metadata
+            .resolve(&processed_event.path, |path| self.source.app_metadata(path))
+            .await;
+        processed_event.app_title = application.app_title;
+
+        // excluded window is still recorded to finish previous app interval, but without its title,
+        // path and app
+        let excluded = self.filter.is_excluded(
+            &processed_event.app_title,
+            &processed_event.path,
+            &processed_event.window_title,
+        );
+
+        let (kind, window_title) = match excluded {
+            true => (event::EventKind::Excluded, String::new()),
+            false => (
+                event::EventKind::Focus,
+                self.privacy.apply(
+                    &processed_event.app_title,
+                    &processed_event.path,
+                    &processed_event.window_title,
+                ),
+            ),
         };
 
-        Ok(ProcessedWindowEvent {
-            window_title,
-            pid,
-            process_handle,
-            path,
-            offset: event.timestamp,
-            app_title,
-        })
+        // excluded windows are not recorded in violations either
+        let violated_rule = match excluded {
+            true => None,
+            false => self.rules.find_violated(
+                &processed_event.app_title,
+                &processed_event.path,
+                &processed_event.window_title,
+                Utc::now(),
+            ),
+        };
+
+        if let Some(rule) = violated_rule {
+            self.handle_violation(rule, &processed_event, &window_title)
+                .await;
+        }
+
+        if let Some(reason) = self.reject_reason(paused) {
+            self.metrics.rejected(reason);
+            return;
+        }
+
+        let category = match kind {
+            event::EventKind::Focus => self.categorizer.categorize(
+                &processed_event.app_title,
+                &processed_event.path,
+                &processed_event.window_title,
+            ),
+            _ => None,
+        };
+
+        let (path, app_title) = match excluded {
+            true => (String::new(), String::new()),
+            false => (processed_event.path, processed_event.app_title),
+        };
+
+        let timestamp = Utc::now();
+
+        let record = event::ActiveModel {
+            path: Set(path),
+            title: Set(window_title),
+            offset: Set(processed_event.offset),
+            timestamp: Set(timestamp),
+            session_id: Set(self.session.id),
+            app_title: Set(app_title),
+            kind: Set(kind),
+            in_schedule: Set(self.schedule.contains(timestamp)),
+            category: Set(category),
+            project: Set(None),
+            ..Default::default()
+        };
+
+        // focus notification is sent by writer once event is written
+        self.writer
+            .write(PendingEvent {
+                record,
+                received_at: Some(received_at),
+            })
+            .await;
     }
 
-    fn setup_channel() -> (
-        UnboundedSender<Option<RawWindowEvent>>,
-        UnboundedReceiver<Option<RawWindowEvent>>,
+    async fn handle_violation(
+        &self,
+        rule: &CompiledForbiddenRule,
+        processed_event: &ProcessedWindowEvent,
+        window_title: &str,
     ) {
-        unbounded_channel::<Option<RawWindowEvent>>()
-    }
-
-    fn setup_event_messaging_thread(tx: UnboundedSender<Option<RawWindowEvent>>) -> JoinHandle<()> {
-        std::thread::spawn(move || {
-            TX.with(|f| f.set(tx.clone())).unwrap();
-
-            let hook = unsafe {
-                SetWinEventHook(
-                    EVENT_SYSTEM_FOREGROUND,
-                    EVENT_SYSTEM_FOREGROUND,
-                    None,
-                    Some(Self::win_foreground_change_callback),
-                    0,
-                    0,
-                    0,
-                )
+        warn!(
+            "Forbidden rule {} violated by {}",
+            rule.name, processed_event.app_title
+        );
+
+        let enforced = rule.strict
+            && match self.enforcer.enforce(processed_event.pid) {
+                Ok(()) => true,
+                Err(err) => {
+                    self.metrics.failed(FailureStage::Enforce);
+                    error!("Error on enforcing forbidden rule {}: {}", rule.name, err);
+                    false
+                }
             };
 
-            if hook.0 == 0 {
-                panic!("Could not setup WinEventHook");
+        let record = violation::ActiveModel {
+            rule: Set(rule.name.clone()),
+            path: Set(processed_event.path.clone()),
+            title: Set(window_title.to_owned()),
+            app_title: Set(processed_event.app_title.clone()),
+            timestamp: Set(Utc::now()),
+            enforced: Set(enforced),
+            session_id: Set(self.session.id),
+            ..Default::default()
+        };
+
+        let started_at = Instant::now();
+        let result = record.insert(&self.db).await;
+        self.metrics.observe_db_write(started_at.elapsed());
+
+        match result {
+            Ok(violation) => self.notifier.notify(Notification::Violation(violation)),
+            Err(err) => {
+                self.metrics.failed(FailureStage::InsertViolation);
+                error!("Error on inserting violation into database: {}", err);
             }
+        }
+    }
 
-            info!("WinEventHook setup successful!");
+    /// Records event without window, which finishes interval of previously focused app
+    async fn insert_marker_event(&self, kind: event::EventKind) {
+        let timestamp = Utc::now();
 
-            // window class creation and registration for unknown reason doesn't work when separated into another function, hence must stay here
+        let record = event::ActiveModel {
+            path: Set(String::new()),
+            title: Set(String::new()),
+            offset: Set(self.source.tick_count()),
+            timestamp: Set(timestamp),
+            session_id: Set(self.session.id),
+            app_title: Set(String::new()),
+            kind: Set(kind),
+            in_schedule: Set(self.schedule.contains(timestamp)),
+            category: Set(None),
+            project: Set(None),
+            ..Default::default()
+        };
 
-            let mut wc = WNDCLASSW::default();
-            let class_name = w!("randomclassname123");
-            wc.lpszClassName = class_name;
-            wc.lpfnWndProc = Some(Self::window_messaging_proc);
-            wc.style = CS_GLOBALCLASS;
-            let wc_ptr: *const WNDCLASSW = &wc;
+        self.writer
+            .write(PendingEvent {
+                record,
+                received_at: None,
+            })
+            .await;
+    }
 
-            let class_register_result = unsafe { RegisterClassW(wc_ptr) };
+    /// Marks recorded events matching exclusion rules as excluded, clearing their window details,
+    /// and refreshes daily usage of days they were in, returns amount of affected events
+    pub async fn hide_excluded_history(
+        db: &DatabaseConnection,
+        filter: &ExclusionFilter,
+    ) -> Result<u64, DbErr> {
+        let mut last_id = 0;
+        let mut hidden = 0;
+        // the earliest and the latest hidden events, daily usage between them is refreshed
+        let mut bounds: Option<(event::Model, event::Model)> = None;
 
-            if class_register_result == 0 {
-                let err = unsafe { GetLastError() };
+        loop {
+            let events = event::Entity::find()
+                .filter(event::Column::Kind.eq(event::EventKind::Focus))
+                .filter(event::Column::Id.gt(last_id))
+                .order_by_asc(event::Column::Id)
+                .limit(HIDE_PAGE_SIZE)
+                .all(db)
+                .await?;
 
-                panic!(
-                    "Error on creating window class for messaging window. Windows error code: {}",
-                    err.0
-                );
+            let Some(last) = events.last() else {
+                break;
             };
 
-            let hwnd = WindowsService::create_messaging_window(wc.lpszClassName);
+            last_id = last.id;
+
+            let excluded: Vec<event::Model> = events
+                .into_iter()
+                .filter(|event| filter.is_excluded(&event.app_title, &event.path, &event.title))
+                .collect();
 
-            let mut msg: MSG = MSG::default();
-            let msg_ptr: *mut MSG = &mut msg;
+            if excluded.is_empty() {
+                continue;
+            }
 
-            loop {
-                let result = unsafe { GetMessageW(msg_ptr, hwnd, 0, 0) };
+            let result = event::Entity::update_many()
+                .col_expr(event::Column::Kind, Expr::value(event::EventKind::Excluded))
+                .col_expr(event::Column::Path, Expr::value(""))
+                .col_expr(event::Column::Title, Expr::value(""))
+                .col_expr(event::Column::AppTitle, Expr::value(""))
+                .col_expr(event::Column::Category, Expr::value(Option::<String>::None))
+                .col_expr(event::Column::Project, Expr::value(Option::<String>::None))
+                .filter(event::Column::Id.is_in(excluded.iter().map(|event| event.id)))
+                .exec(db)
+                .await?;
 
-                // WM_QUIT or error
-                if result.0 == -1 || result.0 == 0 {
-                    break;
-                }
+            hidden += result.rows_affected;
 
-                unsafe { DispatchMessageW(msg_ptr) };
+            for event in excluded {
+                bounds = match bounds {
+                    None => Some((event.clone(), event)),
+                    Some((first, last)) => Some((
+                        std::cmp::min_by_key(first, event.clone(), |event| event.timestamp),
+                        std::cmp::max_by_key(last, event, |event| event.timestamp),
+                    )),
+                };
             }
+        }
 
-            info!(
-                "Got message loop shut down, no messages will be passed further. Preparing to close message thread"
-            );
+        if let Some((first, last)) = bounds {
+            usage::refresh_for_events(db, &[first, last]).await?;
+        }
 
-            TX.with(|f| {
-                let tx = f.get().unwrap();
-                tx.send(None).unwrap()
-            });
+        Ok(hidden)
+    }
 
-            unsafe { UnhookWinEvent(hook) };
-        })
+    /// Applies current privacy rules to titles of recorded events, aggregates, activities and webhook
+    /// payloads, page by page, returns amount of changed records
+    pub async fn redact_history(
+        db: &DatabaseConnection,
+        privacy: &TitlePrivacy,
+    ) -> Result<u64, DbErr> {
+        let mut changed = 0;
+
+        let mut last_id = 0;
+
+        while let Some(events) = next_page::<event::Entity>(event::Column::Id, last_id, db).await? {
+            last_id = events.last().unwrap().id;
+
+            let titles = events
+                .into_iter()
+                .filter_map(|event| {
+                    let title = privacy.apply(&event.app_title, &event.path, &event.title);
+                    (title != event.title).then_some((event.id, title))
+                })
+                .collect();
+
+            changed +=
+                set_by_id::<event::Entity>(event::Column::Id, event::Column::Title, titles, db)
+                    .await?;
+        }
+
+        // aggregates keep no executable path, rules are matched by app title
+        let mut last_id = 0;
+
+        while let Some(aggregates) =
+            next_page::<usage_aggregate::Entity>(usage_aggregate::Column::Id, last_id, db).await?
+        {
+            last_id = aggregates.last().unwrap().id;
+
+            let titles = aggregates
+                .into_iter()
+                .filter_map(|aggregate| {
+                    let title = privacy.apply(&aggregate.app_title, "", &aggregate.title);
+                    (title != aggregate.title).then_some((aggregate.id, title))
+                })
+                .collect();
+
+            changed += set_by_id::<usage_aggregate::Entity>(
+                usage_aggregate::Column::Id,
+                usage_aggregate::Column::Title,
+                titles,
+                db,
+            )
+            .await?;
+        }
+
+        // app of activity is application title or executable name
+        let mut last_id = 0;
+
+        while let Some(activities) =
+            next_page::<activity::Entity>(activity::Column::Id, last_id, db).await?
+        {
+            last_id = activities.last().unwrap().id;
+
+            let titles = activities
+                .into_iter()
+                .filter_map(|activity| {
+                    let app = activity.app.as_deref()?;
+                    let title = activity.title.as_deref()?;
+                    let redacted = privacy.apply(app, app, title);
+                    (redacted != title).then_some((activity.id, redacted))
+                })
+                .collect();
+
+            changed += set_by_id::<activity::Entity>(
+                activity::Column::Id,
+                activity::Column::Title,
+                titles,
+                db,
+            )
+            .await?;
+        }
+
+        // focus and violation notifications carry whole events and violations
+        let mut last_id = 0;
+
+        while let Some(deliveries) =
+            next_page::<webhook_delivery::Entity>(webhook_delivery::Column::Id, last_id, db).await?
+        {
+            last_id = deliveries.last().unwrap().id;
+
+            let payloads = deliveries
+                .into_iter()
+                .filter_map(|delivery| {
+                    let payload = redact_payload(&delivery.payload, privacy)?;
+                    Some((delivery.id, payload))
+                })
+                .collect();
+
+            changed += set_by_id::<webhook_delivery::Entity>(
+                webhook_delivery::Column::Id,
+                webhook_delivery::Column::Payload,
+                payloads,
+                db,
+            )
+            .await?;
+        }
+
+        Ok(changed)
     }
+}
 
-    pub extern "system" fn win_foreground_change_callback(
-        child_id: HWINEVENTHOOK,
-        hook_handle: u32,
-        event_id: HWND,
-        window_handle: i32,
-        object_id: i32,
-        thread_id: u32,
-        timestamp: u32,
-    ) -> () {
-        let event = RawWindowEvent {
-            child_id,
-            hook_handle,
-            event_id,
-            window_handle,
-            object_id,
-            thread_id,
-            timestamp,
-        };
+/// Rows with id greater than `last_id`, `None` once there are no more
+async fn next_page<E: EntityTrait>(
+    id: E::Column,
+    last_id: i32,
+    db: &DatabaseConnection,
+) -> Result<Option<Vec<E::Model>>, DbErr> {
+    let rows = E::find()
+        .filter(id.gt(last_id))
+        .order_by_asc(id)
+        .limit(HIDE_PAGE_SIZE)
+        .all(db)
+        .await?;
 
-        info!("New event received");
+    Ok((!rows.is_empty()).then_some(rows))
+}
+
+/// Sets column of rows to values by their ids with single update, returns amount of changed rows
+async fn set_by_id<E: EntityTrait>(
+    id: E::Column,
+    column: E::Column,
+    values: Vec<(i32, String)>,
+    db: &DatabaseConnection,
+) -> Result<u64, DbErr> {
+    if values.is_empty() {
+        return Ok(0);
+    }
 
-        TX.with(|f| {
-            let tx: &UnboundedSender<Option<RawWindowEvent>> = f.get().unwrap();
+    let ids: Vec<i32> = values.iter().map(|(row, _)| *row).collect();
 
-            tx.send(Some(event)).unwrap();
+    let case = values
+        .into_iter()
+        .fold(CaseStatement::new(), |case, (row, value)| {
+            case.case(id.eq(row), Expr::value(value))
         });
+
+    let result = E::update_many()
+        .col_expr(column, case.into())
+        .filter(id.is_in(ids))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
+
+/// Payload of focus or violation notification with redacted title, `None` when it is unchanged
+fn redact_payload(payload: &str, privacy: &TitlePrivacy) -> Option<String> {
+    let mut payload: serde_json::Value = serde_json::from_str(payload).ok()?;
+
+    let kind = payload.get("type")?.as_str()?;
+
+    if kind != "focus" && kind != "violation" {
+        return None;
+    }
+
+    let app_title = payload.get("app_title")?.as_str()?;
+    let path = payload.get("path")?.as_str()?;
+    let title = payload.get("title")?.as_str()?;
+
+    let redacted = privacy.apply(app_title, path, title);
+
+    if redacted == title {
+        return None;
     }
 
-    pub extern "system" fn window_messaging_proc(
-        hwnd: HWND,
-        msg: u32,
-        wparam: WPARAM,
-        lparam: LPARAM,
-    ) -> LRESULT {
-        match msg {
-            WM_CLOSE => {
-                unsafe { PostQuitMessage(0) };
-                unsafe { DestroyWindow(hwnd) };
-                LRESULT(0)
+    payload["title"] = serde_json::Value::String(redacted);
+
+    Some(payload.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    };
+
+    use rocket::serde::json;
+    use sea_orm::{Database, QueryOrder};
+    use tokio::sync::broadcast;
+
+    use super::source::AppMetadata;
+
+    use crate::database;
+
+    type Sender = Arc<Mutex<Option<FocusSender>>>;
+
+    /// Sends focus changes given by test, remembers whether it was stopped
+    struct FakeSource {
+        tx: Sender,
+        stopped: Arc<AtomicBool>,
+        /// Milliseconds since the last input
+        idle_time: Arc<AtomicU32>,
+    }
+
+    impl ActivitySource for FakeSource {
+        fn start(&mut self, tx: FocusSender) {
+            *self.tx.lock().unwrap() = Some(tx);
+        }
+
+        fn foreground(&self) -> Result<ProcessedWindowEvent, String> {
+            Ok(window("code.exe"))
+        }
+
+        fn app_metadata(&self, path: &str) -> Result<AppMetadata, String> {
+            Ok(AppMetadata {
+                app_title: path.trim_start_matches("C:\\").to_owned(),
+                publisher: None,
+                version: None,
+            })
+        }
+
+        fn stop(&mut self) {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+
+        fn idle_time(&self) -> Result<u32, String> {
+            Ok(self.idle_time.load(Ordering::SeqCst))
+        }
+
+        fn tick_count(&self) -> u32 {
+            0
+        }
+
+        fn startup_timestamp(&self) -> DateTime<Utc> {
+            Utc::now()
+        }
+    }
+
+    struct FakeEnforcer;
+
+    impl Enforcer for FakeEnforcer {
+        fn enforce(&self, _pid: u32) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn window(app_title: &str) -> ProcessedWindowEvent {
+        ProcessedWindowEvent {
+            window_title: format!("{} window", app_title),
+            pid: 1,
+            path: format!("C:\\{}", app_title),
+            offset: 0,
+            app_title: app_title.to_owned(),
+        }
+    }
+
+    async fn setup() -> (Service, DatabaseConnection, Sender, Arc<AtomicBool>) {
+        setup_with(&Config::default()).await
+    }
+
+    async fn setup_with(config: &Config) -> (Service, DatabaseConnection, Sender, Arc<AtomicBool>) {
+        setup_with_idle(config, Arc::new(AtomicU32::new(0))).await
+    }
+
+    async fn setup_with_idle(
+        config: &Config,
+        idle_time: Arc<AtomicU32>,
+    ) -> (Service, DatabaseConnection, Sender, Arc<AtomicBool>) {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let tx: Sender = Arc::new(Mutex::new(None));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let service = Service::new(
+            db.clone(),
+            config,
+            Arc::new(TrackingControl::new()),
+            Arc::new(Notifier::new(&[], db.clone())),
+            Box::new(FakeSource {
+                tx: tx.clone(),
+                stopped: stopped.clone(),
+                idle_time,
+            }),
+            Box::new(FakeEnforcer),
+            Arc::new(TrackerMonitor::new()),
+        )
+        .await;
+
+        (service, db, tx, stopped)
+    }
+
+    fn send(tx: &Sender, event: Option<ProcessedWindowEvent>) {
+        tx.lock().unwrap().as_ref().unwrap().send(event).unwrap();
+    }
+
+    async fn recorded_kinds(db: &DatabaseConnection) -> Vec<(String, event::EventKind)> {
+        event::Entity::find()
+            .order_by_asc(event::Column::Timestamp)
+            .order_by_asc(event::Column::Id)
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.app_title, event.kind))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn shutdown_records_pending_events_and_closes_interval() {
+        let (mut service, db, tx, stopped) = setup().await;
+        let (shutdown_tx, shutdown) = watch::channel(false);
+
+        send(&tx, Some(window("code.exe")));
+        send(&tx, Some(window("firefox.exe")));
+
+        // focus changes sent before shutdown are still recorded
+        shutdown_tx.send(true).unwrap();
+
+        service.spin_loop(shutdown).await;
+
+        assert!(stopped.load(Ordering::SeqCst));
+        assert_eq!(
+            recorded_kinds(&db).await,
+            vec![
+                ("code.exe".to_owned(), event::EventKind::Focus),
+                ("firefox.exe".to_owned(), event::EventKind::Focus),
+                ("".to_owned(), event::EventKind::Shutdown),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn finished_source_closes_interval() {
+        let (mut service, db, tx, _) = setup().await;
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+
+        send(&tx, Some(window("code.exe")));
+        send(&tx, None);
+
+        service.spin_loop(shutdown).await;
+
+        assert_eq!(
+            recorded_kinds(&db).await,
+            vec![
+                ("code.exe".to_owned(), event::EventKind::Focus),
+                ("".to_owned(), event::EventKind::Shutdown),
+            ]
+        );
+    }
+
+    fn received_types(receiver: &mut broadcast::Receiver<Notification>) -> Vec<&'static str> {
+        let mut types = vec![];
+
+        while let Ok(notification) = receiver.try_recv() {
+            types.push(notification.event_type());
+        }
+
+        types
+    }
+
+    #[tokio::test]
+    async fn session_ends_on_shutdown_but_not_when_source_ended() {
+        let (mut service, _, tx, _) = setup().await;
+        let mut notifications = service.notifier.subscribe();
+        let (shutdown_tx, shutdown) = watch::channel(false);
+
+        send(&tx, None);
+        service.spin_loop(shutdown.clone()).await;
+
+        assert!(!received_types(&mut notifications).contains(&"session_ended"));
+
+        service.restart_source().await;
+        shutdown_tx.send(true).unwrap();
+        service.spin_loop(shutdown).await;
+
+        assert_eq!(
+            received_types(&mut notifications).last(),
+            Some(&"session_ended")
+        );
+    }
+
+    #[tokio::test]
+    async fn idle_transitions_are_notified_once() {
+        let config: Config = toml::from_str(
+            r#"
+            [idle]
+            threshold_seconds = 60
+            "#,
+        )
+        .unwrap();
+
+        let idle_time = Arc::new(AtomicU32::new(1000));
+        let (mut service, _, _, _) = setup_with_idle(&config, idle_time.clone()).await;
+        let mut notifications = service.notifier.subscribe();
+
+        service.check_idle();
+
+        idle_time.store(60_000, Ordering::SeqCst);
+        service.check_idle();
+        idle_time.store(65_000, Ordering::SeqCst);
+        service.check_idle();
+
+        let idle_since = service.idle_since.unwrap();
+
+        idle_time.store(500, Ordering::SeqCst);
+        service.check_idle();
+        service.check_idle();
+
+        let notifications: Vec<Notification> =
+            std::iter::from_fn(|| notifications.try_recv().ok()).collect();
+
+        assert_eq!(notifications.len(), 2);
+        assert!(matches!(
+            notifications[0],
+            Notification::Idle { since } if since == idle_since
+        ));
+        assert!(matches!(
+            notifications[1],
+            Notification::Active { idle_since: since, active_at } if since == idle_since && active_at > since
+        ));
+    }
+
+    #[tokio::test]
+    async fn idle_time_is_not_read_when_detection_is_off() {
+        let config: Config = toml::from_str(
+            r#"
+            [idle]
+            threshold_seconds = 0
+            "#,
+        )
+        .unwrap();
+
+        let (mut service, _, _, _) =
+            setup_with_idle(&config, Arc::new(AtomicU32::new(u32::MAX))).await;
+        let mut notifications = service.notifier.subscribe();
+
+        service.check_idle();
+
+        assert!(received_types(&mut notifications).is_empty());
+    }
+
+    #[tokio::test]
+    async fn forbidden_rules_are_checked_while_paused_except_excluded_windows() {
+        let config: Config = toml::from_str(
+            r#"
+            [[filter.exclusions]]
+            app = "secret.exe"
+
+            [[rules.forbidden]]
+            name = "games"
+            app = "game.exe"
+
+            [[rules.forbidden]]
+            name = "secret"
+            app = "secret.exe"
+            "#,
+        )
+        .unwrap();
+
+        let (mut service, db, tx, _) = setup_with(&config).await;
+        let (shutdown_tx, shutdown) = watch::channel(false);
+
+        service.control.pause(None);
+
+        send(&tx, Some(window("game.exe")));
+        send(&tx, Some(window("secret.exe")));
+        shutdown_tx.send(true).unwrap();
+
+        service.spin_loop(shutdown).await;
+
+        let rules: Vec<String> = violation::Entity::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|violation| violation.rule)
+            .collect();
+
+        assert_eq!(rules, vec!["games".to_owned()]);
+        assert_eq!(
+            recorded_kinds(&db).await,
+            vec![("".to_owned(), event::EventKind::Shutdown)]
+        );
+    }
+
+    fn exclusion_config() -> Config {
+        toml::from_str(
+            r#"
+            [[filter.exclusions]]
+            app = "secret.exe"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn excluded_window_is_recorded_without_details() {
+        let (mut service, db, tx, _) = setup_with(&exclusion_config()).await;
+        let (shutdown_tx, shutdown) = watch::channel(false);
+
+        send(&tx, Some(window("code.exe")));
+        send(&tx, Some(window("secret.exe")));
+        shutdown_tx.send(true).unwrap();
+
+        service.spin_loop(shutdown).await;
+
+        let excluded = event::Entity::find()
+            .filter(event::Column::Kind.eq(event::EventKind::Excluded))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            (
+                excluded.app_title.as_str(),
+                excluded.path.as_str(),
+                excluded.title.as_str()
+            ),
+            ("", "", "")
+        );
+    }
+
+    #[tokio::test]
+    async fn history_is_hidden_page_by_page() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let session = session::ActiveModel {
+            datetime: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        // more excluded events than fit into one page, so they are hidden in several updates
+        let count = HIDE_PAGE_SIZE as i64 * 3;
+        let start = Utc::now() - chrono::Duration::seconds(count);
+
+        for index in 0..count {
+            let app_title = match index % 2 {
+                0 => "secret.exe",
+                _ => "code.exe",
+            };
+
+            event::ActiveModel {
+                path: Set(format!("C:\\{}", app_title)),
+                title: Set("window".to_owned()),
+                timestamp: Set(start + chrono::Duration::seconds(index)),
+                app_title: Set(app_title.to_owned()),
+                offset: Set(0),
+                session_id: Set(session.id),
+                kind: Set(event::EventKind::Focus),
+                in_schedule: Set(true),
+                ..Default::default()
             }
-            WM_DESTROY => {
-                unsafe { PostQuitMessage(0) };
-                LRESULT(0)
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+
+        let filter = ExclusionFilter::new(&exclusion_config().filter.exclusions).unwrap();
+        let hidden = Service::hide_excluded_history(&db, &filter).await.unwrap();
+
+        assert_eq!(hidden, count as u64 / 2);
+
+        let events = event::Entity::find().all(&db).await.unwrap();
+
+        assert!(events.iter().all(|event| match event.kind {
+            event::EventKind::Excluded => event.app_title.is_empty() && event.path.is_empty(),
+            _ => event.app_title == "code.exe",
+        }));
+        assert_eq!(
+            Service::hide_excluded_history(&db, &filter).await.unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn history_is_redacted_everywhere_page_by_page() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let session = session::ActiveModel {
+            datetime: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        // more events than fit into one page, so they are redacted in several updates
+        let count = HIDE_PAGE_SIZE as i64 * 2 + 1;
+        let start = Utc::now() - chrono::Duration::seconds(count);
+
+        for index in 0..count {
+            let app_title = match index % 2 {
+                0 => "secret.exe",
+                _ => "code.exe",
+            };
+
+            event::ActiveModel {
+                path: Set(format!("C:\\{}", app_title)),
+                title: Set(format!("window {}", index)),
+                timestamp: Set(start + chrono::Duration::seconds(index)),
+                app_title: Set(app_title.to_owned()),
+                offset: Set(0),
+                session_id: Set(session.id),
+                kind: Set(event::EventKind::Focus),
+                in_schedule: Set(true),
+                ..Default::default()
             }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
 
-            _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+        usage_aggregate::ActiveModel {
+            period: Set(usage_aggregate::AggregatePeriod::Hour),
+            start: Set(start),
+            app_title: Set("secret.exe".to_owned()),
+            title: Set("plain".to_owned()),
+            duration: Set(1000),
+            ..Default::default()
         }
-    }
-}
+        .insert(&db)
+        .await
+        .unwrap();
 
-#[derive(Debug, Clone, Copy)]
-pub struct RawWindowEvent {
-    /// Hook ID
-    /// If this value is [`CHILDID_SELF`], the event was triggered by the object; otherwise, this value is the child ID of the element that triggered the event.
-    pub child_id: HWINEVENTHOOK,
-    /// Handle to the shared event hook function.
-    pub hook_handle: u32,
-    /// handle to newly appeared window
-    pub event_id: HWND,
-    /// Handle to the window that generates the event, or `NULL` if no window is associated with the event.
-    pub window_handle: i32,
-    /// Identifies the object associated with the event.
-    pub object_id: i32,
-    /// Identifies the thread that generated the event.
-    pub thread_id: u32,
-    /// Specifies the time since system startup, in milliseconds, that the event was generated.
-    pub timestamp: u32,
-}
+        activity::ActiveModel {
+            client_id: Set("tab".to_owned()),
+            source: Set("browser".to_owned()),
+            timestamp: Set(start),
+            end: Set(start + chrono::Duration::seconds(1)),
+            app: Set(Some("secret.exe".to_owned())),
+            title: Set(Some("plain".to_owned())),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let first = event::Entity::find()
+            .order_by_asc(event::Column::Id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+
+        for (event_type, payload) in [
+            (
+                "focus",
+                json::to_string(&Notification::Focus(first)).unwrap(),
+            ),
+            (
+                "session_started",
+                json::to_string(&Notification::SessionStarted(session)).unwrap(),
+            ),
+        ] {
+            webhook_delivery::ActiveModel {
+                url: Set("http://localhost/hook".to_owned()),
+                event_type: Set(event_type.to_owned()),
+                payload: Set(payload),
+                attempt: Set(1),
+                delivered: Set(true),
+                timestamp: Set(start),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
 
-#[allow(dead_code)]
-struct ProcessedWindowEvent {
-    pub window_title: String,
-    pub pid: u32,
-    pub process_handle: HANDLE,
-    pub path: String,
-    pub offset: u32,
-    pub app_title: String,
+        let config: Config = toml::from_str(
+            r#"
+            [[privacy.rules]]
+            app = "secret.exe"
+            mode = "redact"
+            "#,
+        )
+        .unwrap();
+        let privacy = TitlePrivacy::new(&config.privacy).unwrap();
+
+        let changed = Service::redact_history(&db, &privacy).await.unwrap();
+
+        // half of events rounded up, aggregate, activity and focus notification
+        assert_eq!(changed, (count as u64).div_ceil(2) + 3);
+
+        let events = event::Entity::find().all(&db).await.unwrap();
+
+        assert!(events.iter().all(|event| match event.app_title.as_str() {
+            "secret.exe" => event.title == "[redacted]",
+            _ => event.title.starts_with("window"),
+        }));
+
+        let aggregate = usage_aggregate::Entity::find()
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        let activity = activity::Entity::find().one(&db).await.unwrap().unwrap();
+        let payloads: Vec<String> = webhook_delivery::Entity::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|delivery| delivery.payload)
+            .collect();
+
+        assert_eq!(aggregate.title, "[redacted]");
+        assert_eq!(activity.title.as_deref(), Some("[redacted]"));
+        assert!(payloads[0].contains("[redacted]"));
+        assert!(!payloads.iter().any(|payload| payload.contains("window 0")));
+
+        assert_eq!(Service::redact_history(&db, &privacy).await.unwrap(), 0);
+    }
 }
@@ -0,0 +1,99 @@
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use crate::config::{PrivacyConfig, PrivacyMode};
+
+use super::filter::matches_app;
+
+/// Prefix of hashed titles, so already hashed titles are not hashed again
+static HASH_PREFIX: &str = "sha256:";
+static DEFAULT_REPLACEMENT: &str = "[redacted]";
+
+struct CompiledRule {
+    app: String,
+    mode: PrivacyMode,
+    pattern: Option<Regex>,
+    replacement: String,
+}
+
+/// Transforms window titles before they are stored, according to per-app privacy modes
+pub struct TitlePrivacy {
+    salt: String,
+    rules: Vec<CompiledRule>,
+}
+
+impl TitlePrivacy {
+    pub fn new(config: &PrivacyConfig) -> Result<Self, String> {
+        let uses_hash = config
+            .rules
+            .iter()
+            .any(|rule| rule.mode == PrivacyMode::Hash);
+
+        if uses_hash && config.salt.is_none() {
+            return Err("Privacy salt must be set when hash mode is used".to_owned());
+        }
+
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let pattern = match &rule.pattern {
+                    Some(pattern) => Some(
+                        Regex::new(pattern)
+                            .map_err(|err| format!("Invalid redact regex {}: {}", pattern, err))?,
+                    ),
+                    None => None,
+                };
+
+                Ok(CompiledRule {
+                    app: rule.app.clone(),
+                    mode: rule.mode,
+                    pattern,
+                    replacement: rule
+                        .replacement
+                        .clone()
+                        .unwrap_or(DEFAULT_REPLACEMENT.to_owned()),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            salt: config.salt.clone().unwrap_or_default(),
+            rules,
+        })
+    }
+
+    pub fn apply(&self, app_title: &str, path: &str, window_title: &str) -> String {
+        let rule = self
+            .rules
+            .iter()
+            .find(|rule| matches_app(&rule.app, app_title, path));
+
+        let Some(rule) = rule else {
+            return window_title.to_owned();
+        };
+
+        match rule.mode {
+            PrivacyMode::Full => window_title.to_owned(),
+            PrivacyMode::Redact => match &rule.pattern {
+                Some(pattern) => pattern
+                    .replace_all(window_title, rule.replacement.as_str())
+                    .into_owned(),
+                None => rule.replacement.clone(),
+            },
+            PrivacyMode::Hash => {
+                if window_title.starts_with(HASH_PREFIX) {
+                    return window_title.to_owned();
+                }
+
+                let digest = Sha256::new()
+                    .chain_update(&self.salt)
+                    .chain_update(window_title)
+                    .finalize();
+
+                format!("{}{:x}", HASH_PREFIX, digest)
+            }
+            PrivacyMode::Drop => String::new(),
+        }
+    }
+}
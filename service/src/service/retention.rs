@@ -0,0 +1,551 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{prelude::*, DurationRound};
+use sea_orm::{
+    prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+
+use crate::config::RetentionConfig;
+use crate::edits;
+use crate::entity::*;
+use crate::statistics;
+
+static RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Aggregated time is summed by these fields within period
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct AggregateKey {
+    start: DateTimeUtc,
+    app_title: String,
+    title: String,
+    category: Option<String>,
+    project: Option<String>,
+    source: Option<String>,
+    domain: Option<String>,
+}
+
+/// Periodically applies retention policy, rolling old events up into aggregates
+pub struct RetentionJob {
+    db: DatabaseConnection,
+    config: RetentionConfig,
+}
+
+impl RetentionJob {
+    pub fn new(db: DatabaseConnection, config: &RetentionConfig) -> Self {
+        Self {
+            db,
+            config: config.clone(),
+        }
+    }
+
+    pub async fn run(self) {
+        if self.config.raw_days.is_none() && self.config.hourly_days.is_none() {
+            info!("Retention policy is not configured, events are kept as is");
+            return;
+        }
+
+        let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.apply(Utc::now()).await {
+                error!("Error on applying retention policy: {}", err);
+            }
+        }
+    }
+
+    async fn apply(&self, now: DateTimeUtc) -> Result<(), DbErr> {
+        if let Some(days) = self.config.raw_days {
+            let cutoff = statistics::day_start(now - chrono::Duration::days(days as i64));
+            let rolled_up = roll_up_events(&self.db, cutoff).await?;
+
+            if rolled_up > 0 {
+                info!("Rolled up {} events older than {}", rolled_up, cutoff);
+            }
+        }
+
+        if let Some(days) = self.config.hourly_days {
+            let cutoff = statistics::day_start(now - chrono::Duration::days(days as i64));
+            let coarsened = coarsen_aggregates(&self.db, cutoff).await?;
+
+            if coarsened > 0 {
+                info!(
+                    "Coarsened {} hourly aggregates older than {}",
+                    coarsened, cutoff
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces events started before `cutoff` with hourly aggregates of their focus time, returns
+/// amount of removed events. Interval crossing `cutoff` keeps its part after it as event. Time is
+/// aggregated the way statistics count it: attributed to activities, without manual entries
+pub async fn roll_up_events(db: &DatabaseConnection, cutoff: DateTimeUtc) -> Result<u64, DbErr> {
+    let txn = db.begin().await?;
+
+    let events = event::Entity::find()
+        .filter(event::Column::Timestamp.lt(cutoff))
+        .order_by_asc(event::Column::Timestamp)
+        .order_by_asc(event::Column::Id)
+        .all(&txn)
+        .await?;
+
+    let Some(last) = events.last().cloned() else {
+        return Ok(0);
+    };
+
+    if edits::interval_end(&txn, &last)
+        .await?
+        .is_some_and(|end| end > cutoff)
+    {
+        edits::insert_copy(&txn, &last, cutoff).await?;
+    }
+
+    // the next event after cutoff finishes the last interval before it
+    let next_event = event::Entity::find()
+        .filter(event::Column::Timestamp.gte(cutoff))
+        .order_by_asc(event::Column::Timestamp)
+        .order_by_asc(event::Column::Id)
+        .limit(1)
+        .one(&txn)
+        .await?;
+
+    let open_session = session::Entity::find()
+        .order_by_desc(session::Column::Id)
+        .limit(1)
+        .one(&txn)
+        .await?
+        .map(|session| session.id);
+
+    let count = events.len() as u64;
+    let first = events[0].timestamp;
+
+    let mut all_events = events;
+    all_events.extend(next_event);
+
+    let intervals = statistics::build_intervals(all_events, open_session, Utc::now());
+    let intervals = statistics::clip_intervals(intervals, first, cutoff);
+
+    let activities = statistics::load_activities(&txn, first, cutoff).await?;
+    let manual = statistics::load_manual_intervals(&txn, first, cutoff).await?;
+    let segments = statistics::attribute_activities(&intervals, &activities);
+    let segments = statistics::exclude_manual(segments, &manual);
+
+    let mut durations: HashMap<AggregateKey, u32> = HashMap::new();
+
+    for segment in segments {
+        let mut start = segment.start;
+        let activity = segment.activity;
+
+        // segment is split at hour boundaries
+        while start < segment.end {
+            let hour = start.duration_trunc(chrono::Duration::hours(1)).unwrap();
+            let end = (hour + chrono::Duration::hours(1)).min(segment.end);
+
+            // project set by manual correction wins over reported one, as in statistics
+            let key = AggregateKey {
+                start: hour,
+                app_title: segment.event.app_title.clone(),
+                title: segment.event.title.clone(),
+                category: segment.event.category.clone(),
+                project: segment
+                    .event
+                    .project
+                    .clone()
+                    .or_else(|| activity.and_then(|activity| activity.project.clone())),
+                source: activity.map(|activity| activity.source.clone()),
+                domain: activity.and_then(|activity| activity.domain.clone()),
+            };
+
+            *durations.entry(key).or_insert(0) += (end - start).num_milliseconds() as u32;
+            start = end;
+        }
+    }
+
+    for (key, duration) in durations {
+        add_usage(&txn, usage_aggregate::AggregatePeriod::Hour, key, duration).await?;
+    }
+
+    event::Entity::delete_many()
+        .filter(event::Column::Timestamp.lt(cutoff))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    Ok(count)
+}
+
+/// Merges hourly aggregates started before `cutoff` into daily ones, returns amount of merged
+pub async fn coarsen_aggregates(
+    db: &DatabaseConnection,
+    cutoff: DateTimeUtc,
+) -> Result<u64, DbErr> {
+    let txn = db.begin().await?;
+
+    let hourly = usage_aggregate::Entity::find()
+        .filter(usage_aggregate::Column::Period.eq(usage_aggregate::AggregatePeriod::Hour))
+        .filter(usage_aggregate::Column::Start.lt(cutoff))
+        .all(&txn)
+        .await?;
+
+    let count = hourly.len() as u64;
+    let mut durations: HashMap<AggregateKey, u32> = HashMap::new();
+
+    for aggregate in hourly {
+        let key = AggregateKey {
+            start: statistics::day_start(aggregate.start),
+            app_title: aggregate.app_title,
+            title: aggregate.title,
+            category: aggregate.category,
+            project: aggregate.project,
+            source: aggregate.source,
+            domain: aggregate.domain,
+        };
+
+        *durations.entry(key).or_insert(0) += aggregate.duration;
+    }
+
+    for (key, duration) in durations {
+        add_usage(&txn, usage_aggregate::AggregatePeriod::Day, key, duration).await?;
+    }
+
+    usage_aggregate::Entity::delete_many()
+        .filter(usage_aggregate::Column::Period.eq(usage_aggregate::AggregatePeriod::Hour))
+        .filter(usage_aggregate::Column::Start.lt(cutoff))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    Ok(count)
+}
+
+/// Adds duration to aggregate with the same key, creating it when missing
+async fn add_usage(
+    conn: &impl ConnectionTrait,
+    period: usage_aggregate::AggregatePeriod,
+    key: AggregateKey,
+    duration: u32,
+) -> Result<(), DbErr> {
+    let category = match &key.category {
+        Some(category) => usage_aggregate::Column::Category.eq(category.clone()),
+        None => usage_aggregate::Column::Category.is_null(),
+    };
+
+    let project = match &key.project {
+        Some(project) => usage_aggregate::Column::Project.eq(project.clone()),
+        None => usage_aggregate::Column::Project.is_null(),
+    };
+
+    let source = match &key.source {
+        Some(source) => usage_aggregate::Column::Source.eq(source.clone()),
+        None => usage_aggregate::Column::Source.is_null(),
+    };
+
+    let domain = match &key.domain {
+        Some(domain) => usage_aggregate::Column::Domain.eq(domain.clone()),
+        None => usage_aggregate::Column::Domain.is_null(),
+    };
+
+    let existing = usage_aggregate::Entity::find()
+        .filter(usage_aggregate::Column::Period.eq(period))
+        .filter(usage_aggregate::Column::Start.eq(key.start))
+        .filter(usage_aggregate::Column::AppTitle.eq(key.app_title.clone()))
+        .filter(usage_aggregate::Column::Title.eq(key.title.clone()))
+        .filter(category)
+        .filter(project)
+        .filter(source)
+        .filter(domain)
+        .one(conn)
+        .await?;
+
+    match existing {
+        Some(existing) => {
+            let total = existing.duration + duration;
+            let mut record: usage_aggregate::ActiveModel = existing.into();
+            record.duration = Set(total);
+            record.update(conn).await?;
+        }
+        None => {
+            let record = usage_aggregate::ActiveModel {
+                period: Set(period),
+                start: Set(key.start),
+                app_title: Set(key.app_title),
+                title: Set(key.title),
+                category: Set(key.category),
+                project: Set(key.project),
+                source: Set(key.source),
+                domain: Set(key.domain),
+                duration: Set(duration),
+                ..Default::default()
+            };
+            record.insert(conn).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sea_orm::Database;
+
+    use crate::database;
+    use crate::server::statistics::{compute_durations, GroupBy};
+
+    async fn insert_event(
+        db: &DatabaseConnection,
+        session_id: i32,
+        timestamp: DateTimeUtc,
+        app_title: &str,
+        kind: event::EventKind,
+        category: Option<&str>,
+        project: Option<&str>,
+    ) {
+        event::ActiveModel {
+            path: Set(format!("{}.exe", app_title.to_lowercase())),
+            title: Set(format!("{} window", app_title)),
+            timestamp: Set(timestamp),
+            app_title: Set(app_title.to_owned()),
+            offset: Set(0),
+            session_id: Set(session_id),
+            kind: Set(kind),
+            in_schedule: Set(true),
+            category: Set(category.map(str::to_owned)),
+            project: Set(project.map(str::to_owned)),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    async fn insert_session(db: &DatabaseConnection, datetime: DateTimeUtc) -> i32 {
+        session::ActiveModel {
+            datetime: Set(datetime),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap()
+        .id
+    }
+
+    async fn insert_activity(
+        db: &DatabaseConnection,
+        source: &str,
+        app: &str,
+        (timestamp, end): (DateTimeUtc, DateTimeUtc),
+        project: Option<&str>,
+        domain: Option<&str>,
+    ) {
+        activity::ActiveModel {
+            client_id: Set(format!("{}-{}", source, timestamp)),
+            source: Set(source.to_owned()),
+            timestamp: Set(timestamp),
+            end: Set(end),
+            app: Set(Some(app.to_owned())),
+            project: Set(project.map(str::to_owned)),
+            domain: Set(domain.map(str::to_owned)),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn roll_up_keeps_statistics() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let now = Utc::now();
+        let hour = chrono::Duration::hours(1);
+        let minutes = chrono::Duration::minutes;
+
+        let base = (now - chrono::Duration::days(5))
+            .duration_trunc(hour)
+            .unwrap()
+            + minutes(7);
+        let cutoff = statistics::day_start(now - chrono::Duration::days(2));
+
+        let old = insert_session(&db, base).await;
+
+        let focus = event::EventKind::Focus;
+        insert_event(&db, old, base, "Code", focus, Some("work"), None).await;
+        insert_event(
+            &db,
+            old,
+            base + minutes(40),
+            "Firefox",
+            focus,
+            Some("web"),
+            None,
+        )
+        .await;
+        insert_event(
+            &db,
+            old,
+            base + minutes(70),
+            "Slack",
+            event::EventKind::Paused,
+            None,
+            None,
+        )
+        .await;
+        insert_event(
+            &db,
+            old,
+            base + minutes(80),
+            "Code",
+            focus,
+            None,
+            Some("fixed"),
+        )
+        .await;
+        insert_event(
+            &db,
+            old,
+            base + minutes(150),
+            "",
+            event::EventKind::Shutdown,
+            None,
+            None,
+        )
+        .await;
+
+        insert_activity(
+            &db,
+            "wakatime",
+            "Code",
+            (base + minutes(5), base + minutes(30)),
+            Some("crate"),
+            None,
+        )
+        .await;
+        insert_activity(
+            &db,
+            "browser",
+            "Firefox",
+            (base + minutes(45), base + minutes(60)),
+            None,
+            Some("docs.rs"),
+        )
+        .await;
+
+        manual_entry::ActiveModel {
+            start: Set(base + minutes(20)),
+            end: Set(Some(base + minutes(50))),
+            description: Set("Meeting".to_owned()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let current = insert_session(&db, now - hour).await;
+        insert_event(&db, current, now - minutes(30), "Code", focus, None, None).await;
+
+        let from = base.duration_trunc(hour).unwrap() - hour;
+        let to = from + hour * 6;
+
+        let groups = [
+            GroupBy::App,
+            GroupBy::Category,
+            GroupBy::Project,
+            GroupBy::Source,
+            GroupBy::Domain,
+        ];
+
+        let mut before = vec![];
+        for group_by in groups {
+            before.push(
+                compute_durations(&db, from, to, group_by, None)
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        assert_eq!(roll_up_events(&db, cutoff).await.unwrap(), 5);
+
+        assert!(event::Entity::find()
+            .filter(event::Column::Timestamp.lt(cutoff))
+            .all(&db)
+            .await
+            .unwrap()
+            .is_empty());
+
+        for (group_by, before) in groups.into_iter().zip(before) {
+            let after = compute_durations(&db, from, to, group_by, None)
+                .await
+                .unwrap();
+
+            assert_eq!(after, before, "{:?}", group_by);
+        }
+
+        let projects = compute_durations(&db, from, to, GroupBy::Project, None)
+            .await
+            .unwrap();
+
+        assert_eq!(projects["crate"], minutes(15).num_milliseconds() as u64);
+        assert_eq!(projects["fixed"], minutes(70).num_milliseconds() as u64);
+    }
+
+    #[tokio::test]
+    async fn partially_covered_aggregate_is_prorated() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let start = (Utc::now() - chrono::Duration::days(3))
+            .duration_trunc(chrono::Duration::hours(1))
+            .unwrap();
+
+        let key = AggregateKey {
+            start,
+            app_title: "Code".to_owned(),
+            title: "main.rs".to_owned(),
+            category: None,
+            project: None,
+            source: None,
+            domain: None,
+        };
+
+        add_usage(
+            &db,
+            usage_aggregate::AggregatePeriod::Hour,
+            key,
+            40 * 60 * 1000,
+        )
+        .await
+        .unwrap();
+
+        let half = statistics::load_aggregates(
+            &db,
+            start + chrono::Duration::minutes(30),
+            start + chrono::Duration::hours(2),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(half.len(), 1);
+        assert_eq!(half[0].duration, 20 * 60 * 1000);
+
+        let outside = statistics::load_aggregates(
+            &db,
+            start + chrono::Duration::hours(1),
+            start + chrono::Duration::hours(2),
+        )
+        .await
+        .unwrap();
+
+        assert!(outside.is_empty());
+    }
+}
@@ -0,0 +1,52 @@
+use chrono::prelude::*;
+
+use crate::config::ForbiddenRule;
+
+use super::{filter::WindowMatcher, schedule::Schedule};
+
+pub struct CompiledForbiddenRule {
+    pub name: String,
+    pub strict: bool,
+    window: WindowMatcher,
+    schedule: Schedule,
+}
+
+/// Forbidden applications from config, checked on every processed event
+pub struct ForbiddenRules {
+    rules: Vec<CompiledForbiddenRule>,
+}
+
+impl ForbiddenRules {
+    pub fn new(rules: &[ForbiddenRule]) -> Result<Self, String> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                Ok(CompiledForbiddenRule {
+                    name: rule.name.clone(),
+                    strict: rule.strict,
+                    window: WindowMatcher::new(&rule.window)?,
+                    schedule: Schedule::new(&rule.windows)?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn find_violated(
+        &self,
+        app_title: &str,
+        path: &str,
+        window_title: &str,
+        at: DateTime<Utc>,
+    ) -> Option<&CompiledForbiddenRule> {
+        self.rules.iter().find(|rule| {
+            rule.schedule.contains(at) && rule.window.matches(app_title, path, window_title)
+        })
+    }
+}
+
+/// Platform specific action against application violating strict forbidden rule
+pub trait Enforcer: Send + Sync {
+    fn enforce(&self, pid: u32) -> Result<(), String>;
+}
@@ -0,0 +1,250 @@
+use chrono::{prelude::*, Duration};
+
+use crate::config::ScheduleWindow;
+
+struct CompiledWindow {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+/// Local time windows in which tracking is active, empty schedule means always active
+pub struct Schedule {
+    windows: Vec<CompiledWindow>,
+}
+
+impl Schedule {
+    pub fn new(windows: &[ScheduleWindow]) -> Result<Self, String> {
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|err| format!("Invalid schedule time {}: {}", time, err))
+        };
+
+        let windows = windows
+            .iter()
+            .map(|window| {
+                let days = window
+                    .days
+                    .iter()
+                    .map(|day| {
+                        day.parse::<Weekday>()
+                            .map_err(|_| format!("Invalid schedule day {}", day))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+
+                Ok(CompiledWindow {
+                    days,
+                    start: parse_time(&window.start)?,
+                    end: parse_time(&window.end)?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self { windows })
+    }
+
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        self.contains_in(at, &Local)
+    }
+
+    /// Closest moment after `at` when tracking is started or stopped by schedule
+    pub fn next_edge(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next_edge_in(at, &Local)
+    }
+
+    fn contains_in<Tz: TimeZone>(&self, at: DateTime<Utc>, zone: &Tz) -> bool {
+        if self.windows.is_empty() {
+            return true;
+        }
+
+        self.occurrences(at, zone)
+            .into_iter()
+            .any(|(start, end)| start <= at && at < end)
+    }
+
+    fn next_edge_in<Tz: TimeZone>(&self, at: DateTime<Utc>, zone: &Tz) -> Option<DateTime<Utc>> {
+        self.occurrences(at, zone)
+            .into_iter()
+            .flat_map(|(start, end)| [start, end])
+            .filter(|edge| *edge > at)
+            .min()
+    }
+
+    /// Windows starting from yesterday (might last past midnight) up to a week ahead, in UTC
+    fn occurrences<Tz: TimeZone>(
+        &self,
+        at: DateTime<Utc>,
+        zone: &Tz,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let today = at.with_timezone(zone).date_naive();
+
+        (-1..=7)
+            .map(|offset| today + Duration::days(offset))
+            .flat_map(|date| {
+                self.windows
+                    .iter()
+                    .filter(move |window| window.days.contains(&date.weekday()))
+                    .map(move |window| {
+                        // window ending before its start finishes on the next day
+                        let end_date = match window.end <= window.start {
+                            true => date + Duration::days(1),
+                            false => date,
+                        };
+
+                        let start = to_utc(date.and_time(window.start), zone);
+                        let end = to_utc(end_date.and_time(window.end), zone);
+
+                        (start, end)
+                    })
+            })
+            .collect()
+    }
+}
+
+/// The earliest instant of local time, or the first valid time after it when DST transition skips it
+fn to_utc<Tz: TimeZone>(datetime: NaiveDateTime, zone: &Tz) -> DateTime<Utc> {
+    (0..=24 * 60)
+        .find_map(|minutes| {
+            zone.from_local_datetime(&(datetime + Duration::minutes(minutes)))
+                .earliest()
+        })
+        .expect("local time is not skipped for a whole day")
+        .with_timezone(&Utc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{FixedOffset, LocalResult};
+
+    /// UTC+1, and UTC+2 from the last Sunday of March until the last Sunday of October of 2023,
+    /// as in central Europe
+    #[derive(Clone, Copy, Debug)]
+    struct CentralEurope;
+
+    impl CentralEurope {
+        fn offset(utc: &NaiveDateTime) -> FixedOffset {
+            let summer_start = NaiveDate::from_ymd_opt(2023, 3, 26)
+                .unwrap()
+                .and_hms_opt(1, 0, 0)
+                .unwrap();
+            let summer_end = NaiveDate::from_ymd_opt(2023, 10, 29)
+                .unwrap()
+                .and_hms_opt(1, 0, 0)
+                .unwrap();
+
+            match summer_start <= *utc && *utc < summer_end {
+                true => FixedOffset::east_opt(2 * 3600).unwrap(),
+                false => FixedOffset::east_opt(3600).unwrap(),
+            }
+        }
+    }
+
+    impl TimeZone for CentralEurope {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            CentralEurope
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let valid: Vec<FixedOffset> = [2 * 3600, 3600]
+                .into_iter()
+                .map(|seconds| FixedOffset::east_opt(seconds).unwrap())
+                .filter(|offset| Self::offset(&(*local - *offset)) == *offset)
+                .collect();
+
+            match valid[..] {
+                [offset] => LocalResult::Single(offset),
+                [earliest, latest] => LocalResult::Ambiguous(earliest, latest),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            Self::offset(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            Self::offset(utc)
+        }
+    }
+
+    fn schedule(days: &[&str], start: &str, end: &str) -> Schedule {
+        Schedule::new(&[ScheduleWindow {
+            days: days.iter().map(|day| day.to_string()).collect(),
+            start: start.to_owned(),
+            end: end.to_owned(),
+        }])
+        .unwrap()
+    }
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 3, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn overnight_window_lasts_until_next_day() {
+        // 2023-03-06 is Monday
+        let schedule = schedule(&["mon"], "22:00", "06:00");
+
+        assert!(schedule.contains_in(utc(6, 23, 0), &Utc));
+        assert!(schedule.contains_in(utc(7, 5, 59), &Utc));
+        assert!(!schedule.contains_in(utc(7, 6, 0), &Utc));
+        assert!(!schedule.contains_in(utc(6, 5, 0), &Utc));
+        assert!(!schedule.contains_in(utc(7, 23, 0), &Utc));
+    }
+
+    #[test]
+    fn next_edge_is_closest_start_or_end() {
+        let schedule = schedule(&["mon", "wed"], "22:00", "06:00");
+
+        assert_eq!(
+            schedule.next_edge_in(utc(6, 12, 0), &Utc),
+            Some(utc(6, 22, 0))
+        );
+        assert_eq!(
+            schedule.next_edge_in(utc(6, 22, 0), &Utc),
+            Some(utc(7, 6, 0))
+        );
+        assert_eq!(
+            schedule.next_edge_in(utc(7, 6, 0), &Utc),
+            Some(utc(8, 22, 0))
+        );
+
+        let empty = Schedule::new(&[]).unwrap();
+        assert!(empty.contains_in(utc(6, 12, 0), &Utc));
+        assert_eq!(empty.next_edge_in(utc(6, 12, 0), &Utc), None);
+    }
+
+    #[test]
+    fn window_starting_in_skipped_hour_starts_at_transition() {
+        // on 2023-03-26 clocks jump from 02:00 to 03:00
+        let schedule = schedule(&["sun"], "02:30", "04:00");
+
+        assert!(!schedule.contains_in(utc(26, 0, 59), &CentralEurope));
+        assert!(schedule.contains_in(utc(26, 1, 0), &CentralEurope));
+        assert!(!schedule.contains_in(utc(26, 2, 0), &CentralEurope));
+        assert_eq!(
+            schedule.next_edge_in(utc(26, 0, 0), &CentralEurope),
+            Some(utc(26, 1, 0))
+        );
+    }
+
+    #[test]
+    fn window_in_repeated_hour_starts_at_its_first_occurrence() {
+        // on 2023-10-29 clocks go back from 03:00 to 02:00
+        let schedule = schedule(&["sun"], "02:30", "03:00");
+        let at = |hour, minute| Utc.with_ymd_and_hms(2023, 10, 29, hour, minute, 0).unwrap();
+
+        assert!(!schedule.contains_in(at(0, 29), &CentralEurope));
+        assert!(schedule.contains_in(at(0, 30), &CentralEurope));
+        assert!(schedule.contains_in(at(1, 45), &CentralEurope));
+        assert!(!schedule.contains_in(at(2, 0), &CentralEurope));
+    }
+}
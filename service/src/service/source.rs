@@ -0,0 +1,82 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use chrono::prelude::*;
+use tokio::sync::mpsc::{error::SendError, UnboundedSender};
+
+/// Platform specific source of focused window changes
+pub trait ActivitySource: Send + Sync {
+    /// Starts sending focus changes, `None` is sent once source finishes
+    fn start(&mut self, tx: FocusSender);
+
+    /// Window focused now, it does not produce focus change by itself
+    fn foreground(&self) -> Result<ProcessedWindowEvent, String>;
+
+    /// Reads metadata of executable file, results are cached by tracker
+    fn app_metadata(&self, path: &str) -> Result<AppMetadata, String>;
+
+    /// Stops sending focus changes and waits until source finishes
+    fn stop(&mut self);
+
+    /// Milliseconds since the last keyboard or mouse input
+    fn idle_time(&self) -> Result<u32, String>;
+
+    /// Milliseconds since system startup, same clock as offsets of focus changes
+    fn tick_count(&self) -> u32;
+
+    fn startup_timestamp(&self) -> DateTime<Utc>;
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct ProcessedWindowEvent {
+    pub window_title: String,
+    pub pid: u32,
+    pub path: String,
+    pub offset: u32,
+    /// Empty until tracker takes it from metadata cache
+    pub app_title: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct AppMetadata {
+    pub app_title: String,
+    pub publisher: Option<String>,
+    pub version: Option<String>,
+}
+
+/// Sending half of focus changes channel, counts changes waiting to be recorded
+#[derive(Clone, Debug)]
+pub struct FocusSender {
+    tx: UnboundedSender<Option<ProcessedWindowEvent>>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl FocusSender {
+    pub fn new(
+        tx: UnboundedSender<Option<ProcessedWindowEvent>>,
+        queued: Arc<AtomicUsize>,
+    ) -> Self {
+        Self { tx, queued }
+    }
+
+    pub fn send(
+        &self,
+        event: Option<ProcessedWindowEvent>,
+    ) -> Result<(), SendError<Option<ProcessedWindowEvent>>> {
+        let counted = event.is_some();
+
+        // counted before sending, so receiver never sees more changes than queued
+        if counted {
+            self.queued.fetch_add(1, Ordering::SeqCst);
+        }
+
+        self.tx.send(event).inspect_err(|_| {
+            if counted {
+                self.queued.fetch_sub(1, Ordering::SeqCst);
+            }
+        })
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::prelude::*;
+use rocket::{futures::FutureExt, serde::Serialize};
+use sea_orm::prelude::DateTimeUtc;
+use tokio::{sync::watch, time::Instant};
+
+use crate::crash::{self, CrashReport};
+use crate::metrics::Metrics;
+
+use super::{LoopExit, Service};
+
+static INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+static MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// Tracker running at least this long since restart is considered recovered, backoff is reset
+static STABLE_RUN: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum TrackerState {
+    /// Tracking is not run in this mode
+    Disabled,
+    Running,
+    /// Tracker crashed and waits for restart
+    Restarting,
+    Finished,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TrackerHealth {
+    pub state: TrackerState,
+    /// Amount of crashes since start
+    pub restarts: u32,
+    pub running_since: Option<DateTimeUtc>,
+    pub restart_at: Option<DateTimeUtc>,
+    pub last_crash: Option<CrashReport>,
+}
+
+/// Health of supervised tracker, shared with api
+pub struct TrackerMonitor {
+    health: Mutex<TrackerHealth>,
+    /// Focus changes sent by source and not taken by tracker yet
+    queued: Arc<AtomicUsize>,
+    metrics: Arc<Metrics>,
+}
+
+impl Default for TrackerMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrackerMonitor {
+    pub fn new() -> Self {
+        Self {
+            health: Mutex::new(TrackerHealth {
+                state: TrackerState::Disabled,
+                restarts: 0,
+                running_since: None,
+                restart_at: None,
+                last_crash: None,
+            }),
+            queued: Arc::new(AtomicUsize::new(0)),
+            metrics: Arc::new(Metrics::new()),
+        }
+    }
+
+    pub fn health(&self) -> TrackerHealth {
+        self.health.lock().unwrap().clone()
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Counter shared with sender given to source
+    pub fn queue_counter(&self) -> Arc<AtomicUsize> {
+        self.queued.clone()
+    }
+
+    /// Counters of processed focus changes, kept between tracker restarts
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Tracker could not be started or its supervisor panicked, it is not restarted
+    pub fn failed(&self) {
+        let report = crash::take_last_panic();
+
+        self.update(|health| {
+            health.state = TrackerState::Finished;
+            health.running_since = None;
+            health.restart_at = None;
+            health.last_crash = report.or(health.last_crash.take());
+        });
+    }
+
+    pub fn dequeued(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn update(&self, update: impl FnOnce(&mut TrackerHealth)) {
+        update(&mut self.health.lock().unwrap());
+    }
+}
+
+/// Delay before restart, doubled after every crash and reset once tracker ran long enough
+struct Backoff {
+    next: Duration,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self {
+            next: INITIAL_BACKOFF,
+        }
+    }
+
+    /// Delay before restart of tracker which crashed after running for `ran`
+    fn delay(&mut self, ran: Duration) -> Duration {
+        if ran >= STABLE_RUN {
+            self.next = INITIAL_BACKOFF;
+        }
+
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+
+        delay
+    }
+}
+
+/// Runs tracking loop until shutdown, restarting it with exponential backoff after panic or
+/// after its source ended. Tracker keeps its session between restarts
+pub async fn supervise(
+    mut service: Service,
+    monitor: Arc<TrackerMonitor>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut backoff = Backoff::new();
+
+    loop {
+        let started = Instant::now();
+
+        monitor.update(|health| {
+            health.state = TrackerState::Running;
+            health.running_since = Some(Utc::now());
+            health.restart_at = None;
+        });
+
+        let result = AssertUnwindSafe(crash::supervised(service.spin_loop(shutdown.clone())))
+            .catch_unwind()
+            .await;
+
+        // interval of focused app is already closed when source ended
+        let source_ended = match result {
+            Ok(LoopExit::Shutdown) => break,
+            Ok(LoopExit::SourceEnded) => true,
+            Err(_) => false,
+        };
+
+        let delay = backoff.delay(started.elapsed());
+
+        let report = match source_ended {
+            true => {
+                error!("Activity source ended, restarting tracker in {:?}", delay);
+                None
+            }
+            false => {
+                error!("Tracker crashed, restarting in {:?}", delay);
+                crash::take_last_panic()
+            }
+        };
+
+        monitor.update(|health| {
+            health.state = TrackerState::Restarting;
+            health.restarts += 1;
+            health.running_since = None;
+            health.restart_at = Some(Utc::now() + chrono::Duration::from_std(delay).unwrap());
+            health.last_crash = report.or(health.last_crash.take());
+        });
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            Ok(()) = shutdown.changed() => {
+                match source_ended {
+                    true => service.end_session(),
+                    false => service.finish(LoopExit::Shutdown).await,
+                }
+                break;
+            }
+        }
+
+        info!("Restarting tracker");
+
+        if source_ended {
+            service.restart_source().await;
+        }
+    }
+
+    monitor.update(|health| {
+        health.state = TrackerState::Finished;
+        health.running_since = None;
+        health.restart_at = None;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sea_orm::{Database, DatabaseConnection, EntityTrait, QueryOrder};
+
+    use crate::config::Config;
+    use crate::database;
+    use crate::entity::event;
+    use crate::notifier::Notifier;
+
+    use super::super::{
+        rules::Enforcer,
+        source::{ActivitySource, AppMetadata, FocusSender, ProcessedWindowEvent},
+        tracking::TrackingControl,
+    };
+
+    /// Source whose thread panics right after the first start, later starts keep it running
+    struct CrashingSource {
+        starts: Arc<AtomicUsize>,
+        tx: Arc<Mutex<Option<FocusSender>>>,
+    }
+
+    impl ActivitySource for CrashingSource {
+        fn start(&mut self, tx: FocusSender) {
+            if self.starts.fetch_add(1, Ordering::SeqCst) == 0 {
+                std::thread::spawn(move || {
+                    let _tx = tx;
+                    panic!("source thread crashed");
+                })
+                .join()
+                .unwrap_err();
+            } else {
+                *self.tx.lock().unwrap() = Some(tx);
+            }
+        }
+
+        fn foreground(&self) -> Result<ProcessedWindowEvent, String> {
+            Ok(ProcessedWindowEvent {
+                window_title: "main.rs".to_owned(),
+                pid: 1,
+                path: "code.exe".to_owned(),
+                offset: 0,
+                app_title: String::new(),
+            })
+        }
+
+        fn app_metadata(&self, path: &str) -> Result<AppMetadata, String> {
+            Ok(AppMetadata {
+                app_title: path.to_owned(),
+                publisher: None,
+                version: None,
+            })
+        }
+
+        fn stop(&mut self) {
+            self.tx.lock().unwrap().take();
+        }
+
+        fn idle_time(&self) -> Result<u32, String> {
+            Ok(0)
+        }
+
+        fn tick_count(&self) -> u32 {
+            0
+        }
+
+        fn startup_timestamp(&self) -> DateTime<Utc> {
+            Utc::now()
+        }
+    }
+
+    struct FakeEnforcer;
+
+    impl Enforcer for FakeEnforcer {
+        fn enforce(&self, _pid: u32) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    async fn recorded_kinds(db: &DatabaseConnection) -> Vec<(String, event::EventKind)> {
+        event::Entity::find()
+            .order_by_asc(event::Column::Timestamp)
+            .order_by_asc(event::Column::Id)
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.app_title, event.kind))
+            .collect()
+    }
+
+    #[test]
+    fn backoff_doubles_until_limit_and_resets_after_stable_run() {
+        let mut backoff = Backoff::new();
+        let short = Duration::from_secs(1);
+
+        let delays: Vec<u64> = (0..11).map(|_| backoff.delay(short).as_secs()).collect();
+
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300]);
+
+        assert_eq!(backoff.delay(STABLE_RUN), INITIAL_BACKOFF);
+        assert_eq!(backoff.delay(short), INITIAL_BACKOFF * 2);
+    }
+
+    #[tokio::test]
+    async fn ended_source_is_restarted() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let starts = Arc::new(AtomicUsize::new(0));
+        let monitor = Arc::new(TrackerMonitor::new());
+
+        let service = Service::new(
+            db.clone(),
+            &Config::default(),
+            Arc::new(TrackingControl::new()),
+            Arc::new(Notifier::new(&[], db.clone())),
+            Box::new(CrashingSource {
+                starts: starts.clone(),
+                tx: Arc::new(Mutex::new(None)),
+            }),
+            Box::new(FakeEnforcer),
+            monitor.clone(),
+        )
+        .await;
+
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let supervisor = tokio::spawn(supervise(service, monitor.clone(), shutdown));
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while starts.load(Ordering::SeqCst) < 2 || monitor.health().state != TrackerState::Running {
+            assert!(Instant::now() < deadline, "source was not restarted");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let health = monitor.health();
+        assert_eq!(health.restarts, 1);
+        assert!(health.last_crash.is_none());
+
+        shutdown_tx.send(true).unwrap();
+        supervisor.await.unwrap();
+
+        assert_eq!(monitor.health().state, TrackerState::Finished);
+        assert_eq!(
+            recorded_kinds(&db).await,
+            vec![
+                ("".to_owned(), event::EventKind::Shutdown),
+                ("code.exe".to_owned(), event::EventKind::Focus),
+                ("".to_owned(), event::EventKind::Shutdown),
+            ]
+        );
+    }
+}
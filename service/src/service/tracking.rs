@@ -0,0 +1,59 @@
+use chrono::{Duration, Utc};
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::prelude::DateTimeUtc;
+use tokio::sync::watch;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TrackingStatus {
+    pub paused: bool,
+    pub paused_at: Option<DateTimeUtc>,
+    /// Tracking is resumed automatically at this moment, paused until manual resume when missing
+    pub resume_at: Option<DateTimeUtc>,
+}
+
+/// Pause state shared between API and service loop, loop is notified on every change
+pub struct TrackingControl {
+    status: watch::Sender<TrackingStatus>,
+}
+
+impl Default for TrackingControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrackingControl {
+    pub fn new() -> Self {
+        let (status, _) = watch::channel(TrackingStatus::default());
+
+        Self { status }
+    }
+
+    pub fn status(&self) -> TrackingStatus {
+        self.status.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<TrackingStatus> {
+        self.status.subscribe()
+    }
+
+    pub fn pause(&self, duration: Option<Duration>) -> TrackingStatus {
+        let now = Utc::now();
+
+        self.status.send_modify(|status| {
+            // pausing again only changes resume time, pause start stays the same
+            status.paused_at = status.paused_at.or(Some(now));
+            status.paused = true;
+            status.resume_at = duration.map(|duration| now + duration);
+        });
+
+        self.status()
+    }
+
+    pub fn resume(&self) -> TrackingStatus {
+        self.status.send_replace(TrackingStatus::default());
+
+        self.status()
+    }
+}
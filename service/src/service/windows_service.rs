@@ -5,22 +5,26 @@ use chrono::prelude::*;
 
 use windows::{
     core::{HSTRING, PCWSTR},
-    h,
     Win32::{
-        Foundation::{GetLastError, HANDLE, HWND, MAX_PATH},
+        Foundation::{CloseHandle, GetLastError, HANDLE, HWND, MAX_PATH},
         Storage::FileSystem::{GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW},
         System::{
             ProcessStatus::GetModuleFileNameExW,
-            SystemInformation::GetTickCount64,
-            Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+            SystemInformation::{GetTickCount, GetTickCount64},
+            Threading::{
+                OpenProcess, TerminateProcess, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_TERMINATE,
+            },
         },
+        UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO},
         UI::WindowsAndMessaging::{
-            CreateWindowExW, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId,
-            HWND_MESSAGE, WS_DISABLED, WS_EX_NOACTIVATE,
+            CreateWindowExW, GetForegroundWindow, GetWindowTextLengthW, GetWindowTextW,
+            GetWindowThreadProcessId, HWND_MESSAGE, WS_DISABLED, WS_EX_NOACTIVATE,
         },
     },
 };
 
+use super::{rules::Enforcer, source::AppMetadata};
+
 pub struct WindowsService {}
 
 impl WindowsService {
@@ -154,7 +158,21 @@ impl WindowsService {
         Ok(path)
     }
 
-    pub fn get_app_title(path: String) -> Result<String, String> {
+    /// Product description, company and version from version info of executable
+    pub fn get_app_metadata(path: &str) -> Result<AppMetadata, String> {
+        let info = Self::get_file_version_info(path)?;
+
+        let app_title = Self::query_version_string(&info, "FileDescription")
+            .ok_or("Error on retrieving application title".to_owned())?;
+
+        Ok(AppMetadata {
+            app_title,
+            publisher: Self::query_version_string(&info, "CompanyName"),
+            version: Self::query_version_string(&info, "ProductVersion"),
+        })
+    }
+
+    fn get_file_version_info(path: &str) -> Result<Vec<u8>, String> {
         let path_ref = &HSTRING::from(path);
 
         let size = unsafe { GetFileVersionInfoSizeW(path_ref, None) };
@@ -193,38 +211,77 @@ impl WindowsService {
             return Err("Error on retrieving file version info".to_owned());
         };
 
+        Ok(info_buffer)
+    }
+
+    /// Value of version info string, `None` when it is missing or empty
+    fn query_version_string(info: &[u8], name: &str) -> Option<String> {
+        let key = HSTRING::from(format!("\\StringFileInfo\\040904B0\\{}", name));
+
         let mut result_ptr = null_mut();
         let mut result_len = 0;
 
         let success = unsafe {
             VerQueryValueW(
-                info_buffer.as_ptr() as *const std::ffi::c_void,
-                h!("\\StringFileInfo\\040904B0\\FileDescription"),
+                info.as_ptr() as *const std::ffi::c_void,
+                &key,
                 &mut result_ptr,
                 &mut result_len,
             )
         }
         .as_bool();
 
-        if !success {
+        if !success || result_len == 0 {
+            return None;
+        };
+
+        let value = unsafe { slice::from_raw_parts(result_ptr as *const u16, result_len as usize) };
+
+        let value = String::from_utf16_lossy(value);
+
+        // Remove trailing null-terminator
+        let value = value.trim_end_matches('\0').to_owned();
+
+        (!value.is_empty()).then_some(value)
+    }
+
+    pub fn close_handle(handle: HANDLE) {
+        let closed = unsafe { CloseHandle(handle) }.as_bool();
+
+        if !closed {
             let err = unsafe { GetLastError() };
 
-            error!(
-                "Error on retrieving application title. Win error code: {}",
-                err.0
-            );
+            error!("Error on closing handle. Windows error code: {}", err.0);
+        }
+    }
 
-            return Err("Error on retrieving application title".to_owned());
-        };
+    pub fn get_foreground_window() -> HWND {
+        unsafe { GetForegroundWindow() }
+    }
 
-        let title = unsafe { slice::from_raw_parts(result_ptr as *const u16, result_len as usize) };
+    /// Same clock as timestamps of window events
+    pub fn get_tick_count() -> u32 {
+        unsafe { GetTickCount() }
+    }
 
-        let mut title = String::from_utf16_lossy(title);
+    /// Milliseconds since the last keyboard or mouse input of session
+    pub fn get_idle_time() -> Result<u32, String> {
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
 
-        // Remove trailing null-terminator
-        title.pop();
+        if !unsafe { GetLastInputInfo(&mut info) }.as_bool() {
+            let err = unsafe { GetLastError() };
 
-        Ok(title)
+            return Err(format!(
+                "Could not get last input time. Windows error code: {}",
+                err.0
+            ));
+        }
+
+        // both are ticks which wrap around after 49 days
+        Ok(Self::get_tick_count().wrapping_sub(info.dwTime))
     }
 
     pub fn get_startup_timestamp() -> DateTime<Utc> {
@@ -237,3 +294,40 @@ impl WindowsService {
             .and_utc()
     }
 }
+
+impl Enforcer for WindowsService {
+    fn enforce(&self, pid: u32) -> Result<(), String> {
+        let handle = unsafe { OpenProcess(PROCESS_TERMINATE, false, pid) };
+
+        if handle.is_err() {
+            let err = unsafe { GetLastError() };
+
+            error!(
+                "Error on acquiring process handle for termination. Windows error code: {}",
+                err.0
+            );
+
+            return Err("Could not acquire process handle for termination".to_owned());
+        };
+
+        let handle = handle.unwrap();
+
+        let terminated = unsafe { TerminateProcess(handle, 1) }.as_bool();
+
+        if !terminated {
+            let err = unsafe { GetLastError() };
+
+            error!(
+                "Error on terminating process. Windows error code: {}",
+                err.0
+            );
+        }
+
+        unsafe { CloseHandle(handle) };
+
+        match terminated {
+            true => Ok(()),
+            false => Err("Could not terminate process".to_owned()),
+        }
+    }
+}
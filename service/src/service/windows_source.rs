@@ -0,0 +1,277 @@
+use std::thread::JoinHandle;
+
+use chrono::prelude::*;
+use once_cell::sync::OnceCell;
+use windows::{
+    w,
+    Win32::{
+        Foundation::{GetLastError, HWND, LPARAM, LRESULT, WPARAM},
+        UI::{
+            Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK},
+            WindowsAndMessaging::{
+                DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, PostMessageW,
+                PostQuitMessage, RegisterClassW, CS_GLOBALCLASS, EVENT_SYSTEM_FOREGROUND, MSG,
+                WM_CLOSE, WM_DESTROY, WNDCLASSW,
+            },
+        },
+    },
+};
+
+use super::{
+    source::{ActivitySource, AppMetadata, FocusSender, ProcessedWindowEvent},
+    windows_service::WindowsService,
+};
+
+thread_local! {
+    static TX: OnceCell<FocusSender>= OnceCell::new()
+}
+
+/// Foreground window changes reported by WinEventHook on messaging thread
+pub struct WindowsSource {
+    thread_handle: Option<JoinHandle<()>>,
+    /// Messaging window of running thread, closing it finishes message loop
+    messaging_window: Option<HWND>,
+}
+
+impl Default for WindowsSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WindowsSource {
+    pub fn new() -> Self {
+        Self {
+            thread_handle: None,
+            messaging_window: None,
+        }
+    }
+
+    fn process_event(event: RawWindowEvent) -> Result<ProcessedWindowEvent, String> {
+        let window_title = WindowsService::get_window_title(event.event_id)?;
+        let pid = WindowsService::get_process_id(event.event_id)?;
+        let process_handle = WindowsService::get_process_handle(pid)?;
+        let path = WindowsService::get_process_executable_path(process_handle);
+
+        WindowsService::close_handle(process_handle);
+
+        // app title is taken from metadata cache by tracker, version info is not read here
+        Ok(ProcessedWindowEvent {
+            window_title,
+            pid,
+            path: path?,
+            offset: event.timestamp,
+            app_title: String::new(),
+        })
+    }
+
+    fn setup_event_messaging_thread(
+        tx: FocusSender,
+        window_tx: std::sync::mpsc::Sender<HWND>,
+    ) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            TX.with(|f| f.set(tx.clone())).unwrap();
+
+            let hook = unsafe {
+                SetWinEventHook(
+                    EVENT_SYSTEM_FOREGROUND,
+                    EVENT_SYSTEM_FOREGROUND,
+                    None,
+                    Some(Self::win_foreground_change_callback),
+                    0,
+                    0,
+                    0,
+                )
+            };
+
+            if hook.0 == 0 {
+                panic!("Could not setup WinEventHook");
+            }
+
+            info!("WinEventHook setup successful!");
+
+            // window class creation and registration for unknown reason doesn't work when separated into another function, hence must stay here
+
+            let mut wc = WNDCLASSW::default();
+            let class_name = w!("randomclassname123");
+            wc.lpszClassName = class_name;
+            wc.lpfnWndProc = Some(Self::window_messaging_proc);
+            wc.style = CS_GLOBALCLASS;
+            let wc_ptr: *const WNDCLASSW = &wc;
+
+            let class_register_result = unsafe { RegisterClassW(wc_ptr) };
+
+            if class_register_result == 0 {
+                let err = unsafe { GetLastError() };
+
+                panic!(
+                    "Error on creating window class for messaging window. Windows error code: {}",
+                    err.0
+                );
+            };
+
+            let hwnd = WindowsService::create_messaging_window(wc.lpszClassName);
+
+            window_tx.send(hwnd).unwrap();
+
+            let mut msg: MSG = MSG::default();
+            let msg_ptr: *mut MSG = &mut msg;
+
+            loop {
+                let result = unsafe { GetMessageW(msg_ptr, hwnd, 0, 0) };
+
+                // WM_QUIT or error
+                if result.0 == -1 || result.0 == 0 {
+                    break;
+                }
+
+                unsafe { DispatchMessageW(msg_ptr) };
+            }
+
+            info!(
+                "Got message loop shut down, no messages will be passed further. Preparing to close message thread"
+            );
+
+            TX.with(|f| {
+                let tx = f.get().unwrap();
+                // service might be already gone
+                let _ = tx.send(None);
+            });
+
+            unsafe { UnhookWinEvent(hook) };
+        })
+    }
+
+    pub extern "system" fn win_foreground_change_callback(
+        child_id: HWINEVENTHOOK,
+        hook_handle: u32,
+        event_id: HWND,
+        window_handle: i32,
+        object_id: i32,
+        thread_id: u32,
+        timestamp: u32,
+    ) {
+        let event = RawWindowEvent {
+            child_id,
+            hook_handle,
+            event_id,
+            window_handle,
+            object_id,
+            thread_id,
+            timestamp,
+        };
+
+        info!("New event received");
+
+        let processed_event = match Self::process_event(event) {
+            Ok(processed_event) => processed_event,
+            Err(err) => {
+                error!("Error on processing event: {}", err);
+                return;
+            }
+        };
+
+        TX.with(|f| {
+            let tx: &FocusSender = f.get().unwrap();
+
+            tx.send(Some(processed_event)).unwrap();
+        });
+    }
+
+    pub extern "system" fn window_messaging_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match msg {
+            WM_CLOSE => {
+                unsafe { PostQuitMessage(0) };
+                unsafe { DestroyWindow(hwnd) };
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                unsafe { PostQuitMessage(0) };
+                LRESULT(0)
+            }
+
+            _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+        }
+    }
+}
+
+impl ActivitySource for WindowsSource {
+    fn start(&mut self, tx: FocusSender) {
+        let (window_tx, window_rx) = std::sync::mpsc::channel();
+
+        self.thread_handle = Some(Self::setup_event_messaging_thread(tx, window_tx));
+        self.messaging_window = window_rx.recv().ok();
+    }
+
+    fn foreground(&self) -> Result<ProcessedWindowEvent, String> {
+        Self::process_event(RawWindowEvent {
+            child_id: HWINEVENTHOOK(0),
+            hook_handle: EVENT_SYSTEM_FOREGROUND,
+            event_id: WindowsService::get_foreground_window(),
+            window_handle: 0,
+            object_id: 0,
+            thread_id: 0,
+            timestamp: WindowsService::get_tick_count(),
+        })
+    }
+
+    fn app_metadata(&self, path: &str) -> Result<AppMetadata, String> {
+        WindowsService::get_app_metadata(path)
+    }
+
+    fn stop(&mut self) {
+        if let Some(hwnd) = self.messaging_window.take() {
+            let posted = unsafe { PostMessageW(hwnd, WM_CLOSE, WPARAM(0), LPARAM(0)) };
+
+            if !posted.as_bool() {
+                let err = unsafe { GetLastError() };
+
+                error!(
+                    "Error on closing messaging window. Windows error code: {}",
+                    err.0
+                );
+                return;
+            }
+        }
+
+        if let Some(thread_handle) = self.thread_handle.take() {
+            thread_handle.join().unwrap();
+        }
+    }
+
+    fn idle_time(&self) -> Result<u32, String> {
+        WindowsService::get_idle_time()
+    }
+
+    fn tick_count(&self) -> u32 {
+        WindowsService::get_tick_count()
+    }
+
+    fn startup_timestamp(&self) -> DateTime<Utc> {
+        WindowsService::get_startup_timestamp()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RawWindowEvent {
+    /// Hook ID
+    /// If this value is [`CHILDID_SELF`], the event was triggered by the object; otherwise, this value is the child ID of the element that triggered the event.
+    pub child_id: HWINEVENTHOOK,
+    /// Handle to the shared event hook function.
+    pub hook_handle: u32,
+    /// handle to newly appeared window
+    pub event_id: HWND,
+    /// Handle to the window that generates the event, or `NULL` if no window is associated with the event.
+    pub window_handle: i32,
+    /// Identifies the object associated with the event.
+    pub object_id: i32,
+    /// Identifies the thread that generated the event.
+    pub thread_id: u32,
+    /// Specifies the time since system startup, in milliseconds, that the event was generated.
+    pub timestamp: u32,
+}
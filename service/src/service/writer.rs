@@ -0,0 +1,649 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use rocket::serde::json;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::NotSet, DatabaseConnection, DatabaseTransaction, DbErr,
+    IntoActiveModel, Set, TransactionTrait, TryIntoModel,
+};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::StorageConfig;
+use crate::entity::*;
+use crate::metrics::{FailureStage, Metrics};
+use crate::notifier::{Notification, Notifier};
+use crate::usage;
+
+/// Most events written within one transaction
+static BATCH_SIZE: usize = 64;
+
+/// Attempts of writing batch while database is busy, before it is spilled into journal
+static BUSY_ATTEMPTS: u32 = 5;
+
+static INITIAL_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// How often journaled events are tried to be written while no new events come
+static REPLAY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Event waiting to be written
+pub struct PendingEvent {
+    pub record: event::ActiveModel,
+    /// Moment focus change was received, `None` for marker events
+    pub received_at: Option<Instant>,
+}
+
+enum WriteRequest {
+    Event(Box<PendingEvent>),
+    /// Answered once every event sent before is written or journaled
+    Flush(oneshot::Sender<()>),
+}
+
+/// Sending half of bounded write buffer
+#[derive(Clone)]
+pub struct EventWriterHandle {
+    tx: mpsc::Sender<WriteRequest>,
+}
+
+impl EventWriterHandle {
+    /// Waits for free space in buffer when it is full
+    pub async fn write(&self, event: PendingEvent) {
+        if self
+            .tx
+            .send(WriteRequest::Event(Box::new(event)))
+            .await
+            .is_err()
+        {
+            error!("Event writer is finished, event is dropped");
+        }
+    }
+
+    /// Waits until every event sent before is written or journaled
+    pub async fn flush(&self) {
+        let (tx, rx) = oneshot::channel();
+
+        if self.tx.send(WriteRequest::Flush(tx)).await.is_ok() {
+            let _ = rx.await;
+        }
+    }
+}
+
+/// Writes events in batches within transactions, retrying while database is busy. Events which
+/// could not be written are appended to journal and written before newer ones on recovery
+pub struct EventWriter {
+    rx: mpsc::Receiver<WriteRequest>,
+    db: DatabaseConnection,
+    journal: Journal,
+    notifier: Arc<Notifier>,
+    metrics: Arc<Metrics>,
+}
+
+impl EventWriter {
+    pub fn spawn(
+        db: DatabaseConnection,
+        config: &StorageConfig,
+        notifier: Arc<Notifier>,
+        metrics: Arc<Metrics>,
+    ) -> EventWriterHandle {
+        let (tx, rx) = mpsc::channel(config.buffer_size.max(1));
+
+        let writer = Self {
+            rx,
+            db,
+            journal: Journal::new(PathBuf::from(&config.journal)),
+            notifier,
+            metrics,
+        };
+
+        tokio::spawn(writer.run());
+
+        EventWriterHandle { tx }
+    }
+
+    async fn run(mut self) {
+        self.replay().await;
+
+        loop {
+            let request = match self.journal.is_pending() {
+                true => tokio::select! {
+                    request = self.rx.recv() => request,
+                    _ = tokio::time::sleep(REPLAY_INTERVAL) => {
+                        self.replay().await;
+                        continue;
+                    }
+                },
+                false => self.rx.recv().await,
+            };
+
+            let Some(request) = request else {
+                break;
+            };
+
+            let mut batch = vec![];
+            let mut flushes = vec![];
+
+            let mut next = Some(request);
+
+            while let Some(request) = next {
+                match request {
+                    WriteRequest::Event(event) => batch.push(*event),
+                    WriteRequest::Flush(tx) => flushes.push(tx),
+                }
+
+                next = match batch.len() < BATCH_SIZE {
+                    true => self.rx.try_recv().ok(),
+                    false => None,
+                };
+            }
+
+            self.write_batch(batch).await;
+
+            for flush in flushes {
+                let _ = flush.send(());
+            }
+        }
+    }
+
+    async fn write_batch(&mut self, batch: Vec<PendingEvent>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        // journaled events are older, they are written first to keep order of intervals
+        if self.journal.is_pending() {
+            self.replay().await;
+        }
+
+        if self.journal.is_pending() {
+            self.spill(&batch);
+            return;
+        }
+
+        let records: Vec<event::ActiveModel> =
+            batch.iter().map(|event| event.record.clone()).collect();
+
+        match self.insert_with_retry(records).await {
+            Ok(events) => self.written(&batch, events),
+            Err(err) if is_unavailable(&err) => {
+                warn!(
+                    "Database is unavailable, {} events are written into journal: {}",
+                    batch.len(),
+                    err
+                );
+                self.spill(&batch);
+            }
+            Err(err) if batch.len() > 1 => {
+                warn!(
+                    "Batch of {} events is rejected, writing them one by one: {}",
+                    batch.len(),
+                    err
+                );
+                self.write_one_by_one(batch).await;
+            }
+            Err(err) => {
+                self.metrics.failed(FailureStage::InsertEvent);
+                error!("Error on inserting event into database: {}", err);
+            }
+        }
+    }
+
+    /// Rejected event of batch is dropped alone, the rest of batch is still written
+    async fn write_one_by_one(&mut self, batch: Vec<PendingEvent>) {
+        for (index, pending) in batch.iter().enumerate() {
+            match self.insert_with_retry(vec![pending.record.clone()]).await {
+                Ok(events) => self.written(std::slice::from_ref(pending), events),
+                Err(err) if is_unavailable(&err) => {
+                    // events after this one go into journal too, so their order is kept
+                    warn!(
+                        "Database is unavailable, {} events are written into journal: {}",
+                        batch.len() - index,
+                        err
+                    );
+                    self.spill(&batch[index..]);
+                    return;
+                }
+                Err(err) => {
+                    self.metrics.failed(FailureStage::InsertEvent);
+                    error!("Error on inserting event into database: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Tracked events are counted as processed and sent as notifications once written
+    fn written(&self, batch: &[PendingEvent], events: Vec<event::Model>) {
+        for (pending, event) in batch.iter().zip(events) {
+            if let Some(received_at) = pending.received_at {
+                self.metrics.processed();
+                self.metrics.observe_processing(received_at.elapsed());
+                self.notifier.notify(Notification::Focus(event));
+            }
+        }
+    }
+
+    /// Writes journaled events, journal is cleared once they are in database
+    async fn replay(&mut self) {
+        if !self.journal.is_pending() {
+            return;
+        }
+
+        let events = match self.journal.read() {
+            Ok(events) => events,
+            Err(err) => {
+                error!("Could not read event journal: {}", err);
+                self.set_journal_aside(0);
+                return;
+            }
+        };
+
+        let count = events.len();
+
+        let records = events
+            .into_iter()
+            .map(|event| {
+                let mut record = event.into_active_model().reset_all();
+                record.id = NotSet;
+                record
+            })
+            .collect();
+
+        match self.insert_with_retry(records).await {
+            Ok(events) => {
+                info!("Written {} journaled events into database", events.len());
+
+                if let Err(err) = self.journal.clear() {
+                    error!("Could not clear event journal: {}", err);
+                }
+            }
+            Err(err) if is_unavailable(&err) => {
+                warn!("Journaled events are not written yet: {}", err)
+            }
+            Err(err) => {
+                // rejected rows would keep every newer event out of database
+                error!("Journaled events are rejected by database: {}", err);
+                self.set_journal_aside(count);
+            }
+        }
+    }
+
+    /// Journal which can not be written is kept for inspection, newer events go into database
+    fn set_journal_aside(&mut self, count: usize) {
+        for _ in 0..count {
+            self.metrics.failed(FailureStage::Journal);
+        }
+
+        match self.journal.set_aside() {
+            Ok(path) => error!("Event journal is moved to {}", path.display()),
+            Err(err) => error!("Could not move event journal aside: {}", err),
+        }
+    }
+
+    async fn insert_with_retry(
+        &self,
+        records: Vec<event::ActiveModel>,
+    ) -> Result<Vec<event::Model>, DbErr> {
+        let mut delay = INITIAL_RETRY_DELAY;
+        let mut attempt = 1;
+
+        loop {
+            let started_at = Instant::now();
+            let result = self.insert(records.clone()).await;
+            self.metrics.observe_db_write(started_at.elapsed());
+
+            match result {
+                Err(err) if is_unavailable(&err) && attempt < BUSY_ATTEMPTS => {
+                    warn!("Database is busy, retrying in {:?}: {}", delay, err);
+
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn insert(&self, records: Vec<event::ActiveModel>) -> Result<Vec<event::Model>, DbErr> {
+        let txn = self.db.begin().await?;
+        let mut events = Vec::with_capacity(records.len());
+
+        for record in records {
+            let event = record.insert(&txn).await?;
+            self.record_usage(&txn, &event).await?;
+            events.push(event);
+        }
+
+        txn.commit().await?;
+
+        Ok(events)
+    }
+
+    /// Daily usage is updated within transaction of the insert, so refresh of days can not run in
+    /// between. Its failure is rolled back to savepoint and does not lose the event
+    async fn record_usage(
+        &self,
+        txn: &DatabaseTransaction,
+        event: &event::Model,
+    ) -> Result<(), DbErr> {
+        let savepoint = txn.begin().await?;
+
+        match usage::record_closed_interval(&savepoint, event).await {
+            Ok(()) => savepoint.commit().await,
+            Err(err) => {
+                self.metrics.failed(FailureStage::DailyUsage);
+                error!("Error on updating daily usage: {}", err);
+
+                savepoint.rollback().await
+            }
+        }
+    }
+
+    fn spill(&mut self, batch: &[PendingEvent]) {
+        let events: Result<Vec<event::Model>, DbErr> = batch
+            .iter()
+            .map(|event| {
+                let mut record = event.record.clone();
+                record.id = Set(0);
+                record.try_into_model()
+            })
+            .collect();
+
+        let result = events
+            .map_err(|err| err.to_string())
+            .and_then(|events| self.journal.append(&events).map_err(|err| err.to_string()));
+
+        match result {
+            Ok(()) => {
+                for _ in batch {
+                    self.metrics.journaled();
+                }
+            }
+            Err(err) => {
+                for _ in batch {
+                    self.metrics.failed(FailureStage::Journal);
+                }
+                error!(
+                    "Could not write events into journal, they are lost: {}",
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// Busy or locked database and failed connection are expected to recover
+fn is_unavailable(err: &DbErr) -> bool {
+    match err {
+        DbErr::ConnectionAcquire | DbErr::Conn(_) => true,
+        err => {
+            let message = err.to_string();
+
+            message.contains("database is locked") || message.contains("database table is locked")
+        }
+    }
+}
+
+/// Append-only file of events as JSON lines
+struct Journal {
+    path: PathBuf,
+    pending: bool,
+}
+
+impl Journal {
+    fn new(path: PathBuf) -> Self {
+        let pending = fs::metadata(&path)
+            .map(|metadata| metadata.len() > 0)
+            .unwrap_or(false);
+
+        Self { path, pending }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    fn append(&mut self, events: &[event::Model]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        let mut lines = String::new();
+
+        for event in events {
+            lines.push_str(&json::to_string(event).unwrap());
+            lines.push('\n');
+        }
+
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()?;
+
+        self.pending = true;
+
+        Ok(())
+    }
+
+    /// Line cut by crash while it was written is skipped
+    fn read(&self) -> io::Result<Vec<event::Model>> {
+        let file = fs::File::open(&self.path)?;
+        let mut events = vec![];
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+
+            match json::from_str(&line) {
+                Ok(event) => events.push(event),
+                Err(err) => warn!("Skipped broken line of event journal: {}", err),
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Renames journal to `<journal>.rejected-<timestamp>`, it is not replayed anymore
+    fn set_aside(&mut self) -> io::Result<PathBuf> {
+        self.pending = false;
+
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".rejected-{}", Utc::now().format("%Y%m%d%H%M%S")));
+        let path = PathBuf::from(path);
+
+        fs::rename(&self.path, &path)?;
+
+        Ok(path)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        // events are in database already, they must not be written again
+        self.pending = false;
+
+        fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sea_orm::{Database, EntityTrait};
+
+    use crate::database;
+
+    async fn setup() -> (DatabaseConnection, i32) {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+
+        let session = session::ActiveModel {
+            datetime: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        (db, session.id)
+    }
+
+    fn journal_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "productivity-journal-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn spawn_writer(db: &DatabaseConnection, path: &std::path::Path) -> EventWriterHandle {
+        let config = StorageConfig {
+            buffer_size: 4,
+            journal: path.to_string_lossy().into_owned(),
+        };
+
+        EventWriter::spawn(
+            db.clone(),
+            &config,
+            Arc::new(Notifier::new(&[], db.clone())),
+            Arc::new(Metrics::new()),
+        )
+    }
+
+    async fn write(writer: &EventWriterHandle, event: event::Model) {
+        let mut record = event.into_active_model().reset_all();
+        record.id = NotSet;
+
+        writer
+            .write(PendingEvent {
+                record,
+                received_at: Some(Instant::now()),
+            })
+            .await;
+        writer.flush().await;
+    }
+
+    async fn recorded(db: &DatabaseConnection) -> Vec<String> {
+        event::Entity::find()
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.app_title)
+            .collect()
+    }
+
+    fn journaled_event(app_title: &str, session_id: i32) -> event::Model {
+        event::Model {
+            id: 0,
+            path: format!("C:\\{}", app_title),
+            title: String::new(),
+            timestamp: Utc::now(),
+            app_title: app_title.to_owned(),
+            offset: 0,
+            session_id,
+            kind: event::EventKind::Focus,
+            in_schedule: true,
+            category: None,
+            project: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn journaled_events_are_written_before_new_ones() {
+        let (db, session_id) = setup().await;
+        let path = journal_path("replay");
+
+        let mut journal = Journal::new(path.clone());
+        journal
+            .append(&[
+                journaled_event("code.exe", session_id),
+                journaled_event("firefox.exe", session_id),
+            ])
+            .unwrap();
+
+        let writer = spawn_writer(&db, &path);
+
+        write(&writer, journaled_event("explorer.exe", session_id)).await;
+
+        assert_eq!(
+            recorded(&db).await,
+            vec!["code.exe", "firefox.exe", "explorer.exe"]
+        );
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn rejected_journal_is_set_aside() {
+        let (db, session_id) = setup().await;
+        let path = journal_path("rejected");
+
+        // session does not exist, foreign key rejects journaled event
+        let mut journal = Journal::new(path.clone());
+        journal
+            .append(&[journaled_event("code.exe", session_id + 1)])
+            .unwrap();
+
+        let writer = spawn_writer(&db, &path);
+
+        write(&writer, journaled_event("explorer.exe", session_id)).await;
+        write(&writer, journaled_event("firefox.exe", session_id)).await;
+
+        assert_eq!(recorded(&db).await, vec!["explorer.exe", "firefox.exe"]);
+        assert!(!path.exists());
+
+        let dir = path.parent().unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+
+        let rejected: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with(&format!("{}.rejected-", name))
+            })
+            .collect();
+
+        assert_eq!(rejected.len(), 1);
+
+        for path in rejected {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_event_does_not_drop_rest_of_batch() {
+        let (db, session_id) = setup().await;
+        let path = journal_path("batch");
+
+        let writer = spawn_writer(&db, &path);
+
+        // events are sent without flush in between, so they are written in one batch
+        for (app_title, session_id) in [
+            ("code.exe", session_id),
+            ("missing.exe", session_id + 1),
+            ("firefox.exe", session_id),
+        ] {
+            let mut record = journaled_event(app_title, session_id)
+                .into_active_model()
+                .reset_all();
+            record.id = NotSet;
+
+            writer
+                .write(PendingEvent {
+                    record,
+                    received_at: Some(Instant::now()),
+                })
+                .await;
+        }
+
+        writer.flush().await;
+
+        assert_eq!(recorded(&db).await, vec!["code.exe", "firefox.exe"]);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,585 @@
+use chrono::{prelude::*, Duration};
+use sea_orm::{
+    prelude::DateTimeUtc, ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect,
+};
+
+use crate::entity::*;
+use crate::service::filter::matches_app;
+
+/// Time between event and the next event of the same session
+#[derive(Clone, Debug)]
+pub struct Interval {
+    pub start: DateTimeUtc,
+    pub end: DateTimeUtc,
+    pub event: event::Model,
+}
+
+impl Interval {
+    /// Only focused windows are counted, excluded, paused and off schedule time is not
+    pub fn is_counted(&self) -> bool {
+        self.event.kind == event::EventKind::Focus
+    }
+}
+
+/// Builds intervals from events ordered by timestamp. Last event of `open_session` lasts until `now`,
+/// last events of finished sessions have unknown end and produce no interval
+pub fn build_intervals(
+    events: Vec<event::Model>,
+    open_session: Option<i32>,
+    now: DateTimeUtc,
+) -> Vec<Interval> {
+    let mut intervals = vec![];
+    let mut events = events.into_iter().peekable();
+
+    while let Some(event) = events.next() {
+        let end = match events.peek() {
+            Some(next) if next.session_id == event.session_id => next.timestamp,
+            _ if open_session == Some(event.session_id) => now,
+            _ => continue,
+        };
+
+        intervals.push(Interval {
+            start: event.timestamp,
+            end: end.max(event.timestamp),
+            event,
+        });
+    }
+
+    intervals
+}
+
+/// Cuts intervals to given range, dropping ones outside of it
+pub fn clip_intervals(
+    intervals: Vec<Interval>,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Vec<Interval> {
+    intervals
+        .into_iter()
+        .filter(|interval| interval.start < to && interval.end > from)
+        .map(|interval| Interval {
+            start: interval.start.max(from),
+            end: interval.end.min(to),
+            event: interval.event,
+        })
+        .collect()
+}
+
+/// Intervals of all sessions overlapping given range, cut to it
+pub async fn load_intervals(
+    db: &impl ConnectionTrait,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<Vec<Interval>, DbErr> {
+    load(db, from, to, true).await
+}
+
+/// Same as `load_intervals`, without interval of the last event which is still open
+pub async fn load_closed_intervals(
+    db: &impl ConnectionTrait,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<Vec<Interval>, DbErr> {
+    load(db, from, to, false).await
+}
+
+async fn load(
+    db: &impl ConnectionTrait,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+    include_open: bool,
+) -> Result<Vec<Interval>, DbErr> {
+    // interval started before range start might still last within the range
+    let previous_event = event::Entity::find()
+        .filter(event::Column::Timestamp.lt(from))
+        .order_by_desc(event::Column::Timestamp)
+        .order_by_desc(event::Column::Id)
+        .limit(1)
+        .one(db)
+        .await?;
+
+    let mut events = event::Entity::find()
+        .filter(event::Column::Timestamp.gte(from))
+        .filter(event::Column::Timestamp.lt(to))
+        .order_by_asc(event::Column::Timestamp)
+        .order_by_asc(event::Column::Id)
+        .all(db)
+        .await?;
+
+    // the next event after the range finishes the last interval within it
+    let next_event = event::Entity::find()
+        .filter(event::Column::Timestamp.gte(to))
+        .order_by_asc(event::Column::Timestamp)
+        .order_by_asc(event::Column::Id)
+        .limit(1)
+        .one(db)
+        .await?;
+
+    let open_session = match include_open {
+        true => session::Entity::find()
+            .order_by_desc(session::Column::Id)
+            .limit(1)
+            .one(db)
+            .await?
+            .map(|session| session.id),
+        false => None,
+    };
+
+    if let Some(previous_event) = previous_event {
+        events.insert(0, previous_event);
+    }
+
+    events.extend(next_event);
+
+    let intervals = build_intervals(events, open_session, Utc::now());
+
+    Ok(clip_intervals(intervals, from, to))
+}
+
+/// Part of counted focus interval, attributed to external activity reported for the same time
+#[derive(Clone, Debug)]
+pub struct Segment<'a> {
+    pub start: DateTimeUtc,
+    pub end: DateTimeUtc,
+    pub event: &'a event::Model,
+    pub activity: Option<&'a activity::Model>,
+}
+
+impl Segment<'_> {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// Splits counted focus intervals by overlapping activities of the same app (or of any app when
+/// activity has none). When activities overlap, the latest started one is used. Focus intervals
+/// stay the source of truth for time, activities outside of them are not counted
+pub fn attribute_activities<'a>(
+    intervals: &'a [Interval],
+    activities: &'a [activity::Model],
+) -> Vec<Segment<'a>> {
+    let mut segments = vec![];
+
+    for interval in intervals.iter().filter(|interval| interval.is_counted()) {
+        let relevant: Vec<&activity::Model> = activities
+            .iter()
+            .filter(|activity| activity.timestamp < interval.end && activity.end > interval.start)
+            .filter(|activity| {
+                activity.app.as_ref().is_none_or(|app| {
+                    matches_app(app, &interval.event.app_title, &interval.event.path)
+                })
+            })
+            .collect();
+
+        let mut boundaries = vec![interval.start, interval.end];
+        for activity in &relevant {
+            boundaries.push(activity.timestamp.clamp(interval.start, interval.end));
+            boundaries.push(activity.end.clamp(interval.start, interval.end));
+        }
+
+        boundaries.sort();
+        boundaries.dedup();
+
+        for window in boundaries.windows(2) {
+            let (start, end) = (window[0], window[1]);
+
+            let activity = relevant
+                .iter()
+                .filter(|activity| activity.timestamp <= start && activity.end >= end)
+                .max_by_key(|activity| activity.timestamp)
+                .copied();
+
+            segments.push(Segment {
+                start,
+                end,
+                event: &interval.event,
+                activity,
+            });
+        }
+    }
+
+    segments
+}
+
+/// Manual entry cut to a range, running timer lasts until now
+#[derive(Clone, Debug)]
+pub struct ManualInterval {
+    pub start: DateTimeUtc,
+    pub end: DateTimeUtc,
+    pub entry: manual_entry::Model,
+}
+
+impl ManualInterval {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// Manual entries overlapping given range, cut to it. When manual entries overlap each other,
+/// the latest started one is used for the common part
+pub async fn load_manual_intervals(
+    db: &impl ConnectionTrait,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<Vec<ManualInterval>, DbErr> {
+    let entries = manual_entry::Entity::find()
+        .filter(manual_entry::Column::Start.lt(to))
+        .filter(
+            Condition::any()
+                .add(manual_entry::Column::End.gt(from))
+                .add(manual_entry::Column::End.is_null()),
+        )
+        .order_by_asc(manual_entry::Column::Start)
+        .all(db)
+        .await?;
+
+    Ok(build_manual_intervals(entries, from, to, Utc::now()))
+}
+
+pub fn build_manual_intervals(
+    entries: Vec<manual_entry::Model>,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+    now: DateTimeUtc,
+) -> Vec<ManualInterval> {
+    let ranges: Vec<(DateTimeUtc, DateTimeUtc)> = entries
+        .iter()
+        .map(|entry| {
+            let end = entry.end.unwrap_or(now).max(entry.start);
+            (entry.start.max(from), end.min(to))
+        })
+        .collect();
+
+    let mut intervals = vec![];
+
+    for (index, entry) in entries.iter().enumerate() {
+        let (start, end) = ranges[index];
+
+        let later: Vec<(DateTimeUtc, DateTimeUtc)> = entries
+            .iter()
+            .zip(&ranges)
+            .enumerate()
+            .filter(|(other, (other_entry, _))| (other_entry.start, *other) > (entry.start, index))
+            .map(|(_, (_, range))| *range)
+            .collect();
+
+        for (start, end) in subtract_ranges(start, end, &later) {
+            intervals.push(ManualInterval {
+                start,
+                end,
+                entry: entry.clone(),
+            });
+        }
+    }
+
+    intervals.sort_by_key(|interval| interval.start);
+
+    intervals
+}
+
+/// Manual entries override automatic tracking, so segments lose time covered by them
+pub fn exclude_manual<'a>(
+    segments: Vec<Segment<'a>>,
+    manual: &[ManualInterval],
+) -> Vec<Segment<'a>> {
+    let ranges: Vec<(DateTimeUtc, DateTimeUtc)> = manual
+        .iter()
+        .map(|interval| (interval.start, interval.end))
+        .collect();
+
+    segments
+        .into_iter()
+        .flat_map(|segment| {
+            subtract_ranges(segment.start, segment.end, &ranges)
+                .into_iter()
+                .map(move |(start, end)| Segment {
+                    start,
+                    end,
+                    ..segment.clone()
+                })
+        })
+        .collect()
+}
+
+/// Parts of `start..end` not covered by any of `ranges`
+pub fn subtract_ranges(
+    start: DateTimeUtc,
+    end: DateTimeUtc,
+    ranges: &[(DateTimeUtc, DateTimeUtc)],
+) -> Vec<(DateTimeUtc, DateTimeUtc)> {
+    let mut parts = vec![(start, end)];
+
+    for (range_start, range_end) in ranges {
+        parts = parts
+            .into_iter()
+            .flat_map(|(start, end)| [(start, end.min(*range_start)), (start.max(*range_end), end)])
+            .filter(|(start, end)| start < end)
+            .collect();
+    }
+
+    parts
+}
+
+/// Aggregates of events removed by retention policy overlapping given range. Duration of
+/// aggregate whose period is covered only partially is prorated by the covered part
+pub async fn load_aggregates(
+    db: &impl ConnectionTrait,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<Vec<usage_aggregate::Model>, DbErr> {
+    // daily aggregate is the longest one, local day lasts at most 25 hours
+    let aggregates = usage_aggregate::Entity::find()
+        .filter(usage_aggregate::Column::Start.gt(from - Duration::hours(25)))
+        .filter(usage_aggregate::Column::Start.lt(to))
+        .all(db)
+        .await?;
+
+    Ok(aggregates
+        .into_iter()
+        .filter_map(|aggregate| prorate_aggregate(aggregate, from, to))
+        .collect())
+}
+
+/// End of period of aggregate
+pub fn aggregate_end(aggregate: &usage_aggregate::Model) -> DateTimeUtc {
+    match aggregate.period {
+        usage_aggregate::AggregatePeriod::Hour => aggregate.start + Duration::hours(1),
+        usage_aggregate::AggregatePeriod::Day => next_day_start(aggregate.start),
+    }
+}
+
+/// Aggregate with duration of the part of its period within range, `None` when outside of it
+pub fn prorate_aggregate(
+    mut aggregate: usage_aggregate::Model,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Option<usage_aggregate::Model> {
+    let end = aggregate_end(&aggregate);
+    let covered = (end.min(to) - aggregate.start.max(from)).num_milliseconds();
+    let period = (end - aggregate.start).num_milliseconds();
+
+    if covered <= 0 {
+        return None;
+    }
+
+    if covered < period {
+        aggregate.duration = (aggregate.duration as i64 * covered / period) as u32;
+    }
+
+    Some(aggregate)
+}
+
+/// Activities overlapping given range
+pub async fn load_activities(
+    db: &impl ConnectionTrait,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<Vec<activity::Model>, DbErr> {
+    activity::Entity::find()
+        .filter(activity::Column::Timestamp.lt(to))
+        .filter(activity::Column::End.gt(from))
+        .order_by_asc(activity::Column::Timestamp)
+        .all(db)
+        .await
+}
+
+/// RFC 3339 datetime, or date which means start of local day
+pub fn parse_datetime(value: &str) -> Result<DateTimeUtc, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("Expected RFC 3339 datetime or date, got {}", value))?;
+
+    Ok(local_midnight(date))
+}
+
+/// Start of local day containing `at`
+pub fn day_start(at: DateTimeUtc) -> DateTimeUtc {
+    let date = at.with_timezone(&Local).date_naive();
+
+    local_midnight(date)
+}
+
+/// Start of local week (Monday) containing `at`
+pub fn week_start(at: DateTimeUtc) -> DateTimeUtc {
+    let date = at.with_timezone(&Local).date_naive();
+    let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+
+    local_midnight(monday)
+}
+
+/// Start of local day following the one containing `at`
+pub fn next_day_start(at: DateTimeUtc) -> DateTimeUtc {
+    let date = at.with_timezone(&Local).date_naive();
+
+    local_midnight(date.succ_opt().unwrap())
+}
+
+/// Start of local day, which is later than midnight when DST transition skips it
+fn local_midnight(date: NaiveDate) -> DateTimeUtc {
+    first_valid_instant(date.and_hms_opt(0, 0, 0).unwrap(), |datetime| {
+        Local
+            .from_local_datetime(datetime)
+            .earliest()
+            .map(|datetime| datetime.with_timezone(&Utc))
+    })
+}
+
+/// `at` or the first valid local time after it, when `resolve` finds no instant for skipped time
+fn first_valid_instant(
+    at: NaiveDateTime,
+    resolve: impl Fn(&NaiveDateTime) -> Option<DateTimeUtc>,
+) -> DateTimeUtc {
+    (0..=24 * 60)
+        .find_map(|minutes| resolve(&(at + Duration::minutes(minutes))))
+        .expect("local time is not skipped for a whole day")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes: i64) -> DateTimeUtc {
+        Utc.with_ymd_and_hms(2023, 6, 1, 10, 0, 0).unwrap() + Duration::minutes(minutes)
+    }
+
+    fn interval(start: i64, end: i64, app_title: &str, kind: event::EventKind) -> Interval {
+        Interval {
+            start: at(start),
+            end: at(end),
+            event: event::Model {
+                id: start as i32,
+                path: format!("C:\\Program Files\\{}", app_title),
+                title: String::new(),
+                timestamp: at(start),
+                app_title: app_title.to_owned(),
+                offset: 0,
+                session_id: 1,
+                kind,
+                in_schedule: true,
+                category: None,
+                project: None,
+            },
+        }
+    }
+
+    fn activity(start: i64, end: i64, app: Option<&str>, project: &str) -> activity::Model {
+        activity::Model {
+            id: start as i32,
+            client_id: format!("{}-{}", project, start),
+            source: "test".to_owned(),
+            timestamp: at(start),
+            end: at(end),
+            app: app.map(str::to_owned),
+            title: None,
+            url: None,
+            domain: None,
+            path: None,
+            project: Some(project.to_owned()),
+            metadata: None,
+        }
+    }
+
+    /// Minutes of segments with app and project of their activity
+    fn attributed(segments: &[Segment]) -> Vec<(i64, i64, String, Option<String>)> {
+        segments
+            .iter()
+            .map(|segment| {
+                (
+                    (segment.start - at(0)).num_minutes(),
+                    (segment.end - at(0)).num_minutes(),
+                    segment.event.app_title.clone(),
+                    segment
+                        .activity
+                        .and_then(|activity| activity.project.clone()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn activities_are_clipped_to_focus_intervals_of_their_app() {
+        let intervals = [
+            interval(0, 10, "code.exe", event::EventKind::Focus),
+            interval(10, 20, "firefox.exe", event::EventKind::Focus),
+            interval(20, 30, "code.exe", event::EventKind::Paused),
+            interval(30, 40, "code.exe", event::EventKind::Focus),
+        ];
+        let activities = [activity(5, 35, Some("Code.exe"), "tracker")];
+
+        assert_eq!(
+            attributed(&attribute_activities(&intervals, &activities)),
+            vec![
+                (0, 5, "code.exe".to_owned(), None),
+                (5, 10, "code.exe".to_owned(), Some("tracker".to_owned())),
+                (10, 20, "firefox.exe".to_owned(), None),
+                (30, 35, "code.exe".to_owned(), Some("tracker".to_owned())),
+                (35, 40, "code.exe".to_owned(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn latest_started_overlapping_activity_wins() {
+        let intervals = [interval(0, 30, "code.exe", event::EventKind::Focus)];
+        let activities = [
+            activity(0, 30, None, "any app"),
+            activity(10, 20, Some("code.exe"), "editor"),
+            activity(15, 25, Some("firefox.exe"), "browser"),
+        ];
+
+        assert_eq!(
+            attributed(&attribute_activities(&intervals, &activities)),
+            vec![
+                (0, 10, "code.exe".to_owned(), Some("any app".to_owned())),
+                (10, 20, "code.exe".to_owned(), Some("editor".to_owned())),
+                (20, 30, "code.exe".to_owned(), Some("any app".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn activity_outside_of_focus_is_not_counted() {
+        let intervals = [interval(0, 10, "code.exe", event::EventKind::Focus)];
+        let activities = [activity(20, 30, None, "later")];
+
+        assert_eq!(
+            attributed(&attribute_activities(&intervals, &activities)),
+            vec![(0, 10, "code.exe".to_owned(), None)]
+        );
+    }
+
+    #[test]
+    fn skipped_midnight_starts_day_at_transition() {
+        // clocks jump from 00:00 at UTC-4 to 01:00 at UTC-3, as in Santiago
+        let transition = Utc.with_ymd_and_hms(2023, 9, 3, 4, 0, 0).unwrap();
+        let offset_before = Duration::hours(-4);
+        let offset_after = Duration::hours(-3);
+
+        let resolve = |datetime: &NaiveDateTime| {
+            let before = Utc.from_utc_datetime(&(*datetime - offset_before));
+            let after = Utc.from_utc_datetime(&(*datetime - offset_after));
+
+            match (before < transition, after >= transition) {
+                (true, _) => Some(before),
+                (false, true) => Some(after),
+                (false, false) => None,
+            }
+        };
+
+        let date = NaiveDate::from_ymd_opt(2023, 9, 3).unwrap();
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+
+        assert_eq!(first_valid_instant(midnight, resolve), transition);
+
+        let previous = midnight - Duration::days(1);
+
+        assert_eq!(
+            first_valid_instant(previous, resolve),
+            Utc.with_ymd_and_hms(2023, 9, 2, 4, 0, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use sea_orm::{
+    prelude::DateTimeUtc, ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait,
+    DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+};
+
+use crate::entity::*;
+use crate::server::ScheduleFilter;
+use crate::statistics::{self, ManualInterval};
+
+/// App title (description of manual entry), category and whether time was within tracking
+/// schedule, which manual entries and retention aggregates do not have
+type UsageKey = (String, Option<String>, Option<bool>);
+
+/// Adds interval of event preceding just inserted one within its session, as it is closed now.
+/// It is called within transaction inserting the event, so refresh of the same day can not run
+/// between them
+pub async fn record_closed_interval(
+    db: &impl ConnectionTrait,
+    event: &event::Model,
+) -> Result<(), DbErr> {
+    let previous = event::Entity::find()
+        .filter(event::Column::SessionId.eq(event.session_id))
+        .filter(
+            Condition::any()
+                .add(event::Column::Timestamp.lt(event.timestamp))
+                .add(
+                    Condition::all()
+                        .add(event::Column::Timestamp.eq(event.timestamp))
+                        .add(event::Column::Id.lt(event.id)),
+                ),
+        )
+        .order_by_desc(event::Column::Timestamp)
+        .order_by_desc(event::Column::Id)
+        .one(db)
+        .await?;
+
+    let Some(previous) = previous.filter(|previous| previous.kind == event::EventKind::Focus)
+    else {
+        return Ok(());
+    };
+
+    // manual entries override automatic tracking
+    let manual: Vec<(DateTimeUtc, DateTimeUtc)> =
+        closed_manual_intervals(db, previous.timestamp, event.timestamp)
+            .await?
+            .iter()
+            .map(|interval| (interval.start, interval.end))
+            .collect();
+
+    let key = (
+        previous.app_title.clone(),
+        previous.category.clone(),
+        Some(previous.in_schedule),
+    );
+    let mut durations: HashMap<(DateTimeUtc, UsageKey), u32> = HashMap::new();
+
+    for (start, end) in statistics::subtract_ranges(previous.timestamp, event.timestamp, &manual) {
+        add_by_days(&mut durations, start, end, &key);
+    }
+
+    for ((day, key), duration) in durations {
+        add_usage(db, day, key, duration).await?;
+    }
+
+    Ok(())
+}
+
+/// Computes usage of local days overlapping given range from recorded data again, returns amount
+/// of refreshed days. Rows of day are deleted first, so the transaction holds write lock while the
+/// day is computed and intervals closed meanwhile are not lost or counted twice
+pub async fn refresh_days(
+    db: &DatabaseConnection,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<u64, DbErr> {
+    let mut day = statistics::day_start(from);
+    let mut days = 0;
+
+    while day < to {
+        let txn = db.begin().await?;
+
+        daily_app_usage::Entity::delete_many()
+            .filter(daily_app_usage::Column::Day.eq(day))
+            .exec(&txn)
+            .await?;
+
+        let durations = compute_day(&txn, day).await?;
+
+        for (key, duration) in durations {
+            add_usage(&txn, day, key, duration).await?;
+        }
+
+        txn.commit().await?;
+
+        day = statistics::next_day_start(day);
+        days += 1;
+    }
+
+    Ok(days)
+}
+
+/// Refreshes days from the first given event until the interval of the last one finishes
+pub async fn refresh_for_events(
+    db: &DatabaseConnection,
+    events: &[event::Model],
+) -> Result<(), DbErr> {
+    let Some(from) = events.iter().map(|event| event.timestamp).min() else {
+        return Ok(());
+    };
+
+    let last = events.iter().map(|event| event.timestamp).max().unwrap();
+
+    let next = event::Entity::find()
+        .filter(event::Column::Timestamp.gt(last))
+        .order_by_asc(event::Column::Timestamp)
+        .one(db)
+        .await?;
+
+    let to = next.map_or(Utc::now(), |next| next.timestamp);
+
+    refresh_days(db, from, statistics::next_day_start(to)).await?;
+
+    Ok(())
+}
+
+/// Computes whole daily usage table again, returns amount of days
+pub async fn rebuild(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    daily_app_usage::Entity::delete_many().exec(db).await?;
+
+    let first_event = event::Entity::find()
+        .order_by_asc(event::Column::Timestamp)
+        .one(db)
+        .await?
+        .map(|event| event.timestamp);
+
+    let first_aggregate = usage_aggregate::Entity::find()
+        .order_by_asc(usage_aggregate::Column::Start)
+        .one(db)
+        .await?
+        .map(|aggregate| aggregate.start);
+
+    let first_entry = manual_entry::Entity::find()
+        .order_by_asc(manual_entry::Column::Start)
+        .one(db)
+        .await?
+        .map(|entry| entry.start);
+
+    let Some(from) = [first_event, first_aggregate, first_entry]
+        .into_iter()
+        .flatten()
+        .min()
+    else {
+        return Ok(0);
+    };
+
+    refresh_days(db, from, Utc::now()).await
+}
+
+/// Table made by newer version is filled from already recorded data
+pub async fn rebuild_if_empty(db: &DatabaseConnection) -> Result<(), DbErr> {
+    if daily_app_usage::Entity::find().count(db).await? > 0 {
+        return Ok(());
+    }
+
+    let days = rebuild(db).await?;
+
+    if days > 0 {
+        info!("Daily usage computed for {} days", days);
+    }
+
+    Ok(())
+}
+
+/// Whole local days within range which have all of their time in daily usage table. Open
+/// interval and running timer are not there yet
+pub async fn materialized_days(
+    db: &DatabaseConnection,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<Option<(DateTimeUtc, DateTimeUtc)>, DbErr> {
+    let last_event = event::Entity::find()
+        .order_by_desc(event::Column::Timestamp)
+        .one(db)
+        .await?
+        .map(|event| event.timestamp);
+
+    let running_timer = manual_entry::Entity::find()
+        .filter(manual_entry::Column::End.is_null())
+        .order_by_asc(manual_entry::Column::Start)
+        .one(db)
+        .await?
+        .map(|entry| entry.start);
+
+    let until = [Some(to), last_event, running_timer]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap();
+
+    let start = match statistics::day_start(from) == from {
+        true => from,
+        false => statistics::next_day_start(from),
+    };
+    let end = statistics::day_start(until);
+
+    Ok((start < end).then_some((start, end)))
+}
+
+/// Usage of days started within range, grouped by app title or category. Filtered by schedule
+/// only tracked time is counted, as manual entries and retention aggregates have no schedule
+pub async fn load_groups(
+    db: &DatabaseConnection,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+    by_category: bool,
+    schedule: Option<ScheduleFilter>,
+) -> Result<HashMap<String, u64>, DbErr> {
+    let mut query = daily_app_usage::Entity::find()
+        .filter(daily_app_usage::Column::Day.gte(from))
+        .filter(daily_app_usage::Column::Day.lt(to));
+
+    if let Some(schedule) = schedule {
+        query =
+            query.filter(daily_app_usage::Column::InSchedule.eq(schedule == ScheduleFilter::In));
+    }
+
+    let rows = query.all(db).await?;
+
+    let mut durations = HashMap::new();
+
+    for row in rows {
+        let key = match by_category {
+            true => row.category.unwrap_or_default(),
+            false => row.app_title,
+        };
+
+        *durations.entry(key).or_insert(0) += row.duration as u64;
+    }
+
+    Ok(durations)
+}
+
+/// Usage of local day from closed intervals, finished manual entries and retention aggregates
+async fn compute_day(
+    db: &impl ConnectionTrait,
+    day: DateTimeUtc,
+) -> Result<HashMap<UsageKey, u32>, DbErr> {
+    let end = statistics::next_day_start(day);
+
+    let intervals = statistics::load_closed_intervals(db, day, end).await?;
+    let manual = closed_manual_intervals(db, day, end).await?;
+    let aggregates = statistics::load_aggregates(db, day, end).await?;
+
+    let segments = statistics::attribute_activities(&intervals, &[]);
+    let segments = statistics::exclude_manual(segments, &manual);
+
+    let mut durations: HashMap<UsageKey, u32> = HashMap::new();
+
+    for segment in segments {
+        let key = (
+            segment.event.app_title.clone(),
+            segment.event.category.clone(),
+            Some(segment.event.in_schedule),
+        );
+        *durations.entry(key).or_insert(0) += segment.duration().num_milliseconds() as u32;
+    }
+
+    for interval in manual {
+        let key = (interval.entry.description, interval.entry.category, None);
+        *durations.entry(key).or_insert(0) +=
+            (interval.end - interval.start).num_milliseconds() as u32;
+    }
+
+    for aggregate in aggregates {
+        let key = (aggregate.app_title, aggregate.category, None);
+        *durations.entry(key).or_insert(0) += aggregate.duration;
+    }
+
+    Ok(durations)
+}
+
+async fn closed_manual_intervals(
+    db: &impl ConnectionTrait,
+    from: DateTimeUtc,
+    to: DateTimeUtc,
+) -> Result<Vec<ManualInterval>, DbErr> {
+    let manual = statistics::load_manual_intervals(db, from, to).await?;
+
+    Ok(manual
+        .into_iter()
+        .filter(|interval| interval.entry.end.is_some())
+        .collect())
+}
+
+fn add_by_days(
+    durations: &mut HashMap<(DateTimeUtc, UsageKey), u32>,
+    mut start: DateTimeUtc,
+    end: DateTimeUtc,
+    key: &UsageKey,
+) {
+    while start < end {
+        let day = statistics::day_start(start);
+        let day_end = statistics::next_day_start(start).min(end);
+
+        *durations.entry((day, key.clone())).or_insert(0) +=
+            (day_end - start).num_milliseconds() as u32;
+
+        start = day_end;
+    }
+}
+
+async fn add_usage(
+    conn: &impl ConnectionTrait,
+    day: DateTimeUtc,
+    key: UsageKey,
+    duration: u32,
+) -> Result<(), DbErr> {
+    let (app_title, category, in_schedule) = key;
+
+    let category_filter = match &category {
+        Some(category) => daily_app_usage::Column::Category.eq(category.clone()),
+        None => daily_app_usage::Column::Category.is_null(),
+    };
+
+    let schedule_filter = match in_schedule {
+        Some(in_schedule) => daily_app_usage::Column::InSchedule.eq(in_schedule),
+        None => daily_app_usage::Column::InSchedule.is_null(),
+    };
+
+    let existing = daily_app_usage::Entity::find()
+        .filter(daily_app_usage::Column::Day.eq(day))
+        .filter(daily_app_usage::Column::AppTitle.eq(app_title.clone()))
+        .filter(category_filter)
+        .filter(schedule_filter)
+        .one(conn)
+        .await?;
+
+    match existing {
+        Some(existing) => {
+            let total = existing.duration + duration;
+            let mut record: daily_app_usage::ActiveModel = existing.into();
+            record.duration = Set(total);
+            record.update(conn).await?;
+        }
+        None => {
+            let record = daily_app_usage::ActiveModel {
+                day: Set(day),
+                app_title: Set(app_title),
+                category: Set(category),
+                in_schedule: Set(in_schedule),
+                duration: Set(duration),
+                ..Default::default()
+            };
+            record.insert(conn).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+    use sea_orm::Database;
+
+    use crate::database;
+    use crate::edits::{self, Reassignment};
+    use crate::server::statistics::{compute_durations, range_durations, GroupBy};
+
+    const SCHEDULES: [Option<ScheduleFilter>; 3] =
+        [None, Some(ScheduleFilter::In), Some(ScheduleFilter::Out)];
+
+    async fn setup_db() -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+        db
+    }
+
+    async fn insert_session(db: &DatabaseConnection, at: DateTimeUtc) -> i32 {
+        let record = session::ActiveModel {
+            datetime: Set(at),
+            ..Default::default()
+        };
+
+        record.insert(db).await.unwrap().id
+    }
+
+    async fn insert_event(
+        db: &DatabaseConnection,
+        session_id: i32,
+        at: DateTimeUtc,
+        index: usize,
+    ) -> event::Model {
+        let apps = ["code.exe", "firefox.exe", "slack.exe"];
+
+        let record = event::ActiveModel {
+            path: Set(format!("C:\\{}", apps[index % 3])),
+            title: Set(format!("window {}", index)),
+            timestamp: Set(at),
+            app_title: Set(apps[index % 3].to_owned()),
+            offset: Set(0),
+            session_id: Set(session_id),
+            kind: Set(match index % 7 {
+                0 => event::EventKind::Paused,
+                _ => event::EventKind::Focus,
+            }),
+            in_schedule: Set(!index.is_multiple_of(5)),
+            category: Set(index.is_multiple_of(2).then(|| "work".to_owned())),
+            project: Set(None),
+            ..Default::default()
+        };
+
+        record.insert(db).await.unwrap()
+    }
+
+    /// Events every 97 minutes through three days, so intervals cross midnight
+    fn event_times(start: DateTimeUtc) -> Vec<DateTimeUtc> {
+        (0..45)
+            .map(|index| start + Duration::minutes(97 * index))
+            .collect()
+    }
+
+    async fn insert_manual_entry(db: &DatabaseConnection, start: DateTimeUtc) {
+        let record = manual_entry::ActiveModel {
+            start: Set(start),
+            end: Set(Some(start + Duration::minutes(150))),
+            description: Set("meeting".to_owned()),
+            project: Set(None),
+            category: Set(Some("work".to_owned())),
+            ..Default::default()
+        };
+
+        record.insert(db).await.unwrap();
+    }
+
+    fn non_zero(durations: HashMap<String, u64>) -> HashMap<String, u64> {
+        durations
+            .into_iter()
+            .filter(|(_, duration)| *duration > 0)
+            .collect()
+    }
+
+    async fn assert_days_match(db: &DatabaseConnection, from: DateTimeUtc, to: DateTimeUtc) {
+        let mut day = statistics::day_start(from);
+
+        while day < to {
+            let end = statistics::next_day_start(day);
+
+            for (group_by, by_category) in [(GroupBy::App, false), (GroupBy::Category, true)] {
+                for schedule in SCHEDULES {
+                    let stored = load_groups(db, day, end, by_category, schedule)
+                        .await
+                        .unwrap();
+                    let computed = compute_durations(db, day, end, group_by, schedule)
+                        .await
+                        .unwrap();
+
+                    assert_eq!(
+                        non_zero(stored),
+                        non_zero(computed),
+                        "day {}, schedule {:?}",
+                        day,
+                        schedule
+                    );
+                }
+            }
+
+            day = end;
+        }
+    }
+
+    #[tokio::test]
+    async fn incremental_updates_match_recomputation() {
+        let db = setup_db().await;
+        let start = statistics::day_start(Utc::now()) - Duration::days(4) + Duration::hours(7);
+
+        let session_id = insert_session(&db, start).await;
+
+        // entry made before intervals it overlaps are closed
+        insert_manual_entry(&db, start + Duration::hours(20)).await;
+        refresh_days(&db, start, start + Duration::days(1))
+            .await
+            .unwrap();
+
+        for (index, at) in event_times(start).into_iter().enumerate() {
+            let event = insert_event(&db, session_id, at, index).await;
+            record_closed_interval(&db, &event).await.unwrap();
+        }
+
+        // the last interval of finished session has unknown end
+        insert_session(&db, Utc::now()).await;
+
+        assert_days_match(&db, start, statistics::day_start(Utc::now())).await;
+    }
+
+    #[tokio::test]
+    async fn rebuild_matches_recomputation() {
+        let db = setup_db().await;
+        let start = statistics::day_start(Utc::now()) - Duration::days(4) + Duration::hours(7);
+
+        let session_id = insert_session(&db, start).await;
+
+        for (index, at) in event_times(start).into_iter().enumerate() {
+            insert_event(&db, session_id, at, index).await;
+        }
+
+        insert_manual_entry(&db, start + Duration::hours(30)).await;
+        insert_session(&db, Utc::now()).await;
+
+        rebuild(&db).await.unwrap();
+
+        assert_days_match(&db, start, statistics::day_start(Utc::now())).await;
+    }
+
+    #[tokio::test]
+    async fn range_statistics_use_table_and_stay_exact() {
+        let db = setup_db().await;
+        let start = statistics::day_start(Utc::now()) - Duration::days(4) + Duration::hours(7);
+
+        let session_id = insert_session(&db, start).await;
+
+        for (index, at) in event_times(start).into_iter().enumerate() {
+            insert_event(&db, session_id, at, index).await;
+        }
+
+        insert_manual_entry(&db, start + Duration::hours(30)).await;
+        insert_session(&db, Utc::now()).await;
+        rebuild(&db).await.unwrap();
+
+        // range starts and ends within days, so only whole days in between are read from table
+        let from = start + Duration::hours(3);
+        let to = Utc::now();
+        assert!(materialized_days(&db, from, to).await.unwrap().is_some());
+
+        // correction of recorded time refreshes touched days
+        edits::reassign(
+            &db,
+            5,
+            Reassignment {
+                app_title: Some("renamed.exe".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        for group_by in [GroupBy::App, GroupBy::Category] {
+            for schedule in SCHEDULES {
+                let stored = range_durations(&db, from, to, group_by, schedule)
+                    .await
+                    .unwrap();
+                let computed = compute_durations(&db, from, to, group_by, schedule)
+                    .await
+                    .unwrap();
+
+                assert_eq!(non_zero(stored), non_zero(computed), "{:?}", schedule);
+            }
+        }
+
+        // time within schedule and outside of it is split, manual entry is counted only
+        // without filter
+        let total = |durations: HashMap<String, u64>| durations.values().sum::<u64>();
+        let all = range_durations(&db, from, to, GroupBy::App, None)
+            .await
+            .unwrap();
+        let within = range_durations(&db, from, to, GroupBy::App, Some(ScheduleFilter::In))
+            .await
+            .unwrap();
+        let outside = range_durations(&db, from, to, GroupBy::App, Some(ScheduleFilter::Out))
+            .await
+            .unwrap();
+
+        assert!(total(outside.clone()) > 0);
+        assert!(!within.contains_key("meeting") && !outside.contains_key("meeting"));
+        assert_eq!(
+            total(within) + total(outside) + all["meeting"],
+            total(all.clone())
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
+use sha2::Sha256;
+
+use crate::config::WebhookConfig;
+use crate::entity::*;
+
+static SIGNATURE_HEADER: &str = "X-Signature";
+static EVENT_HEADER: &str = "X-Event-Type";
+static INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Sends notifications to configured webhooks, logging every attempt into `webhook_delivery` table
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    webhooks: Vec<WebhookConfig>,
+    client: reqwest::Client,
+    db: DatabaseConnection,
+    /// Delay before second attempt, doubled for every next one
+    initial_backoff: Duration,
+}
+
+impl WebhookDispatcher {
+    pub fn new(webhooks: &[WebhookConfig], db: DatabaseConnection) -> Self {
+        Self {
+            webhooks: webhooks.to_vec(),
+            client: reqwest::Client::new(),
+            db,
+            initial_backoff: INITIAL_BACKOFF,
+        }
+    }
+
+    /// Starts delivery to every webhook subscribed to `event_type`, does not wait for it
+    pub fn dispatch(&self, event_type: &str, payload: String) {
+        for webhook in self.subscribed(event_type) {
+            let dispatcher = self.clone();
+            let event_type = event_type.to_owned();
+            let payload = payload.clone();
+
+            tokio::spawn(async move {
+                dispatcher.deliver(&webhook, &event_type, &payload).await;
+            });
+        }
+    }
+
+    fn subscribed(&self, event_type: &str) -> Vec<WebhookConfig> {
+        self.webhooks
+            .iter()
+            .filter(|webhook| {
+                webhook.events.is_empty() || webhook.events.iter().any(|event| event == event_type)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns whether notification was delivered within allowed attempts
+    async fn deliver(&self, webhook: &WebhookConfig, event_type: &str, payload: &str) -> bool {
+        let mut backoff = self.initial_backoff;
+
+        for attempt in 1..=webhook.max_attempts {
+            let (status_code, error) = self.send(webhook, event_type, payload).await;
+            let delivered = error.is_none();
+
+            let record = webhook_delivery::ActiveModel {
+                url: Set(webhook.url.clone()),
+                event_type: Set(event_type.to_owned()),
+                payload: Set(payload.to_owned()),
+                attempt: Set(attempt),
+                status_code: Set(status_code),
+                error: Set(error.clone()),
+                delivered: Set(delivered),
+                timestamp: Set(Utc::now()),
+                ..Default::default()
+            };
+
+            if let Err(err) = record.insert(&self.db).await {
+                error!("Error on inserting webhook delivery into database: {}", err);
+            }
+
+            if delivered {
+                return true;
+            }
+
+            warn!(
+                "Webhook {} attempt {} failed: {}",
+                webhook.url,
+                attempt,
+                error.unwrap_or_default()
+            );
+
+            if attempt < webhook.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        error!(
+            "Giving up on delivering {} to webhook {}",
+            event_type, webhook.url
+        );
+
+        false
+    }
+
+    async fn send(
+        &self,
+        webhook: &WebhookConfig,
+        event_type: &str,
+        payload: &str,
+    ) -> (Option<u16>, Option<String>) {
+        let mut request = self
+            .client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event_type)
+            .body(payload.to_owned());
+
+        if let Some(secret) = &webhook.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, payload));
+        }
+
+        match request.send().await {
+            Ok(response) => {
+                let status = response.status();
+
+                match status.is_success() {
+                    true => (Some(status.as_u16()), None),
+                    false => (
+                        Some(status.as_u16()),
+                        Some(format!("Unexpected status {}", status)),
+                    ),
+                }
+            }
+            Err(err) => (None, Some(err.to_string())),
+        }
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of payload, prefixed with algorithm name
+fn sign(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload.as_bytes());
+
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sea_orm::{Database, EntityTrait};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use crate::database;
+
+    /// Minimal HTTP server answering with given statuses in order, passes received requests to channel
+    async fn stand_in(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buffer = [0u8; 1024];
+
+                // read headers and body of known length
+                loop {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some(headers_end) = text.find("\r\n\r\n") else {
+                        continue;
+                    };
+
+                    let content_length = text[..headers_end]
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length: ")
+                                .map(|length| length.parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+
+                    if request.len() >= headers_end + 4 + content_length {
+                        break;
+                    }
+                }
+
+                tx.send(String::from_utf8(request).unwrap()).unwrap();
+
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    async fn setup_db() -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        database::create_db_tables(&db).await;
+        db
+    }
+
+    fn webhook(url: String, events: Vec<&str>) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            secret: Some("secret".to_owned()),
+            events: events.into_iter().map(String::from).collect(),
+            max_attempts: 3,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_delivered_and_logs_attempts() {
+        let db = setup_db().await;
+        let (url, mut requests) = stand_in(vec![500, 200]).await;
+
+        let dispatcher = WebhookDispatcher {
+            initial_backoff: Duration::from_millis(10),
+            ..WebhookDispatcher::new(&[], db.clone())
+        };
+
+        let payload = r#"{"type":"focus"}"#;
+        let delivered = dispatcher
+            .deliver(&webhook(url, vec![]), "focus", payload)
+            .await;
+
+        assert!(delivered);
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /hook"));
+        assert!(request.to_lowercase().contains("x-event-type: focus"));
+        assert!(request
+            .to_lowercase()
+            .contains(&format!("x-signature: {}", sign("secret", payload))));
+        assert!(request.ends_with(payload));
+
+        let deliveries = webhook_delivery::Entity::find().all(&db).await.unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].status_code, Some(500));
+        assert!(!deliveries[0].delivered);
+        assert_eq!(deliveries[1].attempt, 2);
+        assert!(deliveries[1].delivered);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let db = setup_db().await;
+        let (url, _requests) = stand_in(vec![503, 503, 503]).await;
+
+        let dispatcher = WebhookDispatcher {
+            initial_backoff: Duration::from_millis(10),
+            ..WebhookDispatcher::new(&[], db.clone())
+        };
+
+        let delivered = dispatcher
+            .deliver(&webhook(url, vec![]), "focus", "{}")
+            .await;
+
+        assert!(!delivered);
+
+        let deliveries = webhook_delivery::Entity::find().all(&db).await.unwrap();
+        assert_eq!(deliveries.len(), 3);
+        assert!(deliveries.iter().all(|delivery| !delivery.delivered));
+    }
+
+    #[tokio::test]
+    async fn filters_subscriptions_by_event_type() {
+        let db = setup_db().await;
+
+        let dispatcher = WebhookDispatcher::new(
+            &[
+                webhook("http://all".to_owned(), vec![]),
+                webhook("http://goals".to_owned(), vec!["goal_threshold"]),
+            ],
+            db,
+        );
+
+        let urls = |event_type| {
+            dispatcher
+                .subscribed(event_type)
+                .into_iter()
+                .map(|webhook| webhook.url)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(urls("focus"), vec!["http://all"]);
+        assert_eq!(urls("goal_threshold"), vec!["http://all", "http://goals"]);
+    }
+}